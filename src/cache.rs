@@ -0,0 +1,226 @@
+//! # Proposal Cache
+//!
+//! This module stores the outcome of a `propose` run in a file next to the context
+//! (e.g. `context.cache.json` for `context.json`): the best proposals it kept and the
+//! run's totals, never every scored permutation, so the file stays as small as the
+//! output. Entries are keyed by a hash of the tracklist, medium, constraints and
+//! ranking options, so re-running `propose` with the same inputs and a `--count` no
+//! larger than the stored one reads the proposals back instead of re-scoring every
+//! permutation. Changing any input changes the key.
+//!
+//! The key is a 64-bit FNV-1a hash of the serialized inputs, an algorithm fixed by its
+//! specification, so entries stay valid across Rust releases.
+//!
+//! ## Example
+//! ```rust
+//! let key = cache_key(&(&ser_tl.tracks, ser_medium, &ctx.constraints));
+//! let mut cache = ProposalCache::load(&cache_path);
+//! if let Some(entry) = cache.get(&key).filter(|entry| entry.covers(count)) {
+//!     // Use entry.top instead of scoring
+//! }
+//! cache.save(&cache_path);
+//! ```
+
+use crate::scoring::Score;
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Maximum number of proposal runs kept in the cache file, most recent first.
+pub const MAX_CACHE_ENTRIES: usize = 8;
+
+/// Version of the scoring, hashed into every key. Bumped when the same inputs
+/// score differently (e.g. graded constraints awarding fractional points), so
+/// entries scored the old way are missed instead of reused.
+const SCORING_VERSION: u32 = 2;
+
+/// The best proposals and totals of one proposal run.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CacheEntry {
+    /// Hash of the proposal inputs.
+    pub key: String,
+    /// Number of permutations that were evaluated.
+    pub evaluated: usize,
+    /// Number of evaluated permutations that fit the medium.
+    pub fitting: usize,
+    /// Number of fitting permutations at or above the minimum score.
+    pub kept: usize,
+    /// Best score among the fitting permutations.
+    pub best: Option<Score>,
+    /// Number of fitting permutations scoring above the stored order.
+    pub above_original: usize,
+    /// Score and track order (indices into the stored tracklist) of the best kept
+    /// permutations, best first: as many as the run that stored them proposed.
+    pub top: Vec<(Score, Vec<usize>)>,
+}
+
+impl CacheEntry {
+    /// Returns whether the entry holds the best `count` proposals: it stored at least
+    /// that many, or every kept permutation.
+    pub fn covers(&self, count: usize) -> bool {
+        self.top.len() >= count.min(self.kept)
+    }
+}
+
+/// The cache file content.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct ProposalCache {
+    pub entries: Vec<CacheEntry>,
+}
+
+/// Returns the cache file path for a context file path.
+pub fn cache_path_for(context_path: &Path) -> PathBuf {
+    context_path.with_extension("cache.json")
+}
+
+/// FNV-1a offset basis and prime for 64-bit hashes.
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// Hashes the serialized proposal inputs into a cache key.
+pub fn cache_key<T: Serialize>(inputs: &T) -> String {
+    let json = serde_json::to_string(inputs).unwrap_or_default();
+    let hash = fnv1a(
+        fnv1a(FNV_OFFSET_BASIS, &SCORING_VERSION.to_le_bytes()),
+        json.as_bytes(),
+    );
+    format!("{:016x}", hash)
+}
+
+/// Continues a 64-bit FNV-1a hash with the given bytes.
+fn fnv1a(hash: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(hash, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(FNV_PRIME)
+    })
+}
+
+impl ProposalCache {
+    /// Loads the cache from the given path.
+    /// A missing or unreadable cache is treated as empty.
+    pub fn load(path: &Path) -> Self {
+        let Ok(data) = fs::read_to_string(path) else {
+            debug!("No proposal cache at {:?}", path);
+            return Self::default();
+        };
+
+        serde_json::from_str(&data).unwrap_or_else(|e| {
+            debug!("Ignoring invalid proposal cache {:?}: {}", path, e);
+            Self::default()
+        })
+    }
+
+    /// Saves the cache to the given path.
+    /// Failures are logged but never fail the command.
+    pub fn save(&self, path: &Path) {
+        let result = serde_json::to_string(self)
+            .map_err(|e| e.to_string())
+            .and_then(|json| fs::write(path, json).map_err(|e| e.to_string()));
+
+        if let Err(e) = result {
+            warn!("Failed to write proposal cache {:?}: {}", path, e);
+        }
+    }
+
+    /// Returns the entry with the given key, if any.
+    pub fn get(&self, key: &str) -> Option<&CacheEntry> {
+        self.entries.iter().find(|e| e.key == key)
+    }
+
+    /// Inserts an entry as the most recent one, evicting the oldest beyond [`MAX_CACHE_ENTRIES`].
+    pub fn insert(&mut self, entry: CacheEntry) {
+        self.entries.retain(|e| e.key != entry.key);
+        self.entries.insert(0, entry);
+        self.entries.truncate(MAX_CACHE_ENTRIES);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(key: &str) -> CacheEntry {
+        CacheEntry {
+            key: key.to_string(),
+            evaluated: 2,
+            fitting: 2,
+            kept: 2,
+            best: Some(1.0),
+            above_original: 0,
+            top: vec![(1.0, vec![0, 1]), (0.0, vec![1, 0])],
+        }
+    }
+
+    #[test]
+    fn fnv1a_matches_reference_vectors() {
+        assert_eq!(fnv1a(FNV_OFFSET_BASIS, b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(fnv1a(FNV_OFFSET_BASIS, b"a"), 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(fnv1a(FNV_OFFSET_BASIS, b"foobar"), 0x8594_4171_f739_67e8);
+    }
+
+    #[test]
+    fn cache_key_depends_only_on_inputs() {
+        assert_eq!(cache_key(&("a", 1)), cache_key(&("a", 1)));
+        assert_ne!(cache_key(&("a", 1)), cache_key(&("a", 2)));
+        assert_eq!(cache_key(&"a").len(), 16);
+    }
+
+    #[test]
+    fn insert_replaces_same_key_and_evicts_oldest() {
+        let mut cache = ProposalCache::default();
+        for i in 0..MAX_CACHE_ENTRIES + 2 {
+            cache.insert(entry(&i.to_string()));
+        }
+        cache.insert(entry("3"));
+
+        assert_eq!(cache.entries.len(), MAX_CACHE_ENTRIES);
+        assert_eq!(cache.entries[0].key, "3");
+        assert_eq!(cache.entries.iter().filter(|e| e.key == "3").count(), 1);
+        assert!(cache.get("0").is_none());
+        assert!(cache.get(&(MAX_CACHE_ENTRIES + 1).to_string()).is_some());
+    }
+
+    #[test]
+    fn save_and_load_round_trip() {
+        let path = std::env::temp_dir().join(format!(
+            "albumseq_cli_cache_round_trip_{}.cache.json",
+            std::process::id()
+        ));
+        let mut cache = ProposalCache::default();
+        cache.insert(entry("k"));
+        cache.save(&path);
+
+        let loaded = ProposalCache::load(&path);
+        let _ = fs::remove_file(&path);
+        let loaded = loaded.get("k").expect("entry saved");
+        assert_eq!(loaded.evaluated, 2);
+        assert_eq!(loaded.top, vec![(1.0, vec![0, 1]), (0.0, vec![1, 0])]);
+    }
+
+    #[test]
+    fn entry_covers_counts_up_to_what_it_stored() {
+        let mut stored = entry("k");
+        stored.kept = 10;
+        assert!(stored.covers(1));
+        assert!(stored.covers(2));
+        assert!(!stored.covers(3));
+
+        // Every kept permutation is stored, so any count is covered
+        stored.kept = 2;
+        assert!(stored.covers(50));
+    }
+
+    #[test]
+    fn missing_or_invalid_cache_is_empty() {
+        let path = std::env::temp_dir().join(format!(
+            "albumseq_cli_cache_invalid_{}.cache.json",
+            std::process::id()
+        ));
+        assert!(ProposalCache::load(&path).entries.is_empty());
+
+        fs::write(&path, "not json").unwrap();
+        let loaded = ProposalCache::load(&path);
+        let _ = fs::remove_file(&path);
+        assert!(loaded.entries.is_empty());
+    }
+}
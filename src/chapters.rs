@@ -0,0 +1,178 @@
+//! # Chapter Lists
+//!
+//! This module reads timestamped chapter lists, as found in YouTube descriptions or
+//! Bandcamp track listings, into tracks. Each line holds a start time (`M:SS` or
+//! `H:MM:SS`) followed by the title, e.g. `3:45 Song One` or `1:02:10 - Finale`.
+//! A track lasts until the next chapter starts; the last one lasts until the given
+//! total runtime. Lines without a leading timestamp are ignored, as are comment lines,
+//! starting with a comment character such as `#`, even when a timestamp follows it.
+//!
+//! ## Example
+//! ```rust
+//! let chapters = parse_chapters(&fs::read_to_string("chapters.txt")?, 47.5, '#')?;
+//! handle_add_tracklist(&mut ctx, &name, chapters.tracks)?;
+//! ```
+
+use albumseq::{Duration, Track};
+
+/// The tracks read from a chapter list, and the lines that couldn't be used.
+#[derive(Default)]
+pub struct ChapterList {
+    pub tracks: Vec<Track>,
+    /// Lines skipped, with the reason.
+    pub skipped: Vec<(String, &'static str)>,
+}
+
+/// Parses the contents of a chapter list, given the total runtime (in minutes) of
+/// the recording it describes. Lines whose first non-whitespace character is
+/// `comment` are skipped silently.
+///
+/// Returns an error if no chapter is found or if the last chapter doesn't start
+/// before `total`.
+pub fn parse_chapters(
+    content: &str,
+    total: Duration,
+    comment: char,
+) -> Result<ChapterList, String> {
+    let mut list = ChapterList::default();
+    let mut starts: Vec<(Duration, String)> = Vec::new();
+
+    for line in content.lines() {
+        let line = line.trim_start_matches('\u{feff}').trim();
+        if line.starts_with(comment) {
+            continue;
+        }
+        let Some((start, title)) = split_chapter_line(line) else {
+            continue;
+        };
+        if title.is_empty() {
+            list.skipped.push((line.to_string(), "no title"));
+            continue;
+        }
+        if starts
+            .last()
+            .is_some_and(|(previous, _)| start <= *previous)
+        {
+            list.skipped
+                .push((line.to_string(), "doesn't start after the previous chapter"));
+            continue;
+        }
+        starts.push((start, title.to_string()));
+    }
+
+    let Some((last_start, last_title)) = starts.last() else {
+        return Err("No chapters found (expected lines like \"3:45 Song One\")".to_string());
+    };
+    if total <= *last_start {
+        return Err(format!(
+            "The total runtime must be after the start of the last chapter, '{}'",
+            last_title
+        ));
+    }
+
+    let ends = starts
+        .iter()
+        .skip(1)
+        .map(|(start, _)| *start)
+        .chain(std::iter::once(total));
+    list.tracks = starts
+        .iter()
+        .zip(ends)
+        .map(|((start, title), end)| Track {
+            title: title.clone(),
+            duration: end - start,
+        })
+        .collect();
+
+    Ok(list)
+}
+
+/// Parses a chapter timestamp, `M:SS` or `H:MM:SS`, into minutes.
+pub fn parse_timestamp(s: &str) -> Option<Duration> {
+    let parts: Vec<u32> = s
+        .split(':')
+        .map(|part| part.parse::<u32>().ok())
+        .collect::<Option<_>>()?;
+    let (hours, minutes, seconds) = match parts[..] {
+        [minutes, seconds] => (0, minutes, seconds),
+        [hours, minutes, seconds] if minutes < 60 => (hours, minutes, seconds),
+        _ => return None,
+    };
+    if seconds >= 60 {
+        return None;
+    }
+    Some(hours as Duration * 60.0 + minutes as Duration + seconds as Duration / 60.0)
+}
+
+/// Splits a line into its start time and title, if it starts with a timestamp
+/// (optionally in brackets). A separator such as `-` or `|` after the timestamp
+/// is dropped.
+fn split_chapter_line(line: &str) -> Option<(Duration, &str)> {
+    let (timestamp, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+    let timestamp = timestamp.trim_matches(|c| matches!(c, '(' | ')' | '[' | ']'));
+    let start = parse_timestamp(timestamp)?;
+    let title = rest
+        .trim_start()
+        .trim_start_matches(['-', '–', '—', '|', ':'])
+        .trim();
+    Some((start, title))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::utils::format_duration;
+
+    #[test]
+    fn chapters_last_until_the_next_one_starts() {
+        let content = "Tracklist:\n\
+                       0:00 Intro\n\
+                       (3:45) - Song One\n\
+                       [1:02:10] | Finale\n\
+                       1:01:00 Too Early\n\
+                       1:03:00\n";
+        let list = parse_chapters(content, 65.0, '#').unwrap();
+
+        let tracks: Vec<(&str, String)> = list
+            .tracks
+            .iter()
+            .map(|t| (t.title.as_str(), format_duration(t.duration)))
+            .collect();
+        assert_eq!(
+            tracks,
+            [
+                ("Intro", "03:45".to_string()),
+                ("Song One", "58:25".to_string()),
+                ("Finale", "02:50".to_string()),
+            ]
+        );
+        let skipped: Vec<&str> = list.skipped.iter().map(|(_, reason)| *reason).collect();
+        assert_eq!(
+            skipped,
+            ["doesn't start after the previous chapter", "no title"]
+        );
+
+        assert!(parse_chapters(content, 62.0, '#').is_err());
+        assert!(parse_chapters("Just a description", 10.0, '#').is_err());
+    }
+
+    #[test]
+    fn comment_lines_are_skipped_silently() {
+        let content = "# Chapters of the live set\n\
+                       0:00 Intro\n\
+                       \t# 2:00 cut in the edit\n\
+                       (3:45) - Song One\n\
+                       # 5:00 Encore, to check\n";
+        let list = parse_chapters(content, 8.0, '#').unwrap();
+        let titles: Vec<&str> = list.tracks.iter().map(|t| t.title.as_str()).collect();
+        assert_eq!(titles, ["Intro", "Song One"]);
+        assert!(list.skipped.is_empty());
+
+        // Another comment character: the bracketed chapter is a comment now
+        let list = parse_chapters(content, 8.0, '(').unwrap();
+        let titles: Vec<&str> = list.tracks.iter().map(|t| t.title.as_str()).collect();
+        assert_eq!(titles, ["Intro"]);
+        assert_eq!(format_duration(list.tracks[0].duration), "08:00");
+    }
+}
@@ -10,11 +10,50 @@
 //! ## Supported Commands
 //! - `init`: Initialize a new context file.
 //! - `add-tracklist`: Add or replace a named tracklist.
+//! - `import-m3u`: Add a tracklist from an extended M3U playlist.
+//! - `import-chapters`: Add a tracklist from a timestamped chapter list.
 //! - `add-medium`: Add or replace a named medium.
+//! - `add-standard-media`: Add well-known media (vinyl, cassette, CD).
+//! - `copy-medium`: Copy a medium under a new name with optional overrides.
+//! - `set-notes`: Set or clear the notes of a tracklist.
+//! - `tag-track`: Add or remove tags on a track.
+//! - `set-track-info`: Set metadata (energy, ISRC, continuous, gap, artist, loudness) of a track.
+//! - `set-catalog`: Set or clear the catalog number of a tracklist.
+//! - `offset-durations`: Add a signed offset to every track duration of a tracklist.
+//! - `diff-tracklists`: Show the differences between two tracklists.
+//! - `dedup`: Remove duplicate tracks from a tracklist.
+//! - `check-durations`: Report (and optionally remove) tracks without a positive duration.
+//! - `swap-tracks`: Swap the positions of two tracks of a tracklist.
+//! - `rename-track`: Rename a track across tracklists and constraints.
+//! - `normalize-titles`: Fix the whitespace and case of a tracklist's titles.
+//! - `sort`: Sort the tracks of a tracklist by title or duration.
 //! - `add-constraint`: Add a constraint to the context.
+//! - `add-constraint-wizard`: Build a constraint interactively.
+//! - `add-preset`: Add a curated set of constraints.
 //! - `remove-constraint`: Remove a constraint by index.
+//! - `scale-weights`: Multiply constraint weights by a factor.
+//! - `toggle-constraint`: Enable or disable a constraint by index.
+//! - `export-constraints`: Write all constraints to a JSON or TOML file.
+//! - `import-constraints`: Add the constraints of an exported file.
+//! - `add-medium-constraint`: Add a constraint applied whenever a medium is used.
+//! - `remove-medium-constraint`: Remove a constraint of a medium by index.
+//! - `unconstrained`: List the tracks of a tracklist no constraint refers to.
+//! - `conflicts`: List pairs of constraints that contradict each other.
+//! - `constraint-info`: Describe a constraint and the media its side logic applies to.
+//! - `constraints-for`: List the constraints that apply to a tracklist.
 //! - `show`: Show the current context or filtered parts of it.
+//! - `show-tracklist`: Show a single tracklist, optionally split on a medium.
+//! - `explain`: Show the per-constraint score of a tracklist's stored order.
 //! - `propose`: Propose top scoring tracklist permutations for a tracklist & medium.
+//! - `last-proposals`: Print the proposals stored by `propose --remember`.
+//! - `propose-all`: Propose a tracklist on every stored medium.
+//! - `propose-set`: Propose a box set of several tracklists, one per disc.
+//! - `layout`: Show how a tracklist's stored order splits on a medium.
+//! - `heatmap`: Show how often each track lands at each position.
+//! - `sheet`: Render a printable sheet of a tracklist on a medium.
+//! - `capacity`: Show the capacity math of a medium.
+//! - `fit-medium`: List the stored media a tracklist fits on, smallest first.
+//! - `split`: Split a tracklist into several tracklists that each fit a medium.
 //!
 //! ## Example Usage
 //! ```sh
@@ -23,22 +62,43 @@
 //! albumseq_cli add-medium --name "Vinyl" --sides 2 --max-duration 22:00
 //! albumseq_cli add-constraint --kind adjacent --args "Song1" "Song2" --weight 2
 //! albumseq_cli propose --tracklist "My Album" --medium "Vinyl" --count 10 --min-score 5
+//! albumseq_cli propose --tracklist "My Album" --medium "Vinyl" --algorithm sample --samples 5000
 //! ```
 //!
 //! See each command's help (`--help`) for more details.
 
 use crate::context::DEFAULT_CONTEXT_PATH;
-use clap::{Parser, Subcommand};
+use crate::utils::{GroupBy, OutputFormat, SideLabels, SortBy, TitleNormalization, TrackOrder};
+use clap::{Parser, Subcommand, ValueEnum};
 use std::path::PathBuf;
 
 /// Root CLI options and subcommands for albumseq_cli.
 #[derive(Parser)]
 #[command(author, version, about)]
 pub struct Cli {
-    /// Path to the context file (default: context.json)
+    /// Path to the context file (default: context.json). A `.toml` path is stored as TOML.
+    /// "-" reads a JSON context from stdin and writes the updated context to stdout.
     #[arg(short, long, default_value = DEFAULT_CONTEXT_PATH)]
     pub context: PathBuf,
 
+    /// Round displayed durations down to the second instead of to the nearest second,
+    /// so totals never appear to exceed a limit.
+    #[arg(long, global = true)]
+    pub floor_durations: bool,
+
+    /// Suppress informational messages such as "Added tracklist". Command output
+    /// and errors are still printed.
+    #[arg(short, long, global = true)]
+    pub quiet: bool,
+
+    /// Save a JSON context on a single line instead of indented. Both forms load.
+    #[arg(long, global = true)]
+    pub minified_context: bool,
+
+    /// Increase log verbosity (-v for debug, -vv for trace). Logs go to stderr.
+    #[arg(short, long, action = clap::ArgAction::Count, global = true)]
+    pub verbose: u8,
+
     /// The command to execute.
     #[command(subcommand)]
     pub command: Commands,
@@ -62,9 +122,55 @@ pub enum Commands {
         #[arg(short, long)]
         name: String,
 
-        /// Tracks in format "Title:Duration" (duration supports MM:SS or decimal minutes).
-        #[arg(short, long)]
+        /// Tracks in format "Title:Duration" (duration supports MM:SS, seconds like 225s,
+        /// or decimal minutes like 3.75 or 3.75m).
+        #[arg(short, long, num_args = 1..)]
         tracks: Vec<String>,
+
+        /// Fail if any track entry is malformed, instead of skipping it with a warning.
+        #[arg(long)]
+        strict: bool,
+    },
+
+    /// Add or replace a tracklist read from an extended M3U playlist (.m3u/.m3u8), as
+    /// exported by Rekordbox or Serato. Titles and durations come from the #EXTINF
+    /// lines; entries without one are skipped.
+    ///
+    /// Example:
+    /// albumseq_cli import-m3u --name "Friday Set" --path set.m3u8
+    ImportM3u {
+        /// Name of the tracklist.
+        #[arg(short, long)]
+        name: String,
+
+        /// Playlist file to read.
+        #[arg(short, long)]
+        path: PathBuf,
+    },
+
+    /// Add or replace a tracklist read from a timestamped chapter list, one
+    /// "START Title" line per track (e.g. "3:45 Song One"), as found in YouTube
+    /// descriptions. Each track lasts until the next one starts; the last one lasts
+    /// until --total.
+    ///
+    /// Example:
+    /// albumseq_cli import-chapters --name "My Album" --path chapters.txt --total 47:30
+    ImportChapters {
+        /// Name of the tracklist.
+        #[arg(short, long)]
+        name: String,
+
+        /// Chapter list file to read.
+        #[arg(short, long)]
+        path: PathBuf,
+
+        /// Total runtime of the recording (MM:SS or H:MM:SS), the end of the last track.
+        #[arg(long)]
+        total: String,
+
+        /// Lines starting with this character are comments and skipped silently.
+        #[arg(long, default_value = "#")]
+        comment_char: char,
     },
 
     /// Add or replace a named medium.
@@ -76,13 +182,277 @@ pub enum Commands {
         #[arg(short, long)]
         name: String,
 
-        /// Number of sides (integer).
+        /// Number of sides (integer). Omit to infer it with --total-duration or
+        /// --for-tracklist.
+        #[arg(short = 's', long)]
+        sides: Option<usize>,
+
+        /// Max duration per side (MM:SS or decimal minutes). Can be omitted with --rpm.
+        #[arg(short = 'd', long)]
+        max_duration: Option<String>,
+
+        /// Total capacity (MM:SS); the medium gets as many sides of --max-duration
+        /// as needed to hold it.
+        #[arg(long)]
+        total_duration: Option<String>,
+
+        /// Tracklist the medium must hold; the medium gets as many sides of
+        /// --max-duration as its stored order needs.
+        #[arg(long)]
+        for_tracklist: Option<String>,
+
+        /// Playback speed of a vinyl medium (e.g. 45). Without --max-duration, the side
+        /// length is that of a 12" record, 22:00 at 33 1/3 RPM, scaled to this speed
+        /// (by 33 1/3 / rpm).
+        #[arg(long)]
+        rpm: Option<f64>,
+    },
+
+    /// Add well-known media: "7in-45" (2 x 5:00), "10in-33" (2 x 14:00),
+    /// "12in-33" (2 x 22:00), "12in-45" (2 x 15:00), "C60" (2 x 30:00),
+    /// "C90" (2 x 45:00) and "CD" (1 x 80:00).
+    ///
+    /// Example:
+    /// albumseq_cli add-standard-media
+    AddStandardMedia {
+        /// Replace media with the same names instead of skipping them.
+        #[arg(long)]
+        force: bool,
+    },
+
+    /// Copy a medium under a new name, optionally overriding its sides or max duration.
+    ///
+    /// Example:
+    /// albumseq_cli copy-medium --source "Vinyl-33rpm" --new-name "Vinyl-45rpm" --max-duration 15:00
+    CopyMedium {
+        /// Name of the medium to copy.
+        #[arg(long)]
+        source: String,
+
+        /// Name of the new medium.
+        #[arg(short, long)]
+        new_name: String,
+
+        /// Number of sides (default: the source's).
         #[arg(short = 's', long)]
-        sides: usize,
+        sides: Option<usize>,
 
-        /// Max duration per side (MM:SS or decimal minutes).
+        /// Max duration per side, MM:SS or decimal minutes (default: the source's).
         #[arg(short = 'd', long)]
-        max_duration: String,
+        max_duration: Option<String>,
+    },
+
+    /// Set or clear the notes of a tracklist.
+    ///
+    /// Example:
+    /// albumseq_cli set-notes --tracklist "My Album" --notes "needs remastering"
+    SetNotes {
+        /// Tracklist to annotate.
+        #[arg(short = 'l', long)]
+        tracklist: String,
+
+        /// Notes text. Omit to clear the notes.
+        #[arg(short, long)]
+        notes: Option<String>,
+    },
+
+    /// Add or remove tags on a track of a tracklist.
+    ///
+    /// Example:
+    /// albumseq_cli tag-track --tracklist "My Album" --title "Song1" --tags uptempo live
+    TagTrack {
+        /// Tracklist containing the track.
+        #[arg(short = 'l', long)]
+        tracklist: String,
+
+        /// Title of the track to tag.
+        #[arg(short, long)]
+        title: String,
+
+        /// Tags to add (or remove with --remove).
+        #[arg(short = 'g', long, required = true, num_args = 1..)]
+        tags: Vec<String>,
+
+        /// Remove the given tags instead of adding them.
+        #[arg(short, long)]
+        remove: bool,
+    },
+
+    /// Set metadata of a track of a tracklist.
+    ///
+    /// Example:
+    /// albumseq_cli set-track-info --tracklist "My Album" --title "Song1" --energy 8
+    SetTrackInfo {
+        /// Tracklist containing the track.
+        #[arg(short = 'l', long)]
+        tracklist: String,
+
+        /// Title of the track.
+        #[arg(short, long)]
+        title: String,
+
+        /// Energy rating, e.g. 1 (calm) to 10 (intense).
+        #[arg(short, long)]
+        energy: Option<u8>,
+
+        /// ISRC code, e.g. US-S1Z-99-00001 (hyphens optional).
+        #[arg(long)]
+        isrc: Option<String>,
+
+        /// Mark (true) or unmark (false) the track as part of a continuous flow.
+        #[arg(long)]
+        continuous: Option<bool>,
+
+        /// Silence after the track (MM:SS), counted toward its side; 0:00 removes it.
+        #[arg(long)]
+        gap: Option<String>,
+
+        /// Performing artist of the track; an empty name removes it.
+        #[arg(long)]
+        artist: Option<String>,
+
+        /// Integrated loudness in LUFS, e.g. -9.5.
+        #[arg(long, allow_hyphen_values = true)]
+        loudness: Option<f64>,
+    },
+
+    /// Set or clear the catalog number (e.g. UPC/EAN) of a tracklist.
+    ///
+    /// Example:
+    /// albumseq_cli set-catalog --tracklist "My Album" --catalog 0123456789012
+    SetCatalog {
+        /// Tracklist to update.
+        #[arg(short = 'l', long)]
+        tracklist: String,
+
+        /// Catalog number. Omit to clear it.
+        #[arg(short, long)]
+        catalog: Option<String>,
+    },
+
+    /// Add a signed offset to the duration of every track of a tracklist.
+    /// Durations that would become negative are set to zero.
+    ///
+    /// Example:
+    /// albumseq_cli offset-durations --tracklist "My Album" --delta -0:03
+    OffsetDurations {
+        /// Tracklist to adjust.
+        #[arg(short = 'l', long)]
+        tracklist: String,
+
+        /// Offset to add: MM:SS or decimal minutes, with an optional leading + or -.
+        #[arg(short, long, allow_hyphen_values = true)]
+        delta: String,
+    },
+
+    /// Remove duplicate tracks from a tracklist, keeping the first occurrence.
+    /// Tracks are duplicates when title (case-insensitive) and duration (within a second) match.
+    ///
+    /// Example:
+    /// albumseq_cli dedup --tracklist "My Album" --by-title
+    Dedup {
+        /// Tracklist to deduplicate.
+        #[arg(short = 'l', long)]
+        tracklist: String,
+
+        /// Treat tracks with the same title as duplicates, whatever their duration.
+        #[arg(long)]
+        by_title: bool,
+    },
+
+    /// Report the tracks, across all tracklists, whose duration isn't positive
+    /// (e.g. "0:00" from an import), as they silently break side splitting.
+    ///
+    /// Example:
+    /// albumseq_cli check-durations --fix
+    CheckDurations {
+        /// Remove the reported tracks, after confirmation.
+        #[arg(long)]
+        fix: bool,
+
+        /// With --fix, remove without asking for confirmation (e.g. in scripts).
+        #[arg(short = 'y', long, requires = "fix")]
+        yes: bool,
+    },
+
+    /// Show the differences between two tracklists, matching tracks by title:
+    /// added, removed and moved tracks, and duration changes.
+    ///
+    /// Example:
+    /// albumseq_cli diff-tracklists --a "My Album" --b "My Album (edit)"
+    DiffTracklists {
+        /// Tracklist to compare from.
+        #[arg(long)]
+        a: String,
+
+        /// Tracklist to compare to.
+        #[arg(long)]
+        b: String,
+    },
+
+    /// Swap the positions of two tracks of a tracklist, found by title
+    /// (case-insensitive).
+    ///
+    /// Example:
+    /// albumseq_cli swap-tracks --tracklist "My Album" --title1 "Song1" --title2 "Song2"
+    SwapTracks {
+        /// Tracklist containing the tracks.
+        #[arg(short = 'l', long)]
+        tracklist: String,
+
+        /// Title of the first track.
+        #[arg(long)]
+        title1: String,
+
+        /// Title of the second track.
+        #[arg(long)]
+        title2: String,
+    },
+
+    /// Rename a track in every tracklist and in the constraints referring to it.
+    /// The old title is matched case-insensitively.
+    ///
+    /// Example:
+    /// albumseq_cli rename-track --old "Song1" --new "Song One"
+    RenameTrack {
+        /// Current title of the track.
+        #[arg(long)]
+        old: String,
+
+        /// New title of the track.
+        #[arg(long)]
+        new: String,
+    },
+
+    /// Normalize the track titles of a tracklist: "trim" fixes the whitespace,
+    /// "titlecase" and "lowercase" also change the case. Constraint arguments that
+    /// refer to the tracks are rewritten to match.
+    ///
+    /// Example:
+    /// albumseq_cli normalize-titles --tracklist "My Album" --mode titlecase
+    NormalizeTitles {
+        /// Tracklist whose titles are normalized.
+        #[arg(short = 'l', long)]
+        tracklist: String,
+
+        /// Normalization: "trim", "titlecase", or "lowercase".
+        #[arg(long, value_enum)]
+        mode: TitleNormalization,
+    },
+
+    /// Sort the tracks of a tracklist by title or duration and save the new order.
+    /// A quick deterministic ordering; unlike propose, constraints are ignored.
+    ///
+    /// Example:
+    /// albumseq_cli sort --tracklist "My Album" --by duration-desc
+    Sort {
+        /// Tracklist to sort.
+        #[arg(short = 'l', long)]
+        tracklist: String,
+
+        /// Order: "title", "duration-asc", or "duration-desc".
+        #[arg(long, value_enum)]
+        by: TrackOrder,
     },
 
     /// Add a constraint to the context.
@@ -90,27 +460,193 @@ pub enum Commands {
     /// Example:
     /// albumseq_cli add-constraint --kind adjacent --args "Song1" "Song2" --weight 2
     AddConstraint {
-        /// Constraint kind: "atpos", "adjacent", or "onsameside".
+        /// Constraint kind: "atpos", "nearpos", "atany", "adjacent", "onsameside",
+        /// "prefersameside", "sidesorted", "nosidebreak", "spreadtag", "longestnotcloser",
+        /// "shorttoinner", "startsbefore", "stayclose", "strongopeners", "fillsides",
+        /// "maxhardcuts", "sidecontains", "sidenearfull", "sidebalance", "discbalance",
+        /// "maxartistperside", "smoothloudness", "firstbreakafter", "bookend", or
+        /// "alternatepacing".
         #[arg(short, long)]
         kind: String,
 
         /// Arguments depending on kind.
-        #[arg(short = 'a', long)]
+        #[arg(short = 'a', long, num_args = 1..)]
         args: Vec<String>,
 
         /// Weight of the constraint.
         #[arg(short, long, default_value = "1")]
         weight: usize,
+
+        /// Show the top proposal for a tracklist & medium with and without the
+        /// constraint instead of saving it.
+        #[arg(long, num_args = 2, value_names = ["TRACKLIST", "MEDIUM"])]
+        preview: Option<Vec<String>>,
+    },
+
+    /// Build a constraint interactively: pick a kind, its tracks and a weight.
+    /// Requires a terminal; scripts should use add-constraint.
+    ///
+    /// Example:
+    /// albumseq_cli add-constraint-wizard --tracklist "My Album"
+    AddConstraintWizard {
+        /// Tracklist whose titles are offered as choices (default: all tracklists).
+        #[arg(short, long)]
+        tracklist: Option<String>,
+    },
+
+    /// Add a curated set of constraints with sensible weights.
+    /// Presets: "vinyl-audiophile" (longestnotcloser, sidebalance 2:00, fillsides 0.8)
+    /// and "vinyl-basic" (longestnotcloser, fillsides 0.6).
+    ///
+    /// Example:
+    /// albumseq_cli add-preset --name vinyl-audiophile
+    AddPreset {
+        /// Name of the preset.
+        #[arg(short, long)]
+        name: String,
     },
 
     /// Remove a constraint by index.
     ///
     /// Example:
-    /// albumseq_cli remove-constraint --index 0
+    /// albumseq_cli remove-constraint --index 0 --dry-run
     RemoveConstraint {
         /// Index of the constraint to remove.
         #[arg(long)]
         index: usize,
+
+        /// Print the constraint that would be removed without changing the context.
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// Multiply the weights of all constraints, medium constraints included, or only
+    /// those of one kind, by a factor. Weights are rounded to the nearest integer,
+    /// with a minimum of 1.
+    ///
+    /// Example:
+    /// albumseq_cli scale-weights --factor 2 --kind adjacent
+    ScaleWeights {
+        /// Factor to multiply the weights by (e.g. 2 or 0.5).
+        #[arg(short, long)]
+        factor: f64,
+
+        /// Only scale constraints of this kind, as given to add-constraint (e.g. "adjacent").
+        #[arg(short, long)]
+        kind: Option<String>,
+    },
+
+    /// Enable or disable a constraint by index, keeping it in the context.
+    ///
+    /// Example:
+    /// albumseq_cli toggle-constraint --index 0
+    ToggleConstraint {
+        /// Index of the constraint to toggle.
+        #[arg(long)]
+        index: usize,
+    },
+
+    /// Write all constraints (kind, args, weight, enabled) to a file for sharing or
+    /// version control. The file is TOML for a `.toml` path, JSON otherwise.
+    ///
+    /// Example:
+    /// albumseq_cli export-constraints --path constraints.toml
+    ExportConstraints {
+        /// File to write the constraints to.
+        #[arg(short, long)]
+        path: PathBuf,
+    },
+
+    /// Add the constraints of a file written by export-constraints, replacing
+    /// constraints of the same kind.
+    ///
+    /// Example:
+    /// albumseq_cli import-constraints --path constraints.toml
+    ImportConstraints {
+        /// File to read the constraints from.
+        #[arg(short, long)]
+        path: PathBuf,
+    },
+
+    /// Add a constraint to a medium, applied on top of the context's constraints
+    /// whenever that medium is proposed on. Replaces one of the same kind.
+    ///
+    /// Example:
+    /// albumseq_cli add-medium-constraint --medium "Vinyl" --kind shorttoinner --args 1 --weight 2
+    AddMediumConstraint {
+        /// Medium to add the constraint to.
+        #[arg(short, long)]
+        medium: String,
+
+        /// Constraint kind, as for add-constraint.
+        #[arg(short, long)]
+        kind: String,
+
+        /// Arguments depending on kind.
+        #[arg(short = 'a', long, num_args = 1..)]
+        args: Vec<String>,
+
+        /// Weight of the constraint.
+        #[arg(short, long, default_value = "1")]
+        weight: usize,
+    },
+
+    /// Remove a constraint of a medium by its index (see `show media`).
+    ///
+    /// Example:
+    /// albumseq_cli remove-medium-constraint --medium "Vinyl" --index 0
+    RemoveMediumConstraint {
+        /// Medium to remove the constraint from.
+        #[arg(short, long)]
+        medium: String,
+
+        /// Index of the constraint in the medium's list.
+        #[arg(long)]
+        index: usize,
+    },
+
+    /// List the tracks of a tracklist that no enabled constraint refers to by title,
+    /// i.e. those the optimizer can place freely.
+    ///
+    /// Example:
+    /// albumseq_cli unconstrained --tracklist "My Album"
+    Unconstrained {
+        /// Tracklist whose tracks are checked.
+        #[arg(short = 'l', long)]
+        tracklist: String,
+    },
+
+    /// List pairs of enabled constraints that obviously contradict each other, such
+    /// as two tracks required at the same position. Medium constraints are checked
+    /// against the constraints that apply with them.
+    ///
+    /// Example:
+    /// albumseq_cli conflicts
+    Conflicts,
+
+    /// Describe a constraint and, for each medium, whether its side logic means
+    /// anything there (e.g. "onsameside" is a no-op on a single side).
+    ///
+    /// Example:
+    /// albumseq_cli constraint-info --index 0
+    ConstraintInfo {
+        /// Index of the constraint to describe.
+        #[arg(long)]
+        index: usize,
+
+        /// Describe a constraint of this medium instead (see `show media`).
+        #[arg(short, long)]
+        medium: Option<String>,
+    },
+
+    /// List the constraints whose referenced tracks all exist in a tracklist.
+    ///
+    /// Example:
+    /// albumseq_cli constraints-for --tracklist "My Album"
+    ConstraintsFor {
+        /// Tracklist to check the constraints against.
+        #[arg(short = 'l', long)]
+        tracklist: String,
     },
 
     /// Show the current context or filtered parts of it.
@@ -123,6 +659,34 @@ pub enum Commands {
         filter: Option<String>,
     },
 
+    /// Show a single tracklist with its total runtime, and optionally its sides on a medium.
+    ///
+    /// Example:
+    /// albumseq_cli show-tracklist --name "My Album" --medium "Vinyl"
+    ShowTracklist {
+        /// Name of the tracklist.
+        #[arg(short, long)]
+        name: String,
+
+        /// Also show how the stored order splits into sides on this medium.
+        #[arg(short, long)]
+        medium: Option<String>,
+    },
+
+    /// Explain the score of a tracklist's stored order: the points of each constraint and the total.
+    ///
+    /// Example:
+    /// albumseq_cli explain --tracklist "My Album" --medium "Vinyl"
+    Explain {
+        /// Tracklist whose stored order is scored.
+        #[arg(short = 'l', long)]
+        tracklist: String,
+
+        /// Medium to score the order on.
+        #[arg(short, long)]
+        medium: String,
+    },
+
     /// Propose top scoring tracklist permutations for a tracklist & medium.
     ///
     /// Example:
@@ -136,12 +700,276 @@ pub enum Commands {
         #[arg(short, long)]
         medium: String,
 
+        /// Use this number of sides instead of the medium's, without changing the medium.
+        #[arg(long)]
+        sides: Option<usize>,
+
+        /// Use this max duration per side (MM:SS or decimal minutes) instead of the
+        /// medium's, without changing the medium.
+        #[arg(long)]
+        max_duration: Option<String>,
+
         /// Number of propositions to show.
         #[arg(short, long, default_value = "15")]
         count: usize,
 
-        /// Minimum score to include (optional).
+        /// Minimum score to include (optional); may be fractional, e.g. 7.5.
         #[arg(short = 'm', long)]
-        min_score: Option<usize>,
+        min_score: Option<f64>,
+
+        /// When nothing is proposed, explain whether capacity or --min-score is the cause.
+        #[arg(long)]
+        diagnose: bool,
+
+        /// Re-score every permutation instead of reading the proposal cache.
+        #[arg(long)]
+        no_cache: bool,
+
+        /// How permutations are picked for scoring.
+        #[arg(long, value_enum, default_value_t = Algorithm::Exhaustive)]
+        algorithm: Algorithm,
+
+        /// Number of distinct random permutations scored by `--algorithm sample`.
+        #[arg(long, default_value = "1000")]
+        samples: usize,
+
+        /// Random seed for `--algorithm sample`; the same seed gives the same proposals.
+        #[arg(long, default_value = "0")]
+        seed: u64,
+
+        /// With `--algorithm sample`, favour permutations close to the order of this
+        /// tracklist, so runs don't wander far from it.
+        #[arg(long)]
+        anchor: Option<String>,
+
+        /// Points a permutation earns for matching the `--anchor` order exactly, added to
+        /// its constraint score; fewer the further it strays.
+        #[arg(long, default_value = "10")]
+        anchor_strength: usize,
+
+        /// With `--algorithm sample`, keep the stored first track first (e.g. an intro)
+        /// and only shuffle the others.
+        #[arg(long)]
+        fix_first: bool,
+
+        /// With `--algorithm sample`, keep the stored last track last.
+        #[arg(long)]
+        fix_last: bool,
+
+        /// Side labels in the output: "numbers" (1, 2, ...) or "letters" (A, B, ...).
+        #[arg(long, value_enum, default_value_t = SideLabels::Numbers)]
+        side_labels: SideLabels,
+
+        /// Print run statistics (permutations evaluated and fitting, score distribution, time).
+        #[arg(long)]
+        stats: bool,
+
+        /// Print the wall-clock time spent generating, scoring and ranking permutations.
+        #[arg(long)]
+        measure: bool,
+
+        /// Also score the stored order and show its rank among the fitting permutations.
+        #[arg(long)]
+        include_original: bool,
+
+        /// Among permutations with equal scores, show those closest to the stored order first.
+        #[arg(long)]
+        prefer_original: bool,
+
+        /// Order the permutations by "score" (highest first) or by "balance" (smallest
+        /// difference between the longest and shortest side first, score breaking ties).
+        #[arg(long, value_enum, default_value_t = SortBy::Score)]
+        sort_by: SortBy,
+
+        /// Group the top permutations: "side1" shows only the best one of those with
+        /// the same set of tracks on the first side, with how many it stands for.
+        #[arg(long, value_enum)]
+        group_by: Option<GroupBy>,
+
+        /// Points a permutation earns for a perfect fit, added to its constraint score:
+        /// in proportion to how full and how evenly filled its sides are. 0 ranks by
+        /// constraints only.
+        #[arg(long, default_value = "0")]
+        fit_weight: f64,
+
+        /// When the tracks run longer than the medium holds, propose orders of the
+        /// subsets of tracks that fill it best, listing the tracks left off. Up to 20 tracks.
+        #[arg(long)]
+        allow_partial: bool,
+
+        /// Store the proposals shown in the context, to print again with last-proposals.
+        /// Not available when the context is piped through stdin and stdout.
+        #[arg(long)]
+        remember: bool,
+
+        /// Output format: "table" (terminal), "markdown" (GitHub-flavored pipe tables),
+        /// or "ndjson" (one JSON object per permutation and line).
+        #[arg(long, value_enum, default_value_t = OutputFormat::Table)]
+        format: OutputFormat,
+    },
+
+    /// Print the proposals stored by the last `propose --remember` run.
+    ///
+    /// Example:
+    /// albumseq_cli last-proposals
+    LastProposals,
+
+    /// Propose top scoring permutations of a tracklist on every stored medium.
+    ///
+    /// Example:
+    /// albumseq_cli propose-all --tracklist "My Album" --count 3
+    ProposeAll {
+        /// Tracklist name to use.
+        #[arg(short, long)]
+        tracklist: String,
+
+        /// Number of propositions to show per medium.
+        #[arg(short, long, default_value = "5")]
+        count: usize,
+
+        /// Re-score every permutation instead of reading the proposal cache.
+        #[arg(long)]
+        no_cache: bool,
+
+        /// Side labels in the output: "numbers" (1, 2, ...) or "letters" (A, B, ...).
+        #[arg(long, value_enum, default_value_t = SideLabels::Numbers)]
+        side_labels: SideLabels,
+    },
+
+    /// Propose a box set: each tracklist is one disc, permuted within itself only.
+    /// Constraints are scored across the whole set, discs played in the given order.
+    ///
+    /// Example:
+    /// albumseq_cli propose-set --tracklists "Disc A" "Disc B" --medium "Vinyl" --count 5
+    ProposeSet {
+        /// Tracklist names to use, one per disc, in disc order.
+        #[arg(short, long, required = true, num_args = 1..)]
+        tracklists: Vec<String>,
+
+        /// Medium each disc goes on.
+        #[arg(short, long)]
+        medium: String,
+
+        /// Number of sets to show.
+        #[arg(short, long, default_value = "5")]
+        count: usize,
+
+        /// Side labels in the output: "numbers" (1, 2, ...) or "letters" (A, B, ...).
+        #[arg(long, value_enum, default_value_t = SideLabels::Numbers)]
+        side_labels: SideLabels,
+    },
+
+    /// Show how a tracklist's stored order splits on a medium, with each side's
+    /// duration and fill percentage, without scoring or permuting.
+    ///
+    /// Example:
+    /// albumseq_cli layout --tracklist "My Album" --medium "Vinyl"
+    Layout {
+        /// Tracklist to lay out, in its stored order.
+        #[arg(short = 'l', long)]
+        tracklist: String,
+
+        /// Medium the tracklist is split on.
+        #[arg(short, long)]
+        medium: String,
+
+        /// Side labels: "numbers" (1, 2, ...) or "letters" (A, B, ...).
+        #[arg(long, value_enum, default_value_t = SideLabels::Numbers)]
+        side_labels: SideLabels,
     },
+
+    /// Show, over a sample of permutations that fit a medium, how often each track lands
+    /// at each position, as a grid of percentages (tracks x positions).
+    ///
+    /// Example:
+    /// albumseq_cli heatmap --tracklist "My Album" --medium "Vinyl" --samples 5000
+    Heatmap {
+        /// Tracklist whose permutations are sampled.
+        #[arg(short = 'l', long)]
+        tracklist: String,
+
+        /// Medium the permutations must fit.
+        #[arg(short, long)]
+        medium: String,
+
+        /// Number of distinct random permutations drawn (all of them if there are fewer).
+        #[arg(long, default_value = "1000")]
+        samples: usize,
+
+        /// Random seed; the same seed draws the same permutations.
+        #[arg(long, default_value = "0")]
+        seed: u64,
+    },
+
+    /// Render a printable plain-text sheet of a tracklist's stored order on a medium:
+    /// title, sides side by side with track and side durations, and total runtime.
+    ///
+    /// Example:
+    /// albumseq_cli sheet --tracklist "My Album" --medium "Vinyl" --output sheet.txt
+    Sheet {
+        /// Tracklist to render, in its stored order.
+        #[arg(short = 'l', long)]
+        tracklist: String,
+
+        /// Medium the tracklist is split on.
+        #[arg(short, long)]
+        medium: String,
+
+        /// File to write the sheet to; printed when omitted.
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+
+        /// Side labels: "numbers" (1, 2, ...) or "letters" (A, B, ...).
+        #[arg(long, value_enum, default_value_t = SideLabels::Numbers)]
+        side_labels: SideLabels,
+    },
+
+    /// Show the capacity math of a medium and flag tracks too long for any side.
+    ///
+    /// Example:
+    /// albumseq_cli capacity --medium "Vinyl"
+    Capacity {
+        /// Medium name to inspect.
+        #[arg(short, long)]
+        medium: String,
+    },
+
+    /// List the stored media a tracklist fits on in at least one order,
+    /// ordered by total capacity (smallest first).
+    ///
+    /// Example:
+    /// albumseq_cli fit-medium --tracklist "My Album"
+    FitMedium {
+        /// Tracklist to fit.
+        #[arg(short = 'l', long)]
+        tracklist: String,
+    },
+
+    /// Split a tracklist, in order, into several tracklists that each fit a medium.
+    /// The parts are saved as "<name>-disc1", "<name>-disc2", etc.
+    ///
+    /// Example:
+    /// albumseq_cli split --tracklist "My Album" --medium "Vinyl" --into 2
+    Split {
+        /// Tracklist name to split.
+        #[arg(short, long)]
+        tracklist: String,
+
+        /// Medium each part must fit on.
+        #[arg(short, long)]
+        medium: String,
+
+        /// Maximum number of parts to split into.
+        #[arg(short, long, default_value = "2")]
+        into: usize,
+    },
+}
+
+/// How `propose` picks the permutations it scores.
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Algorithm {
+    /// Score every permutation (exact, but slow on long tracklists).
+    Exhaustive,
+    /// Score `--samples` random permutations (approximate, for long tracklists).
+    Sample,
 }
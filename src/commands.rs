@@ -2,381 +2,4418 @@
 //!
 //! This module contains functions that implement the logic for each CLI command.
 //! Each handler is responsible for updating the context, performing calculations,
-//! or displaying output as needed.
+//! or displaying output as needed. Handlers return a [`CliResult`]; the caller saves
+//! the context only when a mutating handler succeeds.
 //!
 //! ## Example
 //! ```rust
-//! handle_add_tracklist(&mut ctx, &name, tracks);
-//! handle_propose(&ctx, &tracklist, &medium, &count, &min_score);
+//! handle_add_tracklist(&mut ctx, &name, tracks)?;
+//! handle_propose(&ctx, &tracklist, &medium, &opts)?;
 //! ```
 
-use crate::context::ProgramContext;
-use crate::utils::format_duration;
-use albumseq::{
-    Constraint as AlbumConstraint, ConstraintKind as AlbumConstraintKind, Duration,
-    Medium as AlbumMedium, Track, Tracklist, TracklistPermutations, score_tracklist,
+use crate::cache::{CacheEntry, ProposalCache, cache_key};
+use crate::chapters::parse_chapters;
+use crate::conflicts::{Conflict, find_conflicts};
+use crate::context::{
+    NamedSerTracklist, ProgramContext, SerConstraint, SerConstraintKind, SerMedium, SerTrack,
+    SerTracklist, StoredProposal, StoredProposals,
 };
+use crate::error::{CliError, CliResult};
+use crate::m3u::parse_m3u;
+use crate::ranking::TopProposals;
+use crate::scoring::{
+    FIT_TOLERANCE, Gaps, SIDES_PER_DISC, Score, Scorer, arg_matches, fits_medium,
+    split_tracklist_by_side,
+};
+use crate::sheet::render_sheet;
+use crate::utils::{
+    GroupBy, OutputFormat, SideLabels, SortBy, TitleNormalization, TrackOrder, format_duration,
+    format_score, parse_duration, parse_signed_duration, status,
+};
+use crate::wizard::prompt_constraint;
+use albumseq::{Duration, Medium as AlbumMedium, Track, Tracklist, TracklistPermutations};
 use colored::*;
+use log::{debug, warn};
 use prettytable::{Cell, Row, Table, format};
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::fs;
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration as TimeDuration, Instant};
 
 /// Parses a constraint kind and its arguments from CLI input.
-/// Returns `Some(AlbumConstraintKind)` if parsing is successful, or `None` if invalid.
-fn parse_constraint_kind(kind: &str, args: &[String]) -> Option<AlbumConstraintKind> {
+/// Returns a user error describing the problem if the kind or its arguments are invalid.
+fn parse_constraint_kind(kind: &str, args: &[String]) -> CliResult<SerConstraintKind> {
+    // A title may be qualified with a duration ("Song One@3:45"); see split_duration_qualifier
+    for arg in args {
+        if let Some((_, qualifier)) = arg.rsplit_once('@')
+            && qualifier.contains(':')
+            && parse_duration(qualifier).is_none()
+        {
+            return Err(CliError::User(format!(
+                "Invalid duration qualifier in '{}' (expected Title@MM:SS)",
+                arg
+            )));
+        }
+    }
+
     match kind.to_lowercase().as_str() {
         "atpos" => {
             if args.len() == 2 {
                 let pos = args[1].parse::<usize>();
                 if let Ok(pos) = pos {
-                    Some(AlbumConstraintKind::AtPosition(args[0].clone(), pos))
+                    Ok(SerConstraintKind::AtPosition(args[0].clone(), pos))
                 } else {
-                    eprintln!("Invalid position number: {}", args[1]);
-                    None
+                    Err(CliError::User(format!(
+                        "Invalid position number: {}",
+                        args[1]
+                    )))
                 }
             } else {
-                eprintln!("AtPosition constraint requires exactly 2 arguments: title pos");
-                None
+                Err(CliError::User(
+                    "AtPosition constraint requires exactly 2 arguments: title pos".to_string(),
+                ))
+            }
+        }
+        "nearpos" => {
+            if args.len() == 2 {
+                args[1]
+                    .parse::<usize>()
+                    .map(|pos| SerConstraintKind::NearPosition(args[0].clone(), pos))
+                    .map_err(|_| CliError::User(format!("Invalid position number: {}", args[1])))
+            } else {
+                Err(CliError::User(
+                    "NearPosition constraint requires exactly 2 arguments: title pos".to_string(),
+                ))
             }
         }
         "adjacent" => {
             if args.len() == 2 {
-                Some(AlbumConstraintKind::Adjacent(
+                Ok(SerConstraintKind::Adjacent(
                     args[0].clone(),
                     args[1].clone(),
                 ))
             } else {
-                eprintln!("Adjacent constraint requires exactly 2 arguments: title1 title2");
-                None
+                Err(CliError::User(
+                    "Adjacent constraint requires exactly 2 arguments: title1 title2".to_string(),
+                ))
+            }
+        }
+        "bookend" => {
+            if args.len() == 2 {
+                Ok(SerConstraintKind::Bookend(args[0].clone(), args[1].clone()))
+            } else {
+                Err(CliError::User(
+                    "Bookend constraint requires exactly 2 arguments: opener closer".to_string(),
+                ))
             }
         }
         "onsameside" => {
             if args.len() == 2 {
-                Some(AlbumConstraintKind::OnSameSide(
+                Ok(SerConstraintKind::OnSameSide(
                     args[0].clone(),
                     args[1].clone(),
                 ))
             } else {
-                eprintln!("OnSameSide constraint requires exactly 2 arguments: title1 title2");
-                None
+                Err(CliError::User(
+                    "OnSameSide constraint requires exactly 2 arguments: title1 title2".to_string(),
+                ))
             }
         }
-        _ => {
-            eprintln!("Unknown constraint kind: {}", kind);
-            None
+        "sidesorted" => {
+            if args.len() == 2 {
+                let side = args[0].parse::<usize>();
+                let ascending = match args[1].to_lowercase().as_str() {
+                    "asc" | "ascending" => Some(true),
+                    "desc" | "descending" => Some(false),
+                    _ => None,
+                };
+                match (side, ascending) {
+                    (Ok(side), Some(ascending)) => {
+                        Ok(SerConstraintKind::SideSortedByDuration(side, ascending))
+                    }
+                    (Err(_), _) => Err(CliError::User(format!("Invalid side number: {}", args[0]))),
+                    (_, None) => Err(CliError::User(format!(
+                        "Invalid sort order: {} (expected asc or desc)",
+                        args[1]
+                    ))),
+                }
+            } else {
+                Err(CliError::User(
+                    "SideSorted constraint requires exactly 2 arguments: side asc|desc".to_string(),
+                ))
+            }
+        }
+        "nosidebreak" => {
+            if args.len() == 2 {
+                Ok(SerConstraintKind::MaxSideBreaksBetween(
+                    args[0].clone(),
+                    args[1].clone(),
+                ))
+            } else {
+                Err(CliError::User(
+                    "NoSideBreak constraint requires exactly 2 arguments: title1 title2"
+                        .to_string(),
+                ))
+            }
+        }
+        "spreadtag" => {
+            if args.len() == 2 {
+                if let Ok(min_gap) = args[1].parse::<usize>() {
+                    Ok(SerConstraintKind::SpreadTag(args[0].clone(), min_gap))
+                } else {
+                    Err(CliError::User(format!("Invalid minimum gap: {}", args[1])))
+                }
+            } else {
+                Err(CliError::User(
+                    "SpreadTag constraint requires exactly 2 arguments: tag min_gap".to_string(),
+                ))
+            }
+        }
+        "longestnotcloser" => {
+            if args.is_empty() {
+                Ok(SerConstraintKind::LongestNotSideCloser)
+            } else {
+                Err(CliError::User(
+                    "LongestNotCloser constraint takes no arguments".to_string(),
+                ))
+            }
+        }
+        "shorttoinner" => match args {
+            [] => Ok(SerConstraintKind::ShortToInner(1)),
+            [scale] => scale
+                .parse::<usize>()
+                .map(SerConstraintKind::ShortToInner)
+                .map_err(|_| CliError::User(format!("Invalid weight scale: {}", scale))),
+            _ => Err(CliError::User(
+                "ShortToInner constraint takes at most 1 argument: weight_scale".to_string(),
+            )),
+        },
+        "startsbefore" => {
+            if args.len() == 2 {
+                if let Some(minutes) = parse_duration(&args[1]) {
+                    Ok(SerConstraintKind::StartsBefore(args[0].clone(), minutes))
+                } else {
+                    Err(CliError::User(format!("Invalid duration: {}", args[1])))
+                }
+            } else {
+                Err(CliError::User(
+                    "StartsBefore constraint requires exactly 2 arguments: title duration"
+                        .to_string(),
+                ))
+            }
+        }
+        "sidebalance" => {
+            if args.len() == 1 {
+                parse_duration(&args[0])
+                    .map(SerConstraintKind::SideBalance)
+                    .ok_or_else(|| CliError::User(format!("Invalid duration: {}", args[0])))
+            } else {
+                Err(CliError::User(
+                    "SideBalance constraint requires exactly 1 argument: tolerance".to_string(),
+                ))
+            }
+        }
+        "discbalance" => {
+            if args.len() == 1 {
+                parse_duration(&args[0])
+                    .map(SerConstraintKind::DiscBalance)
+                    .ok_or_else(|| CliError::User(format!("Invalid duration: {}", args[0])))
+            } else {
+                Err(CliError::User(
+                    "DiscBalance constraint requires exactly 1 argument: tolerance".to_string(),
+                ))
+            }
+        }
+        "maxartistperside" => {
+            if args.len() == 1 {
+                args[0]
+                    .parse::<usize>()
+                    .map(SerConstraintKind::MaxArtistPerSide)
+                    .map_err(|_| CliError::User(format!("Invalid number of tracks: {}", args[0])))
+            } else {
+                Err(CliError::User(
+                    "MaxArtistPerSide constraint requires exactly 1 argument: max_tracks"
+                        .to_string(),
+                ))
+            }
+        }
+        "firstbreakafter" => {
+            if args.len() == 1 {
+                parse_duration(&args[0])
+                    .map(SerConstraintKind::FirstBreakAfter)
+                    .ok_or_else(|| CliError::User(format!("Invalid duration: {}", args[0])))
+            } else {
+                Err(CliError::User(
+                    "FirstBreakAfter constraint requires exactly 1 argument: duration".to_string(),
+                ))
+            }
+        }
+        "alternatepacing" => {
+            if args.len() == 1 {
+                parse_duration(&args[0])
+                    .filter(|threshold| *threshold > 0.0)
+                    .map(SerConstraintKind::AlternatePacing)
+                    .ok_or_else(|| CliError::User(format!("Invalid duration: {}", args[0])))
+            } else {
+                Err(CliError::User(
+                    "AlternatePacing constraint requires exactly 1 argument: threshold".to_string(),
+                ))
+            }
+        }
+        "smoothloudness" => {
+            if args.len() == 1 {
+                args[0]
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|jump| *jump >= 0.0)
+                    .map(SerConstraintKind::SmoothLoudness)
+                    .ok_or_else(|| {
+                        CliError::User(format!(
+                            "Invalid loudness jump: {} (expected LU, e.g. 3)",
+                            args[0]
+                        ))
+                    })
+            } else {
+                Err(CliError::User(
+                    "SmoothLoudness constraint requires exactly 1 argument: max_jump".to_string(),
+                ))
+            }
+        }
+        "stayclose" => {
+            if args.len() == 1 {
+                Ok(SerConstraintKind::StayCloseTo(args[0].clone()))
+            } else {
+                Err(CliError::User(
+                    "StayClose constraint requires exactly 1 argument: tracklist".to_string(),
+                ))
+            }
+        }
+        "strongopeners" => {
+            if args.len() == 1 {
+                args[0]
+                    .parse::<u8>()
+                    .map(SerConstraintKind::StrongSideOpeners)
+                    .map_err(|_| CliError::User(format!("Invalid energy: {}", args[0])))
+            } else {
+                Err(CliError::User(
+                    "StrongOpeners constraint requires exactly 1 argument: min_energy".to_string(),
+                ))
+            }
+        }
+        "fillsides" => {
+            if args.len() == 1 {
+                match args[0].parse::<f64>() {
+                    Ok(target) if target > 0.0 && target <= 1.0 => {
+                        Ok(SerConstraintKind::FillSides(target))
+                    }
+                    _ => Err(CliError::User(format!(
+                        "Invalid fill fraction: {} (expected a number in (0, 1])",
+                        args[0]
+                    ))),
+                }
+            } else {
+                Err(CliError::User(
+                    "FillSides constraint requires exactly 1 argument: target_fraction".to_string(),
+                ))
+            }
+        }
+        "sidenearfull" => {
+            if args.len() == 2 {
+                let target = args[0]
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|t| *t > 0.0 && *t <= 1.0)
+                    .ok_or_else(|| {
+                        CliError::User(format!(
+                            "Invalid fill fraction: {} (expected a number in (0, 1])",
+                            args[0]
+                        ))
+                    })?;
+                let tolerance = args[1]
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|t| *t >= 0.0)
+                    .ok_or_else(|| {
+                        CliError::User(format!(
+                            "Invalid tolerance: {} (expected a fraction, e.g. 0.05)",
+                            args[1]
+                        ))
+                    })?;
+                Ok(SerConstraintKind::SideNearFull(target, tolerance))
+            } else {
+                Err(CliError::User(
+                    "SideNearFull constraint requires exactly 2 arguments: target_fraction tolerance"
+                        .to_string(),
+                ))
+            }
+        }
+        "maxhardcuts" => {
+            if args.len() == 1 {
+                args[0]
+                    .parse::<usize>()
+                    .map(SerConstraintKind::MaxHardCuts)
+                    .map_err(|_| CliError::User(format!("Invalid number of cuts: {}", args[0])))
+            } else {
+                Err(CliError::User(
+                    "MaxHardCuts constraint requires exactly 1 argument: max_cuts".to_string(),
+                ))
+            }
         }
+        "prefersameside" => {
+            if args.len() == 2 {
+                Ok(SerConstraintKind::PreferSameSide(
+                    args[0].clone(),
+                    args[1].clone(),
+                ))
+            } else {
+                Err(CliError::User(
+                    "PreferSameSide constraint requires exactly 2 arguments: title1 title2"
+                        .to_string(),
+                ))
+            }
+        }
+        "atany" => {
+            if args.len() == 2 {
+                let positions = args[1]
+                    .split(',')
+                    .map(|p| p.trim().parse::<usize>())
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|_| {
+                        CliError::User(format!(
+                            "Invalid position list: {} (expected e.g. 0,9)",
+                            args[1]
+                        ))
+                    })?;
+                Ok(SerConstraintKind::AtAnyPosition(args[0].clone(), positions))
+            } else {
+                Err(CliError::User(
+                    "AtAnyPosition constraint requires exactly 2 arguments: title positions"
+                        .to_string(),
+                ))
+            }
+        }
+        "sidecontains" => {
+            if args.len() >= 3 {
+                let side = args[0]
+                    .parse::<usize>()
+                    .map_err(|_| CliError::User(format!("Invalid side number: {}", args[0])))?;
+                let exact = match args[1].to_lowercase().as_str() {
+                    "exact" | "exactly" => true,
+                    "atleast" | "at-least" => false,
+                    _ => {
+                        return Err(CliError::User(format!(
+                            "Invalid side contents mode: {} (expected exact or atleast)",
+                            args[1]
+                        )));
+                    }
+                };
+                Ok(SerConstraintKind::SideContains(
+                    side,
+                    args[2..].to_vec(),
+                    exact,
+                ))
+            } else {
+                Err(CliError::User(
+                    "SideContains constraint requires at least 3 arguments: side exact|atleast title..."
+                        .to_string(),
+                ))
+            }
+        }
+        _ => Err(CliError::User(format!("Unknown constraint kind: {}", kind))),
     }
 }
 
-/// Splits a tracklist into sides based on medium max duration per side.
-/// Returns a vector of vectors, each representing a side.
-fn split_tracklist_by_side<'a>(
-    tracklist: &'a Tracklist,
-    medium: &'a AlbumMedium,
-) -> Vec<Vec<&'a Track>> {
-    let mut sides = Vec::new();
-    let mut current_side = Vec::new();
-    let mut current_duration = 0.0;
+/// Returns the `add-constraint` keyword of a constraint kind, as parsed by
+/// [`parse_constraint_kind`].
+fn constraint_keyword(kind: &SerConstraintKind) -> &'static str {
+    match kind {
+        SerConstraintKind::AtPosition(..) => "atpos",
+        SerConstraintKind::NearPosition(..) => "nearpos",
+        SerConstraintKind::AtAnyPosition(..) => "atany",
+        SerConstraintKind::Adjacent(..) => "adjacent",
+        SerConstraintKind::Bookend(..) => "bookend",
+        SerConstraintKind::OnSameSide(..) => "onsameside",
+        SerConstraintKind::PreferSameSide(..) => "prefersameside",
+        SerConstraintKind::SideSortedByDuration(..) => "sidesorted",
+        SerConstraintKind::MaxSideBreaksBetween(..) => "nosidebreak",
+        SerConstraintKind::SpreadTag(..) => "spreadtag",
+        SerConstraintKind::LongestNotSideCloser => "longestnotcloser",
+        SerConstraintKind::ShortToInner(..) => "shorttoinner",
+        SerConstraintKind::StartsBefore(..) => "startsbefore",
+        SerConstraintKind::StayCloseTo(..) => "stayclose",
+        SerConstraintKind::StrongSideOpeners(..) => "strongopeners",
+        SerConstraintKind::FillSides(..) => "fillsides",
+        SerConstraintKind::MaxHardCuts(..) => "maxhardcuts",
+        SerConstraintKind::SideContains(..) => "sidecontains",
+        SerConstraintKind::SideNearFull(..) => "sidenearfull",
+        SerConstraintKind::SideBalance(..) => "sidebalance",
+        SerConstraintKind::DiscBalance(..) => "discbalance",
+        SerConstraintKind::MaxArtistPerSide(..) => "maxartistperside",
+        SerConstraintKind::SmoothLoudness(..) => "smoothloudness",
+        SerConstraintKind::FirstBreakAfter(..) => "firstbreakafter",
+        SerConstraintKind::AlternatePacing(..) => "alternatepacing",
+    }
+}
 
-    for track in &tracklist.0 {
-        if current_duration + track.duration <= medium.max_duration_per_side {
-            current_side.push(track);
-            current_duration += track.duration;
-        } else {
-            sides.push(current_side);
-            current_side = vec![track];
-            current_duration = track.duration;
-        }
+/// Handles adding a new tracklist to the context.
+/// Returns an error if no valid track was given.
+pub fn handle_add_tracklist(
+    ctx: &mut ProgramContext,
+    name: &String,
+    tracks: Vec<Track>,
+) -> CliResult {
+    if tracks.is_empty() {
+        return Err(CliError::User(format!(
+            "No valid tracks provided for tracklist '{}'",
+            name
+        )));
+    }
 
-        if sides.len() == medium.sides {
-            break;
-        }
+    if let Ok(medium) = ctx.medium(name) {
+        warn!(
+            "Tracklist '{}' has the same name as medium '{}'; names are matched case-insensitively",
+            name, medium.name
+        );
     }
 
-    if !current_side.is_empty() {
-        sides.push(current_side);
+    ctx.add_or_replace_tracklist(name.clone(), tracks);
+
+    Ok(())
+}
+
+/// Handles adding a tracklist read from an extended M3U playlist (e.g. a Rekordbox
+/// or Serato export). Entries without a usable `#EXTINF` line are skipped with a warning.
+pub fn handle_import_m3u(ctx: &mut ProgramContext, name: &String, path: &Path) -> CliResult {
+    let content = fs::read_to_string(path)
+        .map_err(|e| CliError::Io(format!("Failed to read playlist {:?}: {}", path, e)))?;
+    let playlist = parse_m3u(&content);
+
+    for (entry, reason) in &playlist.skipped {
+        warn!("Skipping playlist entry \"{}\": {}", entry, reason);
     }
+    status!(
+        "Read {} tracks from {:?} ({} skipped)",
+        playlist.tracks.len(),
+        path,
+        playlist.skipped.len()
+    );
 
-    sides
+    handle_add_tracklist(ctx, name, playlist.tracks)
 }
 
-/// Handles adding a new tracklist to the context.
-/// Returns true if the tracklist was added or replaced.
-pub fn handle_add_tracklist(ctx: &mut ProgramContext, name: &String, tracks: Vec<Track>) -> bool {
-    ctx.add_or_replace_tracklist(name.clone(), tracks);
+/// Handles adding a tracklist read from a timestamped chapter list (e.g. a YouTube
+/// description). `total` is the runtime of the whole recording, which ends the last track.
+/// Lines starting with `comment` are skipped.
+pub fn handle_import_chapters(
+    ctx: &mut ProgramContext,
+    name: &String,
+    path: &Path,
+    total: Duration,
+    comment: char,
+) -> CliResult {
+    let content = fs::read_to_string(path)
+        .map_err(|e| CliError::Io(format!("Failed to read chapter list {:?}: {}", path, e)))?;
+    let chapters = parse_chapters(&content, total, comment).map_err(CliError::Parse)?;
 
-    true
+    for (line, reason) in &chapters.skipped {
+        warn!("Skipping chapter \"{}\": {}", line, reason);
+    }
+    status!(
+        "Read {} tracks from {:?} ({} skipped)",
+        chapters.tracks.len(),
+        path,
+        chapters.skipped.len()
+    );
+
+    handle_add_tracklist(ctx, name, chapters.tracks)
 }
 
-/// Handles adding a new medium to the context.
-/// Returns true if the medium was added or replaced.
+/// Handles adding a new medium to the context, recording the speed of a vinyl one.
 pub fn handle_add_medium(
     ctx: &mut ProgramContext,
     name: &String,
     sides: usize,
     max_duration: Duration,
-) -> bool {
-    ctx.add_or_replace_medium(name.clone(), sides, max_duration);
+    rpm: Option<f64>,
+) -> CliResult {
+    if let Some(rpm) = rpm
+        && !(rpm > 0.0 && rpm.is_finite())
+    {
+        return Err(CliError::User(format!(
+            "Invalid speed: {} (expected a positive RPM, e.g. 45)",
+            rpm
+        )));
+    }
+    if let Ok(tracklist) = ctx.tracklist(name) {
+        warn!(
+            "Medium '{}' has the same name as tracklist '{}'; names are matched case-insensitively",
+            name, tracklist.name
+        );
+    }
 
-    true
+    ctx.add_or_replace_medium(name.clone(), sides, max_duration, rpm);
+    if let Some(rpm) = rpm {
+        status!(
+            "Sides of medium '{}' hold {} at {} RPM",
+            name,
+            format_duration(max_duration),
+            rpm
+        );
+    }
+
+    Ok(())
+}
+
+/// Returns the number of sides of `max_duration` the stored order of a tracklist
+/// needs: its total runtime (gaps included) over the side length, rounded up, plus
+/// any sides lost to tracks that don't pack exactly.
+pub fn sides_needed_for(
+    ctx: &ProgramContext,
+    tracklist_name: &str,
+    max_duration: Duration,
+) -> CliResult<usize> {
+    let ser_tl = ctx.tracklist(tracklist_name)?;
+    ensure_has_tracks(ser_tl)?;
+    let tracklist = Tracklist::from(&ser_tl.tracks);
+    let gaps = Gaps::of(&ser_tl.tracks);
+    if let Some(track) = tracklist
+        .0
+        .iter()
+        .find(|t| t.duration > max_duration + FIT_TOLERANCE)
+    {
+        return Err(CliError::User(format!(
+            "Track '{}' ({}) is longer than a side of {}",
+            track.title,
+            format_duration(track.duration),
+            format_duration(max_duration)
+        )));
+    }
+
+    let all: Vec<&Track> = tracklist.0.iter().collect();
+    let mut medium =
+        SerMedium::for_total_duration(ser_tl.name.clone(), gaps.side_duration(&all), max_duration)
+            .to_album_medium();
+    while !fits_medium(&tracklist, &medium, &gaps) {
+        medium.sides += 1;
+    }
+
+    status!(
+        "Tracklist '{}' needs {} sides of {}",
+        ser_tl.name,
+        medium.sides,
+        format_duration(max_duration)
+    );
+    Ok(medium.sides)
+}
+
+/// Handles copying a medium under a new name.
+/// `sides` and `max_duration` override the source's values when given.
+/// Returns an error if the source doesn't exist or the new name is taken.
+pub fn handle_copy_medium(
+    ctx: &mut ProgramContext,
+    source: &str,
+    new_name: &String,
+    sides: Option<usize>,
+    max_duration: Option<Duration>,
+) -> CliResult {
+    let source = ctx.medium(source)?;
+    if let Ok(existing) = ctx.medium(new_name) {
+        return Err(CliError::User(format!(
+            "Medium '{}' already exists",
+            existing.name
+        )));
+    }
+
+    let sides = sides.unwrap_or(source.sides);
+    let rpm = source.rpm;
+    let max_duration = max_duration.unwrap_or(source.max_duration_per_side);
+    let constraints = source.constraints.clone();
+    status!("Copying medium '{}' to '{}'", source.name, new_name);
+
+    handle_add_medium(ctx, new_name, sides, max_duration, rpm)?;
+    for constraint in constraints {
+        ctx.add_or_replace_medium_constraint(new_name, constraint)?;
+    }
+    Ok(())
 }
 
 /// Handles adding a constraint to the context.
-/// Returns true if the constraint was added.
+/// Returns an error if the kind or its arguments are invalid.
 pub fn handle_add_constraint(
     ctx: &mut ProgramContext,
     kind: &String,
     args: &Vec<String>,
     weight: usize,
-) -> bool {
-    if let Some(kind) = parse_constraint_kind(kind, args) {
-        let constraint = AlbumConstraint {
-            kind,
-            weight: weight,
-        };
-        ctx.add_or_replace_constraint(constraint);
-        return true;
+) -> CliResult {
+    let kind = parse_constraint_kind(kind, args)?;
+    if let SerConstraintKind::StayCloseTo(reference) = &kind {
+        ctx.tracklist(reference)?;
     }
+    let constraint = SerConstraint {
+        kind,
+        weight: weight,
+        enabled: true,
+    };
+    ctx.add_or_replace_constraint(constraint);
 
-    false
+    Ok(())
 }
 
-/// Handles removing a constraint from the context by index.
-/// Returns true if the constraint was removed.
-pub fn handle_remove_constraint(ctx: &mut ProgramContext, index: &usize) -> bool {
-    let before_len = ctx.constraints.len();
+/// Handles adding a constraint to a medium, applied whenever that medium is proposed on.
+/// Returns an error if the medium doesn't exist or the kind or its arguments are invalid.
+pub fn handle_add_medium_constraint(
+    ctx: &mut ProgramContext,
+    medium: &str,
+    kind: &str,
+    args: &[String],
+    weight: usize,
+) -> CliResult {
+    let kind = parse_constraint_kind(kind, args)?;
+    if let SerConstraintKind::StayCloseTo(reference) = &kind {
+        ctx.tracklist(reference)?;
+    }
+    let constraint = SerConstraint {
+        kind,
+        weight,
+        enabled: true,
+    };
+    ctx.add_or_replace_medium_constraint(medium, constraint)
+}
 
-    let cc = ctx.constraints.clone();
-    let cc = cc.get(*index);
+/// Handles removing a constraint of a medium by its index in that medium's list.
+pub fn handle_remove_medium_constraint(
+    ctx: &mut ProgramContext,
+    medium: &str,
+    index: usize,
+) -> CliResult {
+    ctx.remove_medium_constraint(medium, index)
+}
 
-    if let Some(c) = cc {
-        ctx.constraints.remove(*index);
-        println!("Removed constraint at index {}", index);
-        println!("=== Constraint ===");
-        println!("{:?} (weight {})", c.kind, c.weight);
-        println!();
-    } else {
-        eprintln!("Index out of range");
-        return false;
-    }
+/// A constraint of a preset: kind, arguments and weight, as given to `add-constraint`.
+type PresetConstraint = (&'static str, &'static [&'static str], usize);
 
-    println!("{} constraints removed", before_len - ctx.constraints.len());
+/// Curated constraint sets by preset name.
+const PRESETS: &[(&str, &[PresetConstraint])] = &[
+    (
+        "vinyl-audiophile",
+        &[
+            ("longestnotcloser", &[], 3),
+            ("sidebalance", &["2:00"], 2),
+            ("fillsides", &["0.8"], 2),
+        ],
+    ),
+    (
+        "vinyl-basic",
+        &[("longestnotcloser", &[], 2), ("fillsides", &["0.6"], 1)],
+    ),
+];
 
-    true
+/// Well-known media: name, sides and typical max duration per side (in minutes).
+const STANDARD_MEDIA: &[(&str, usize, Duration)] = &[
+    ("7in-45", 2, 5.0),
+    ("10in-33", 2, 14.0),
+    ("12in-33", 2, 22.0),
+    ("12in-45", 2, 15.0),
+    ("C60", 2, 30.0),
+    ("C90", 2, 45.0),
+    ("CD", 1, 80.0),
+];
+
+/// Handles adding the standard media. Media with the same name already in the
+/// context are kept, unless `force` is set.
+pub fn handle_add_standard_media(ctx: &mut ProgramContext, force: bool) -> CliResult {
+    for (name, sides, max_duration) in STANDARD_MEDIA {
+        if !force
+            && ctx
+                .mediums
+                .iter()
+                .any(|m| m.name.eq_ignore_ascii_case(name))
+        {
+            status!(
+                "Skipped medium '{}', which already exists (use --force to replace it)",
+                name
+            );
+            continue;
+        }
+        ctx.add_or_replace_medium(name.to_string(), *sides, *max_duration, None);
+    }
+
+    Ok(())
 }
 
-/// Handles displaying the context or filtered parts of it.
-/// Now with prettytable output for tracklists, media, and constraints.
-pub fn handle_show(ctx: &ProgramContext, filter: &Option<String>) {
-    let filter = filter.as_ref().map(|s| s.to_lowercase());
+/// Handles adding every constraint of a named preset.
+/// Constraints of the same kind already in the context are replaced.
+pub fn handle_add_preset(ctx: &mut ProgramContext, name: &str) -> CliResult {
+    let Some((preset, constraints)) = PRESETS
+        .iter()
+        .find(|(preset, _)| preset.eq_ignore_ascii_case(name))
+    else {
+        let names: Vec<&str> = PRESETS.iter().map(|(preset, _)| *preset).collect();
+        return Err(CliError::User(format!(
+            "Unknown preset: {} (available: {})",
+            name,
+            names.join(", ")
+        )));
+    };
 
-    // Show tracklists
-    if filter.is_none() || filter.as_deref() == Some("tracklists") {
-        println!("{}", "Tracklists:".bold().cyan());
-        for tl in &ctx.tracklists {
-            println!("{}", format!("Tracklist: {}", tl.name).bold().yellow());
-            let tracks = &tl.tracks.0;
-            if tracks.is_empty() {
-                println!("  (empty)");
-                continue;
-            }
-            let mut table = Table::new();
-            table.set_format(*format::consts::FORMAT_NO_LINESEP_WITH_TITLE);
-            table.set_titles(Row::new(vec![
-                Cell::new("#").style_spec("bFc"),
-                Cell::new("Title").style_spec("bFc"),
-                Cell::new("Duration").style_spec("bFc"),
-            ]));
-            for (i, t) in tracks.iter().enumerate() {
-                table.add_row(Row::new(vec![
-                    Cell::new(&format!("{}", i + 1)),
-                    Cell::new(&t.title),
-                    Cell::new(&crate::utils::format_duration(t.duration)),
-                ]));
-            }
-            table.printstd();
-            println!();
-        }
+    status!("Applying preset '{}':", preset);
+    for (kind, args, weight) in constraints.iter() {
+        let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+        let constraint = SerConstraint {
+            kind: parse_constraint_kind(kind, &args)?,
+            weight: *weight,
+            enabled: true,
+        };
+        ctx.add_or_replace_constraint(constraint);
     }
 
-    // Show media
-    if filter.is_none()
-        || filter.as_deref() == Some("media")
-        || filter.as_deref() == Some("mediums")
-    {
-        println!("{}", "Media:".bold().cyan());
-        let mut table = Table::new();
-        table.set_format(*format::consts::FORMAT_NO_LINESEP_WITH_TITLE);
-        table.set_titles(Row::new(vec![
-            Cell::new("Name").style_spec("bFc"),
-            Cell::new("Sides").style_spec("bFc"),
-            Cell::new("Max Duration/Side").style_spec("bFc"),
-        ]));
-        for m in &ctx.mediums {
-            table.add_row(Row::new(vec![
-                Cell::new(&m.name),
-                Cell::new(&format!("{}", m.sides)),
-                Cell::new(&crate::utils::format_duration(m.max_duration_per_side)),
-            ]));
-        }
-        table.printstd();
-        println!();
+    Ok(())
+}
+
+/// Handles building a constraint interactively, then adding it like `add-constraint`.
+/// Titles of the given tracklist (or of every tracklist) are offered as choices.
+/// Returns a user error when stdin isn't a terminal.
+pub fn handle_add_constraint_wizard(
+    ctx: &mut ProgramContext,
+    tracklist: &Option<String>,
+) -> CliResult {
+    if !io::stdin().is_terminal() {
+        return Err(CliError::User(
+            "add-constraint-wizard needs an interactive terminal; use add-constraint instead"
+                .to_string(),
+        ));
     }
 
-    // Show constraints
-    if filter.is_none() || filter.as_deref() == Some("constraints") {
-        println!("{}", "Constraints:".bold().cyan());
-        let mut table = Table::new();
-        table.set_format(*format::consts::FORMAT_NO_LINESEP_WITH_TITLE);
-        table.set_titles(Row::new(vec![
-            Cell::new("Index").style_spec("bFc"),
-            Cell::new("Kind").style_spec("bFc"),
-            Cell::new("Args").style_spec("bFc"),
-            Cell::new("Weight").style_spec("bFc"),
-        ]));
-        for (i, c) in ctx.constraints.iter().enumerate() {
-            let (kind, args) = match &c.kind {
-                crate::context::SerConstraintKind::AtPosition(title, pos) => {
-                    ("AtPosition", format!("{} @ {}", title, pos))
-                }
-                crate::context::SerConstraintKind::Adjacent(a, b) => {
-                    ("Adjacent", format!("{}, {}", a, b))
-                }
-                crate::context::SerConstraintKind::OnSameSide(a, b) => {
-                    ("OnSameSide", format!("{}, {}", a, b))
-                }
-            };
-            table.add_row(Row::new(vec![
-                Cell::new(&format!("{}", i)),
-                Cell::new(kind),
-                Cell::new(&args),
-                Cell::new(&format!("{}", c.weight)),
-            ]));
+    let mut titles: Vec<String> = Vec::new();
+    let tracklists = match tracklist {
+        Some(name) => vec![ctx.tracklist(name)?],
+        None => ctx.tracklists.iter().collect(),
+    };
+    for tl in tracklists {
+        for track in &tl.tracks.0 {
+            if !titles.contains(&track.title) {
+                titles.push(track.title.clone());
+            }
         }
-        table.printstd();
-        println!();
     }
+
+    let answers = prompt_constraint(&titles, &mut io::stdin().lock(), &mut io::stdout())?;
+    handle_add_constraint(ctx, &answers.kind, &answers.args, answers.weight)
 }
 
-/// Handles proposing top scoring tracklist permutations for a tracklist & medium.
-/// Now with prettytable output.
-pub fn handle_propose(
+/// Handles setting or clearing the notes of a tracklist.
+pub fn handle_set_notes(
+    ctx: &mut ProgramContext,
+    tracklist: &str,
+    notes: &Option<String>,
+) -> CliResult {
+    ctx.set_tracklist_notes(tracklist, notes.clone())
+}
+
+/// Handles previewing a constraint without saving it.
+/// Prints the top proposal for the tracklist & medium with and without the constraint.
+pub fn handle_preview_constraint(
     ctx: &ProgramContext,
+    kind: &str,
+    args: &[String],
+    weight: usize,
     tracklist_name: &str,
     medium_name: &str,
-    count: &usize,
-    min_score: &Option<usize>,
-) {
-    // Find the tracklist by name
-    let ser_tl = ctx
-        .tracklists
-        .iter()
-        .find(|tl| tl.name.eq_ignore_ascii_case(tracklist_name));
-
-    if ser_tl.is_none() {
-        eprintln!("Tracklist '{}' not found", tracklist_name);
+) -> CliResult {
+    let kind = parse_constraint_kind(kind, args)?;
+    if let SerConstraintKind::StayCloseTo(reference) = &kind {
+        ctx.tracklist(reference)?;
+    }
+    let constraint = SerConstraint {
+        kind,
+        weight,
+        enabled: true,
+    };
+    let ser_tl = ctx.tracklist(tracklist_name)?;
+    ensure_has_tracks(ser_tl)?;
+    let ser_medium = ctx.medium(medium_name)?;
+    let tracklist = Tracklist::from(&ser_tl.tracks);
+    let medium = ser_medium.to_album_medium();
+    let gaps = Gaps::of(&ser_tl.tracks);
+
+    let without_constraint = ctx.constraints_on(ser_medium);
+    let mut with_constraint = without_constraint.clone();
+    match with_constraint
+        .iter_mut()
+        .find(|c| c.kind == constraint.kind)
+    {
+        Some(existing) => *existing = constraint,
+        None => with_constraint.push(constraint),
+    }
+
+    for (label, constraints) in [
+        ("Without the new constraint:", &without_constraint),
+        ("With the new constraint:", &with_constraint),
+    ] {
+        println!("{}", label.bold().cyan());
+        let scorer = Scorer::new(constraints, &medium, &ser_tl.tracks, &ctx.tracklists);
+        let candidates = TracklistPermutations::new(&tracklist.0);
+        let mut top = TopProposals::new(1);
+        score_permutations(candidates, &medium, &gaps, &scorer, |score, tl, _| {
+            if top.would_keep(score) {
+                top.push(score, tl);
+            }
+        });
+        match top.into_sorted_vec().into_iter().next() {
+            Some((score, tl)) => {
+                print_proposal(1, score, &tl, &medium, &gaps, SideLabels::default())
+            }
+            None => println!("  No permutation fits the medium."),
+        }
+    }
+
+    println!("Preview only: the constraint was not saved.");
+
+    Ok(())
+}
+
+/// Handles adding or removing tags on a track.
+pub fn handle_tag_track(
+    ctx: &mut ProgramContext,
+    tracklist: &str,
+    title: &str,
+    tags: &[String],
+    remove: bool,
+) -> CliResult {
+    ctx.tag_track(tracklist, title, tags, remove)
+}
+
+/// Track metadata given to `set-track-info`; `None` fields are left unchanged.
+pub struct TrackInfoUpdate {
+    pub energy: Option<u8>,
+    pub isrc: Option<String>,
+    pub continuous: Option<bool>,
+    /// Gap after the track, as typed (MM:SS).
+    pub gap: Option<String>,
+    /// Artist of the track; an empty name clears it.
+    pub artist: Option<String>,
+    /// Integrated loudness in LUFS.
+    pub loudness: Option<f64>,
+}
+
+/// Handles setting metadata of a track.
+/// Returns a user error when no field to set is given.
+pub fn handle_set_track_info(
+    ctx: &mut ProgramContext,
+    tracklist: &str,
+    title: &str,
+    update: &TrackInfoUpdate,
+) -> CliResult {
+    let TrackInfoUpdate {
+        energy,
+        isrc,
+        continuous,
+        gap,
+        artist,
+        loudness,
+    } = update;
+    if energy.is_none()
+        && isrc.is_none()
+        && continuous.is_none()
+        && gap.is_none()
+        && artist.is_none()
+        && loudness.is_none()
+    {
+        return Err(CliError::User(
+            "Nothing to set: pass --energy, --isrc, --continuous, --gap, --artist or --loudness"
+                .to_string(),
+        ));
+    }
+    let gap = gap
+        .as_deref()
+        .map(|g| parse_duration(g).ok_or_else(|| CliError::User(format!("Invalid gap: {}", g))))
+        .transpose()?;
+
+    if let Some(energy) = *energy {
+        ctx.set_track_energy(tracklist, title, energy)?;
+    }
+    if let Some(isrc) = isrc {
+        ctx.set_track_isrc(tracklist, title, isrc)?;
+    }
+    if let Some(continuous) = *continuous {
+        ctx.set_track_continuous(tracklist, title, continuous)?;
+    }
+    if let Some(gap) = gap {
+        ctx.set_track_gap(tracklist, title, gap)?;
+    }
+    if let Some(artist) = artist {
+        ctx.set_track_artist(tracklist, title, artist)?;
+    }
+    if let Some(lufs) = *loudness {
+        ctx.set_track_loudness(tracklist, title, lufs)?;
+    }
+
+    Ok(())
+}
+
+/// Handles setting or clearing the catalog number of a tracklist.
+pub fn handle_set_catalog(
+    ctx: &mut ProgramContext,
+    tracklist: &str,
+    catalog: &Option<String>,
+) -> CliResult {
+    ctx.set_tracklist_catalog(tracklist, catalog.clone())
+}
+
+/// Handles adding a signed offset to every track duration of a tracklist.
+pub fn handle_offset_durations(
+    ctx: &mut ProgramContext,
+    tracklist: &str,
+    delta: &str,
+) -> CliResult {
+    let delta = parse_signed_duration(delta)
+        .ok_or_else(|| CliError::User(format!("Invalid duration offset: {}", delta)))?;
+    ctx.offset_durations(tracklist, delta)
+}
+
+/// Handles renaming a track across every tracklist and constraint.
+/// Returns an error if the new title is empty or nothing was renamed.
+pub fn handle_rename_track(ctx: &mut ProgramContext, old: &str, new: &str) -> CliResult {
+    if new.trim().is_empty() {
+        return Err(CliError::User("The new title can't be empty".to_string()));
+    }
+
+    let (tracks, args) = ctx.rename_track(old, new);
+    if tracks + args == 0 {
+        return Err(CliError::User(format!(
+            "No track or constraint refers to '{}'",
+            old
+        )));
+    }
+
+    status!(
+        "Renamed '{}' to '{}': {} tracks, {} constraint arguments",
+        old,
+        new,
+        tracks,
+        args
+    );
+    Ok(())
+}
+
+/// Handles normalizing the track titles of a tracklist (whitespace, and optionally
+/// case), rewriting the constraint arguments that refer to them. Reports each change
+/// and warns about titles that became duplicates.
+pub fn handle_normalize_titles(
+    ctx: &mut ProgramContext,
+    tracklist: &str,
+    mode: TitleNormalization,
+) -> CliResult {
+    let (renamed, args) = ctx.normalize_titles(tracklist, |title| mode.apply(title))?;
+
+    for (old, new) in &renamed {
+        status!("'{}' -> '{}'", old, new);
+    }
+    status!(
+        "Normalized {} titles and {} constraint arguments",
+        renamed.len(),
+        args
+    );
+
+    let tl = ctx.tracklist(tracklist)?;
+    let mut seen = HashSet::new();
+    for track in &tl.tracks.0 {
+        if !seen.insert(track.title.as_str()) {
+            warn!(
+                "'{}' appears more than once in tracklist '{}'; see the dedup command",
+                track.title, tl.name
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Handles sorting the tracks of a tracklist by title or duration, then prints the
+/// new order.
+pub fn handle_sort(ctx: &mut ProgramContext, tracklist: &str, by: TrackOrder) -> CliResult {
+    ctx.sort_tracklist(tracklist, by)?;
+
+    let tl = ctx.tracklist(tracklist)?;
+    status!("Sorted tracklist '{}':", tl.name);
+    for (i, track) in tl.tracks.0.iter().enumerate() {
+        status!(
+            "{:>3}. {} ({})",
+            i + 1,
+            track.title,
+            format_duration(track.duration)
+        );
+    }
+
+    Ok(())
+}
+
+/// Handles swapping the positions of two tracks of a tracklist by title.
+/// Returns an error if either track is missing.
+pub fn handle_swap_tracks(
+    ctx: &mut ProgramContext,
+    tracklist: &str,
+    title1: &str,
+    title2: &str,
+) -> CliResult {
+    ctx.swap_tracks(tracklist, title1, title2)
+}
+
+/// Handles printing the differences between two tracklists, matching tracks by title
+/// (case-insensitive): tracks only in `b` (added), only in `a` (removed), common tracks
+/// whose order relative to the other common tracks changed (moved), and common tracks
+/// whose duration changed.
+pub fn handle_diff_tracklists(ctx: &ProgramContext, a_name: &str, b_name: &str) -> CliResult {
+    let a = ctx.tracklist(a_name)?;
+    let b = ctx.tracklist(b_name)?;
+    let position_in = |tl: &NamedSerTracklist, title: &str| {
+        tl.tracks
+            .0
+            .iter()
+            .position(|t| t.title.eq_ignore_ascii_case(title))
+    };
+
+    let removed: Vec<&SerTrack> = a
+        .tracks
+        .0
+        .iter()
+        .filter(|t| position_in(b, &t.title).is_none())
+        .collect();
+    let added: Vec<&SerTrack> = b
+        .tracks
+        .0
+        .iter()
+        .filter(|t| position_in(a, &t.title).is_none())
+        .collect();
+    // Common tracks in the order of each tracklist, with their positions
+    let common_a: Vec<(usize, &SerTrack)> = a
+        .tracks
+        .0
+        .iter()
+        .enumerate()
+        .filter(|(_, t)| position_in(b, &t.title).is_some())
+        .collect();
+    let common_b: Vec<(usize, &SerTrack)> = b
+        .tracks
+        .0
+        .iter()
+        .enumerate()
+        .filter(|(_, t)| position_in(a, &t.title).is_some())
+        .collect();
+
+    println!(
+        "{}",
+        format!("Changes from '{}' to '{}':", a.name, b.name)
+            .bold()
+            .cyan()
+    );
+    let mut changes = 0;
+    for t in &added {
+        println!(
+            "  {} {} ({})",
+            "+".green(),
+            t.title,
+            format_duration(t.duration)
+        );
+        changes += 1;
+    }
+    for t in &removed {
+        println!(
+            "  {} {} ({})",
+            "-".red(),
+            t.title,
+            format_duration(t.duration)
+        );
+        changes += 1;
+    }
+    let kept_in_order = longest_common_order(&common_a, &common_b);
+    for (rank, (pos_a, track)) in common_a.iter().enumerate() {
+        let Some(&(pos_b, track_b)) = common_b
+            .iter()
+            .find(|(_, t)| t.title.eq_ignore_ascii_case(&track.title))
+        else {
+            continue;
+        };
+        if !kept_in_order[rank] {
+            println!(
+                "  {} {} moved from #{} to #{}",
+                "~".yellow(),
+                track.title,
+                pos_a + 1,
+                pos_b + 1
+            );
+            changes += 1;
+        }
+        if format_duration(track.duration) != format_duration(track_b.duration) {
+            println!(
+                "  {} {} duration {} -> {}",
+                "~".yellow(),
+                track.title,
+                format_duration(track.duration),
+                format_duration(track_b.duration)
+            );
+            changes += 1;
+        }
+    }
+
+    if changes == 0 {
+        println!("  (no differences)");
+    }
+
+    Ok(())
+}
+
+/// Returns, for each track of `a`, whether it belongs to a longest sequence of tracks
+/// appearing in the same order in `b` (titles compared case-insensitively). The
+/// other tracks are the fewest that moved.
+fn longest_common_order(a: &[(usize, &SerTrack)], b: &[(usize, &SerTrack)]) -> Vec<bool> {
+    let same = |i: usize, j: usize| a[i].1.title.eq_ignore_ascii_case(&b[j].1.title);
+
+    // lengths[i][j]: longest common subsequence of a[i..] and b[j..]
+    let mut lengths = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lengths[i][j] = if same(i, j) {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+
+    let mut kept = vec![false; a.len()];
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() && j < b.len() {
+        if same(i, j) {
+            kept[i] = true;
+            i += 1;
+            j += 1;
+        } else if lengths[i + 1][j] >= lengths[i][j + 1] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    kept
+}
+
+/// Handles removing duplicate tracks from a tracklist.
+pub fn handle_dedup(ctx: &mut ProgramContext, tracklist: &str, by_title: bool) -> CliResult {
+    ctx.dedup_tracklist(tracklist, by_title)?;
+    Ok(())
+}
+
+/// Handles reporting the tracks of every tracklist whose duration isn't positive.
+/// With `fix`, removes them once confirmed (or right away with `yes`); confirmation
+/// needs an interactive terminal. Returns whether the context changed.
+pub fn handle_check_durations(ctx: &mut ProgramContext, fix: bool, yes: bool) -> CliResult<bool> {
+    let invalid = ctx.non_positive_durations();
+    if invalid.is_empty() {
+        println!("{}", "Every track has a positive duration.".green());
+        return Ok(false);
+    }
+
+    println!(
+        "{}",
+        format!("{} tracks without a positive duration:", invalid.len())
+            .bold()
+            .red()
+    );
+    let mut table = Table::new();
+    table.set_format(*format::consts::FORMAT_NO_LINESEP_WITH_TITLE);
+    table.set_titles(Row::new(vec![
+        Cell::new("Tracklist").style_spec("bFc"),
+        Cell::new("#").style_spec("bFc"),
+        Cell::new("Title").style_spec("bFc"),
+        Cell::new("Duration").style_spec("bFc"),
+    ]));
+    for (tracklist, position, track) in &invalid {
+        table.add_row(Row::new(vec![
+            Cell::new(tracklist),
+            Cell::new(&(position + 1).to_string()),
+            Cell::new(&track.title),
+            Cell::new(&format_duration(track.duration)),
+        ]));
+    }
+    table.printstd();
+
+    if !fix {
+        return Ok(false);
+    }
+    if !yes {
+        if !io::stdin().is_terminal() {
+            return Err(CliError::User(
+                "check-durations --fix needs an interactive terminal to confirm; pass --yes"
+                    .to_string(),
+            ));
+        }
+        let prompt = format!("Remove these {} tracks? [y/N] ", invalid.len());
+        if !confirm(&prompt)? {
+            status!("Nothing removed");
+            return Ok(false);
+        }
+    }
+
+    let removed = ctx.remove_non_positive_durations();
+    status!("{} tracks removed", removed);
+    Ok(removed > 0)
+}
+
+/// Asks a yes/no question on the terminal; anything but "y" or "yes" is a no.
+fn confirm(prompt: &str) -> CliResult<bool> {
+    print!("{}", prompt);
+    io::stdout()
+        .flush()
+        .map_err(|e| CliError::Io(format!("Failed to write prompt: {}", e)))?;
+
+    let mut answer = String::new();
+    io::stdin()
+        .read_line(&mut answer)
+        .map_err(|e| CliError::Io(format!("Failed to read input: {}", e)))?;
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+/// Handles removing a constraint from the context by index.
+/// Returns an error if the index is out of range.
+/// With `dry_run`, only prints the constraint that would be removed.
+pub fn handle_remove_constraint(
+    ctx: &mut ProgramContext,
+    index: &usize,
+    dry_run: bool,
+) -> CliResult {
+    let before_len = ctx.constraints.len();
+
+    let cc = ctx.constraints.clone();
+    let cc = cc.get(*index);
+
+    if let Some(c) = cc
+        && dry_run
+    {
+        let (kind, args) = describe_constraint(&c.kind);
+        println!(
+            "Would remove constraint {}: {} {} (weight {})",
+            index, kind, args, c.weight
+        );
+        return Ok(());
+    }
+
+    if let Some(c) = cc {
+        ctx.constraints.remove(*index);
+        status!("Removed constraint at index {}", index);
+        status!("=== Constraint ===");
+        status!("{:?} (weight {})", c.kind, c.weight);
+        status!();
+    } else {
+        return Err(CliError::User(format!(
+            "Index {} out of range ({} constraints)",
+            index,
+            ctx.constraints.len()
+        )));
+    }
+
+    status!("{} constraints removed", before_len - ctx.constraints.len());
+
+    Ok(())
+}
+
+/// Handles multiplying the weights of the constraints, medium constraints included, or
+/// only those of one kind (an `add-constraint` keyword), by a factor. Weights are
+/// rounded to the nearest integer, with a minimum of 1. Returns an error if the
+/// factor isn't positive.
+pub fn handle_scale_weights(
+    ctx: &mut ProgramContext,
+    factor: f64,
+    kind: &Option<String>,
+) -> CliResult {
+    if !(factor > 0.0 && factor.is_finite()) {
+        return Err(CliError::User(format!(
+            "Invalid factor: {} (expected a positive number)",
+            factor
+        )));
+    }
+
+    // The context's constraints, then those of each medium
+    let labelled = ctx
+        .constraints
+        .iter_mut()
+        .enumerate()
+        .map(|(i, c)| (format!("Constraint {}", i), c))
+        .chain(ctx.mediums.iter_mut().flat_map(|m| {
+            let name = m.name.clone();
+            m.constraints
+                .iter_mut()
+                .enumerate()
+                .map(move |(j, c)| (format!("Constraint {} of medium '{}'", j, name), c))
+        }));
+
+    let mut scaled = 0;
+    for (label, c) in labelled {
+        if kind
+            .as_deref()
+            .is_some_and(|k| !k.eq_ignore_ascii_case(constraint_keyword(&c.kind)))
+        {
+            continue;
+        }
+
+        let weight = ((c.weight as f64 * factor).round() as usize).max(1);
+        if weight != c.weight {
+            status!("{} {:?}: weight {} -> {}", label, c.kind, c.weight, weight);
+            c.weight = weight;
+        }
+        scaled += 1;
+    }
+
+    match kind {
+        Some(kind) if scaled == 0 => {
+            warn!("No constraint of kind '{}'", kind);
+        }
+        _ => status!("{} constraint weights scaled by {}", scaled, factor),
+    }
+
+    Ok(())
+}
+
+/// Handles enabling or disabling a constraint by index.
+/// Disabled constraints stay in the context but are ignored when scoring.
+/// Returns an error if the index is out of range.
+pub fn handle_toggle_constraint(ctx: &mut ProgramContext, index: usize) -> CliResult {
+    let count = ctx.constraints.len();
+    let Some(c) = ctx.constraints.get_mut(index) else {
+        return Err(CliError::User(format!(
+            "Index {} out of range ({} constraints)",
+            index, count
+        )));
+    };
+
+    c.enabled = !c.enabled;
+    status!(
+        "{} constraint at index {}: {:?} (weight {})",
+        if c.enabled { "Enabled" } else { "Disabled" },
+        index,
+        c.kind,
+        c.weight
+    );
+
+    Ok(())
+}
+
+/// Handles writing all constraints to a file for sharing or version control.
+pub fn handle_export_constraints(ctx: &ProgramContext, path: &Path) -> CliResult {
+    ctx.export_constraints(path)
+}
+
+/// Handles adding the constraints of a file written by `export-constraints`.
+/// Constraints of a kind already in the context replace it.
+pub fn handle_import_constraints(ctx: &mut ProgramContext, path: &Path) -> CliResult {
+    let count = ctx.import_constraints(path)?;
+    status!("Imported {} constraints from {:?}", count, path);
+    Ok(())
+}
+
+/// Handles displaying the context or filtered parts of it.
+/// Now with prettytable output for tracklists, media, and constraints.
+pub fn handle_show(ctx: &ProgramContext, filter: &Option<String>) {
+    let filter = filter.as_ref().map(|s| s.to_lowercase());
+
+    // Show tracklists
+    if filter.is_none() || filter.as_deref() == Some("tracklists") {
+        println!("{}", "Tracklists:".bold().cyan());
+        for tl in &ctx.tracklists {
+            println!("{}", format!("Tracklist: {}", tl.name).bold().yellow());
+            if let Some(notes) = &tl.notes {
+                println!("{} {}", "Notes:".bold(), notes);
+            }
+            if let Some(catalog) = &tl.catalog {
+                println!("{} {}", "Catalog:".bold(), catalog);
+            }
+            print_track_table(&tl.tracks);
+        }
+    }
+
+    // Show media
+    if filter.is_none()
+        || filter.as_deref() == Some("media")
+        || filter.as_deref() == Some("mediums")
+    {
+        println!("{}", "Media:".bold().cyan());
+        let mut table = Table::new();
+        table.set_format(*format::consts::FORMAT_NO_LINESEP_WITH_TITLE);
+        table.set_titles(Row::new(vec![
+            Cell::new("Name").style_spec("bFc"),
+            Cell::new("Sides").style_spec("bFc"),
+            Cell::new("Max Duration/Side").style_spec("bFc"),
+            Cell::new("Constraints").style_spec("bFc"),
+        ]));
+        for m in &ctx.mediums {
+            table.add_row(Row::new(vec![
+                Cell::new(&m.name),
+                Cell::new(&format!("{}", m.sides)),
+                Cell::new(&match m.rpm {
+                    Some(rpm) => {
+                        format!("{} ({} RPM)", format_duration(m.max_duration_per_side), rpm)
+                    }
+                    None => format_duration(m.max_duration_per_side),
+                }),
+                Cell::new(&m.constraints.len().to_string()),
+            ]));
+        }
+        table.printstd();
+        println!();
+
+        for m in ctx.mediums.iter().filter(|m| !m.constraints.is_empty()) {
+            println!("{}", format!("Constraints of medium '{}':", m.name).bold());
+            print_constraint_table(m.constraints.iter().enumerate());
+        }
+    }
+
+    // Show constraints
+    if filter.is_none() || filter.as_deref() == Some("constraints") {
+        println!("{}", "Constraints:".bold().cyan());
+        print_constraint_table(ctx.constraints.iter().enumerate());
+    }
+}
+
+/// Handles listing the constraints that apply to a tracklist: those whose referenced
+/// track titles all exist in it. Constraints referring to no track (e.g. `fillsides`)
+/// apply to every tracklist; the others are counted as dangling.
+/// Medium constraints are listed under their medium.
+pub fn handle_constraints_for(ctx: &ProgramContext, tracklist_name: &str) -> CliResult {
+    let tl = ctx.tracklist(tracklist_name)?;
+    let has_title = |arg: &str| {
+        tl.tracks
+            .0
+            .iter()
+            .any(|t| arg_matches(arg, &t.title, t.duration))
+    };
+
+    let applies = |c: &SerConstraint| c.kind.titles().into_iter().all(has_title);
+    let mut dangling = 0;
+
+    let (applicable, missing): (Vec<_>, Vec<_>) = ctx
+        .constraints
+        .iter()
+        .enumerate()
+        .partition(|(_, c)| applies(c));
+    dangling += missing.len();
+
+    println!(
+        "{}",
+        format!("Constraints for tracklist '{}':", tl.name)
+            .bold()
+            .cyan()
+    );
+    if applicable.is_empty() {
+        println!("  (none)");
+    } else {
+        print_constraint_table(applicable.into_iter());
+    }
+
+    for m in ctx.mediums.iter().filter(|m| !m.constraints.is_empty()) {
+        let (applicable, missing): (Vec<_>, Vec<_>) = m
+            .constraints
+            .iter()
+            .enumerate()
+            .partition(|(_, c)| applies(c));
+        dangling += missing.len();
+        if !applicable.is_empty() {
+            println!("{}", format!("Constraints of medium '{}':", m.name).bold());
+            print_constraint_table(applicable.into_iter());
+        }
+    }
+
+    if dangling > 0 {
+        println!(
+            "{} constraints refer to tracks missing from this tracklist.",
+            dangling
+        );
+    }
+
+    Ok(())
+}
+
+/// Handles describing a constraint and, for each medium of the context, whether its
+/// side logic means anything there: e.g. `onsameside` is always satisfied on a
+/// single side. With a medium, the index refers to that medium's constraints, which
+/// only apply there. Returns an error if the index is out of range.
+pub fn handle_constraint_info(
+    ctx: &ProgramContext,
+    index: usize,
+    medium: Option<&str>,
+) -> CliResult {
+    let (c, mediums, title) = match medium {
+        Some(name) => {
+            let m = ctx.medium(name)?;
+            let c = m.constraints.get(index).ok_or_else(|| {
+                CliError::User(format!(
+                    "Index {} out of range ({} constraints on medium '{}')",
+                    index,
+                    m.constraints.len(),
+                    m.name
+                ))
+            })?;
+            (
+                c,
+                std::slice::from_ref(m),
+                format!("Constraint {} of medium '{}'", index, m.name),
+            )
+        }
+        None => {
+            let c = ctx.constraints.get(index).ok_or_else(|| {
+                CliError::User(format!(
+                    "Index {} out of range ({} constraints)",
+                    index,
+                    ctx.constraints.len()
+                ))
+            })?;
+            (c, &ctx.mediums[..], format!("Constraint {}", index))
+        }
+    };
+
+    let (kind, args) = describe_constraint(&c.kind);
+    println!("{}", format!("{}: {} {}", title, kind, args).bold().cyan());
+    println!(
+        "  Weight {}{}",
+        c.weight,
+        if c.enabled { "" } else { " (disabled)" }
+    );
+
+    if !is_side_based(&c.kind) {
+        println!("  Doesn't depend on sides; meaningful on every medium.");
+        return Ok(());
+    }
+
+    println!("  Depends on the side layout:");
+    if mediums.is_empty() {
+        println!("    (no media)");
+    }
+    for medium in mediums {
+        let sides = format!(
+            "{} side{}",
+            medium.sides,
+            if medium.sides == 1 { "" } else { "s" }
+        );
+        match side_logic_noop(&c.kind, medium.sides) {
+            Some(reason) => println!(
+                "    {} ({}): {} {}",
+                medium.name,
+                sides,
+                "no-op,".yellow(),
+                reason
+            ),
+            None => println!("    {} ({}): meaningful", medium.name, sides),
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns whether a constraint kind looks at how tracks are split into sides.
+fn is_side_based(kind: &SerConstraintKind) -> bool {
+    !matches!(
+        kind,
+        SerConstraintKind::AtPosition(..)
+            | SerConstraintKind::NearPosition(..)
+            | SerConstraintKind::AtAnyPosition(..)
+            | SerConstraintKind::Adjacent(..)
+            | SerConstraintKind::Bookend(..)
+            | SerConstraintKind::SpreadTag(..)
+            | SerConstraintKind::StartsBefore(..)
+            | SerConstraintKind::StayCloseTo(..)
+            | SerConstraintKind::SmoothLoudness(..)
+            | SerConstraintKind::AlternatePacing(..)
+    )
+}
+
+/// Returns why a side-based constraint kind can't tell permutations apart on a
+/// medium with the given number of sides, if it can't.
+fn side_logic_noop(kind: &SerConstraintKind, sides: usize) -> Option<String> {
+    match kind {
+        SerConstraintKind::OnSameSide(..)
+        | SerConstraintKind::PreferSameSide(..)
+        | SerConstraintKind::MaxSideBreaksBetween(..)
+        | SerConstraintKind::MaxHardCuts(..)
+        | SerConstraintKind::SideBalance(..)
+            if sides == 1 =>
+        {
+            Some("always satisfied with a single side".to_string())
+        }
+        SerConstraintKind::FirstBreakAfter(..) if sides == 1 => {
+            Some("there is no side break, only the total runtime counts".to_string())
+        }
+        SerConstraintKind::DiscBalance(..) if sides <= SIDES_PER_DISC => {
+            Some("always satisfied with a single disc".to_string())
+        }
+        SerConstraintKind::SideSortedByDuration(side, _) if *side >= sides => {
+            Some(format!("always satisfied, the medium has no side {}", side))
+        }
+        SerConstraintKind::SideContains(side, ..) if *side >= sides => {
+            Some(format!("never satisfied, the medium has no side {}", side))
+        }
+        _ => None,
+    }
+}
+
+/// Handles listing the tracks of a tracklist that no enabled constraint, of the context
+/// or of any medium, refers to by title, i.e. where the optimizer has total freedom.
+/// Constraints referring to no track (e.g. `fillsides`) still affect every track and
+/// aren't counted.
+pub fn handle_unconstrained(ctx: &ProgramContext, tracklist_name: &str) -> CliResult {
+    let tl = ctx.tracklist(tracklist_name)?;
+    let referenced: Vec<&str> = ctx
+        .constraints
+        .iter()
+        .chain(ctx.mediums.iter().flat_map(|m| &m.constraints))
+        .filter(|c| c.enabled)
+        .flat_map(|c| c.kind.titles())
+        .collect();
+    let unconstrained: Vec<&SerTrack> = tl
+        .tracks
+        .0
+        .iter()
+        .filter(|t| {
+            !referenced
+                .iter()
+                .any(|arg| arg_matches(arg, &t.title, t.duration))
+        })
+        .collect();
+
+    println!(
+        "{}",
+        format!(
+            "Tracks of '{}' referenced by no constraint ({} of {}):",
+            tl.name,
+            unconstrained.len(),
+            tl.tracks.0.len()
+        )
+        .bold()
+        .cyan()
+    );
+    if unconstrained.is_empty() {
+        println!("  (none)");
+    }
+    for t in unconstrained {
+        println!("  {} ({})", t.title, format_duration(t.duration));
+    }
+
+    Ok(())
+}
+
+/// Handles listing the pairs of enabled constraints that obviously contradict each other.
+/// Medium constraints are checked against each other and the context's constraints;
+/// pairs of context constraints are listed once, not for every medium.
+pub fn handle_conflicts(ctx: &ProgramContext) {
+    let shared = ctx.constraints.len();
+    let mut conflicts: Vec<(Option<&SerMedium>, Conflict)> = find_conflicts(&ctx.constraints)
+        .into_iter()
+        .map(|conflict| (None, conflict))
+        .collect();
+    for medium in ctx.mediums.iter().filter(|m| !m.constraints.is_empty()) {
+        conflicts.extend(
+            find_conflicts(&ctx.constraints_on(medium))
+                .into_iter()
+                .filter(|conflict| conflict.second >= shared)
+                .map(|conflict| (Some(medium), conflict)),
+        );
+    }
+    if conflicts.is_empty() {
+        println!("{}", "No conflicting constraints found.".green());
+        return;
+    }
+
+    println!(
+        "{}",
+        format!("{} conflicting constraint pairs:", conflicts.len())
+            .bold()
+            .red()
+    );
+    let mut table = Table::new();
+    table.set_format(*format::consts::FORMAT_NO_LINESEP_WITH_TITLE);
+    table.set_titles(Row::new(vec![
+        Cell::new("First").style_spec("bFc"),
+        Cell::new("Second").style_spec("bFc"),
+        Cell::new("Conflict").style_spec("bFc"),
+    ]));
+    for (medium, conflict) in &conflicts {
+        let describe = |index: usize| match (medium, index.checked_sub(shared)) {
+            (Some(m), Some(j)) => {
+                let (kind, args) = describe_constraint(&m.constraints[j].kind);
+                format!("medium '{}' #{} {} ({})", m.name, j, kind, args)
+            }
+            _ => {
+                let (kind, args) = describe_constraint(&ctx.constraints[index].kind);
+                format!("#{} {} ({})", index, kind, args)
+            }
+        };
+        table.add_row(Row::new(vec![
+            Cell::new(&describe(conflict.first)),
+            Cell::new(&describe(conflict.second)),
+            Cell::new(&conflict.reason),
+        ]));
+    }
+    table.printstd();
+}
+
+/// Returns the display name of a constraint kind and a summary of its arguments.
+fn describe_constraint(kind: &SerConstraintKind) -> (&'static str, String) {
+    match kind {
+        SerConstraintKind::AtPosition(title, pos) => ("AtPosition", format!("{} @ {}", title, pos)),
+        SerConstraintKind::NearPosition(title, pos) => {
+            ("NearPosition", format!("{} @ ~{}", title, pos))
+        }
+        SerConstraintKind::Adjacent(a, b) => ("Adjacent", format!("{}, {}", a, b)),
+        SerConstraintKind::Bookend(opener, closer) => {
+            ("Bookend", format!("{} ... {}", opener, closer))
+        }
+        SerConstraintKind::OnSameSide(a, b) => ("OnSameSide", format!("{}, {}", a, b)),
+        SerConstraintKind::SideSortedByDuration(side, ascending) => (
+            "SideSortedByDuration",
+            format!(
+                "side {} {}",
+                side,
+                if *ascending {
+                    "ascending"
+                } else {
+                    "descending"
+                }
+            ),
+        ),
+        SerConstraintKind::MaxSideBreaksBetween(a, b) => {
+            ("MaxSideBreaksBetween", format!("{}, {}", a, b))
+        }
+        SerConstraintKind::PreferSameSide(a, b) => ("PreferSameSide", format!("{}, {}", a, b)),
+        SerConstraintKind::SideContains(side, titles, exact) => (
+            "SideContains",
+            format!(
+                "side {} {} {}",
+                side,
+                if *exact { "exactly" } else { "at least" },
+                titles.join(", ")
+            ),
+        ),
+        SerConstraintKind::SpreadTag(tag, min_gap) => {
+            ("SpreadTag", format!("{} (gap >= {})", tag, min_gap))
+        }
+        SerConstraintKind::LongestNotSideCloser => ("LongestNotSideCloser", String::new()),
+        SerConstraintKind::ShortToInner(scale) => ("ShortToInner", format!("scale x{}", scale)),
+        SerConstraintKind::SmoothLoudness(max_jump) => {
+            ("SmoothLoudness", format!("jumps <= {} LU", max_jump))
+        }
+        SerConstraintKind::FirstBreakAfter(minutes) => (
+            "FirstBreakAfter",
+            format!("first side >= {}", format_duration(*minutes)),
+        ),
+        SerConstraintKind::AlternatePacing(threshold) => (
+            "AlternatePacing",
+            format!("short/long around {}", format_duration(*threshold)),
+        ),
+        SerConstraintKind::MaxArtistPerSide(max) => (
+            "MaxArtistPerSide",
+            format!("<= {} per artist and side", max),
+        ),
+        SerConstraintKind::SideBalance(tolerance) => (
+            "SideBalance",
+            format!("sides within {}", format_duration(*tolerance)),
+        ),
+        SerConstraintKind::DiscBalance(tolerance) => (
+            "DiscBalance",
+            format!("discs within {}", format_duration(*tolerance)),
+        ),
+        SerConstraintKind::StartsBefore(title, minutes) => (
+            "StartsBefore",
+            format!("{} before {}", title, format_duration(*minutes)),
+        ),
+        SerConstraintKind::StayCloseTo(reference) => ("StayCloseTo", reference.clone()),
+        SerConstraintKind::StrongSideOpeners(min_energy) => {
+            ("StrongSideOpeners", format!("energy >= {}", min_energy))
+        }
+        SerConstraintKind::MaxHardCuts(max_cuts) => ("MaxHardCuts", format!("<= {}", max_cuts)),
+        SerConstraintKind::FillSides(target) => {
+            ("FillSides", format!("{:.0}% of each side", target * 100.0))
+        }
+        SerConstraintKind::SideNearFull(target, tolerance) => (
+            "SideNearFull",
+            format!(
+                "{:.0}% ± {:.0}% of each side",
+                target * 100.0,
+                tolerance * 100.0
+            ),
+        ),
+        SerConstraintKind::AtAnyPosition(title, positions) => {
+            let positions: Vec<String> = positions.iter().map(|p| p.to_string()).collect();
+            (
+                "AtAnyPosition",
+                format!("{} @ {}", title, positions.join(" or ")),
+            )
+        }
+    }
+}
+
+/// Prints a table of constraints with their index in the context.
+fn print_constraint_table<'c>(constraints: impl Iterator<Item = (usize, &'c SerConstraint)>) {
+    let mut table = Table::new();
+    table.set_format(*format::consts::FORMAT_NO_LINESEP_WITH_TITLE);
+    table.set_titles(Row::new(vec![
+        Cell::new("Index").style_spec("bFc"),
+        Cell::new("Kind").style_spec("bFc"),
+        Cell::new("Args").style_spec("bFc"),
+        Cell::new("Weight").style_spec("bFc"),
+        Cell::new("Enabled").style_spec("bFc"),
+    ]));
+    for (i, c) in constraints {
+        let (kind, args) = describe_constraint(&c.kind);
+        table.add_row(Row::new(vec![
+            Cell::new(&format!("{}", i)),
+            Cell::new(kind),
+            Cell::new(&args),
+            Cell::new(&format!("{}", c.weight)),
+            Cell::new(if c.enabled { "yes" } else { "no" }),
+        ]));
+    }
+    table.printstd();
+    println!();
+}
+
+/// Prints the tracks of a tracklist as a table, or "(empty)".
+fn print_track_table(tracks: &SerTracklist) {
+    let tracks = &tracks.0;
+    if tracks.is_empty() {
+        println!("  (empty)");
+        return;
+    }
+    let mut table = Table::new();
+    table.set_format(*format::consts::FORMAT_NO_LINESEP_WITH_TITLE);
+    table.set_titles(Row::new(vec![
+        Cell::new("#").style_spec("bFc"),
+        Cell::new("Title").style_spec("bFc"),
+        Cell::new("Artist").style_spec("bFc"),
+        Cell::new("Duration").style_spec("bFc"),
+        Cell::new("Tags").style_spec("bFc"),
+        Cell::new("Energy").style_spec("bFc"),
+        Cell::new("ISRC").style_spec("bFc"),
+        Cell::new("Continuous").style_spec("bFc"),
+        Cell::new("Gap").style_spec("bFc"),
+        Cell::new("LUFS").style_spec("bFc"),
+    ]));
+    for (i, t) in tracks.iter().enumerate() {
+        table.add_row(Row::new(vec![
+            Cell::new(&format!("{}", i + 1)),
+            Cell::new(&t.title),
+            Cell::new(t.artist.as_deref().unwrap_or_default()),
+            Cell::new(&crate::utils::format_duration(t.duration)),
+            Cell::new(&t.tags.join(", ")),
+            Cell::new(&t.energy.map(|e| e.to_string()).unwrap_or_default()),
+            Cell::new(t.isrc.as_deref().unwrap_or_default()),
+            Cell::new(if t.continuous { "yes" } else { "" }),
+            Cell::new(&t.gap_after.map(format_duration).unwrap_or_default()),
+            Cell::new(&t.loudness.map(|l| l.to_string()).unwrap_or_default()),
+        ]));
+    }
+    table.printstd();
+    println!();
+}
+
+/// Handles showing a single tracklist with its total runtime.
+/// With a medium, also shows how the stored order splits into sides on it.
+pub fn handle_show_tracklist(
+    ctx: &ProgramContext,
+    name: &str,
+    medium_name: &Option<String>,
+) -> CliResult {
+    let tl = ctx.tracklist(name)?;
+    let medium = medium_name
+        .as_deref()
+        .map(|m| ctx.medium(m).map(|m| m.to_album_medium()))
+        .transpose()?;
+
+    println!("{}", format!("Tracklist: {}", tl.name).bold().yellow());
+    if let Some(notes) = &tl.notes {
+        println!("{} {}", "Notes:".bold(), notes);
+    }
+    if let Some(catalog) = &tl.catalog {
+        println!("{} {}", "Catalog:".bold(), catalog);
+    }
+    print_track_table(&tl.tracks);
+
+    let total: Duration = tl.tracks.0.iter().map(|t| t.duration).sum();
+    let gap_total: Duration = tl.tracks.0.iter().filter_map(|t| t.gap_after).sum();
+    println!(
+        "{} {} ({} tracks)",
+        "Total runtime:".bold(),
+        format_duration(total),
+        tl.tracks.0.len()
+    );
+    if gap_total > 0.0 {
+        println!("{} {}", "Gaps:".bold(), format_duration(gap_total));
+    }
+
+    if let Some(medium) = medium {
+        let tracklist = Tracklist::from(&tl.tracks);
+        let gaps = Gaps::of(&tl.tracks);
+        println!();
+        println!(
+            "{}",
+            format!("Stored order on medium '{}':", medium.name)
+                .bold()
+                .cyan()
+        );
+        print_sides_table(&tracklist, &medium, &gaps, SideLabels::default());
+        if !fits_medium(&tracklist, &medium, &gaps) {
+            println!(
+                "{}",
+                "The stored order doesn't fit this medium; see propose --diagnose.".red()
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Handles explaining the score of a tracklist's stored order on a medium: the
+/// points each constraint awards, how fully it is satisfied, and the total.
+pub fn handle_explain(ctx: &ProgramContext, tracklist_name: &str, medium_name: &str) -> CliResult {
+    let ser_tl = ctx.tracklist(tracklist_name)?;
+    let ser_medium = ctx.medium(medium_name)?;
+    let medium = ser_medium.to_album_medium();
+    let tracklist = Tracklist::from(&ser_tl.tracks);
+    let gaps = Gaps::of(&ser_tl.tracks);
+
+    let constraints = ctx.constraints_on(ser_medium);
+    let scorer = Scorer::new(&constraints, &medium, &ser_tl.tracks, &ctx.tracklists);
+    let breakdown = scorer.breakdown(&tracklist);
+
+    println!(
+        "{}",
+        format!(
+            "Score of the stored order of '{}' on medium '{}':",
+            ser_tl.name, medium.name
+        )
+        .bold()
+        .cyan()
+    );
+
+    let mut table = Table::new();
+    table.set_format(*format::consts::FORMAT_NO_LINESEP_WITH_TITLE);
+    table.set_titles(Row::new(vec![
+        Cell::new("Index").style_spec("bFc"),
+        Cell::new("Kind").style_spec("bFc"),
+        Cell::new("Args").style_spec("bFc"),
+        Cell::new("Satisfied").style_spec("bFc"),
+        Cell::new("Points").style_spec("bFc"),
+    ]));
+    for (i, (c, points)) in constraints.iter().zip(&breakdown).enumerate() {
+        let index = match i.checked_sub(ctx.constraints.len()) {
+            Some(j) => format!("medium #{}", j),
+            None => i.to_string(),
+        };
+        let (kind, args) = describe_constraint(&c.kind);
+        let max = c.max_points() as Score;
+        let (satisfied, points) = match points {
+            None => ("disabled", String::new()),
+            Some(p) if *p <= 0.0 && max > 0.0 => ("no", format!("0 / {}", format_score(max))),
+            Some(p) if *p < max => (
+                "partly",
+                format!("{} / {}", format_score(*p), format_score(max)),
+            ),
+            Some(p) => (
+                "yes",
+                format!("{} / {}", format_score(*p), format_score(max)),
+            ),
+        };
+        table.add_row(Row::new(vec![
+            Cell::new(&index),
+            Cell::new(kind),
+            Cell::new(&args),
+            Cell::new(satisfied),
+            Cell::new(&points),
+        ]));
+    }
+    table.printstd();
+    println!();
+
+    let total: Score = breakdown.iter().flatten().sum();
+    println!("{} {}", "Total score:".bold(), format_score(total));
+    if !fits_medium(&tracklist, &medium, &gaps) {
+        println!(
+            "{}",
+            "The stored order doesn't fit this medium; see propose --diagnose.".red()
+        );
+    }
+
+    Ok(())
+}
+
+/// Explains why no permutation was proposed: either the tracklist can't fit the
+/// medium at all (capacity, overlong tracks), or fitting permutations exist but
+/// all of them score below `min_score`. Returns an error if the medium's sides have
+/// no length, as nothing can ever fit.
+fn print_fit_diagnosis(
+    tracklist: &Tracklist,
+    medium: &AlbumMedium,
+    gaps: &Gaps,
+    fitting: usize,
+    best_fitting_score: Option<Score>,
+    min_score: &Option<Score>,
+) -> CliResult {
+    if medium.max_duration_per_side <= 0.0 {
+        return Err(CliError::User(format!(
+            "Medium '{}' has sides of length {}; give it a positive --max-duration",
+            medium.name,
+            format_duration(medium.max_duration_per_side)
+        )));
+    }
+
+    println!("{}", "Diagnosis:".bold().red());
+
+    if let (Some(best), Some(min)) = (best_fitting_score, min_score) {
+        println!(
+            "  {} permutations fit the medium, but the best score is {} (below --min-score {}).",
+            fitting,
+            format_score(best),
+            format_score(*min)
+        );
+        return Ok(());
+    }
+
+    let total: Duration = tracklist.0.iter().map(|t| t.duration).sum();
+    let capacity = medium.max_duration_per_side * medium.sides as Duration;
+    // With the gaps between tracks, which only a side break drops
+    let all_tracks: Vec<&Track> = tracklist.0.iter().collect();
+    let min_sides =
+        (gaps.side_duration(&all_tracks) / medium.max_duration_per_side).ceil() as usize;
+
+    println!(
+        "  Total duration {} vs capacity {} ({} sides x {}).",
+        format_duration(total),
+        format_duration(capacity),
+        medium.sides,
+        format_duration(medium.max_duration_per_side)
+    );
+    println!(
+        "  At least {} sides are required, the medium has {}.",
+        min_sides, medium.sides
+    );
+
+    if total > capacity {
+        println!(
+            "  Overflow: {} over the medium's capacity.",
+            format_duration(total - capacity)
+        );
+    }
+
+    let longest = tracklist
+        .0
+        .iter()
+        .filter(|t| t.duration > medium.max_duration_per_side + FIT_TOLERANCE)
+        .max_by(|a, b| a.duration.total_cmp(&b.duration));
+    if let Some(track) = longest {
+        println!(
+            "  Longest unplaceable track: '{}' ({}) is longer than a side ({}).",
+            track.title,
+            format_duration(track.duration),
+            format_duration(medium.max_duration_per_side)
+        );
+    }
+
+    let sides = split_tracklist_by_side(tracklist, medium, gaps);
+    let placed: usize = sides.iter().map(|s| s.len()).sum();
+    if placed < tracklist.0.len() {
+        let left_off: Vec<&str> = tracklist.0[placed..]
+            .iter()
+            .map(|t| t.title.as_str())
+            .collect();
+        println!(
+            "  In the stored order, {} tracks spill past the last side: {}.",
+            left_off.len(),
+            left_off.join(", ")
+        );
+    }
+
+    if total <= capacity && longest.is_none() {
+        println!(
+            "  The total fits, but no order packs the tracks into {} sides without a gap at a side break.",
+            medium.sides
+        );
+    }
+
+    Ok(())
+}
+
+/// Returns the positions in the stored tracklist of the tracks of a permutation.
+fn permutation_order(tracklist: &Tracklist, perm: &[&Track]) -> Vec<usize> {
+    perm.iter()
+        .map(|t| {
+            tracklist
+                .0
+                .iter()
+                .position(|stored| std::ptr::eq(stored, *t))
+                .expect("permutation tracks come from the tracklist")
+        })
+        .collect()
+}
+
+/// Returns the positions in the stored tracklist of the tracks of a proposal, a copy
+/// of a permutation. Each track is matched to the first stored track not matched yet
+/// with the same title and duration.
+fn stored_order(tracklist: &Tracklist, proposal: &Tracklist) -> Vec<usize> {
+    let mut matched = vec![false; tracklist.0.len()];
+    proposal
+        .0
+        .iter()
+        .map(|t| {
+            let i = (0..tracklist.0.len())
+                .find(|&i| {
+                    !matched[i]
+                        && tracklist.0[i].title == t.title
+                        && tracklist.0[i].duration == t.duration
+                })
+                .expect("proposal tracks come from the tracklist");
+            matched[i] = true;
+            i
+        })
+        .collect()
+}
+
+/// How `propose` picks the permutations it scores.
+#[derive(Serialize, Clone, Copy, Debug)]
+pub enum Search {
+    /// Score every permutation.
+    Exhaustive,
+    /// Score `samples` distinct random permutations drawn with the given seed,
+    /// optionally keeping the first and/or last track in place.
+    Sample {
+        samples: usize,
+        seed: u64,
+        fix_first: bool,
+        fix_last: bool,
+    },
+}
+
+/// Options shared by the proposal commands.
+pub struct ProposeOptions {
+    /// Number of propositions to show.
+    pub count: usize,
+    /// Which permutations are scored.
+    pub search: Search,
+    /// Minimum score to include.
+    pub min_score: Option<Score>,
+    /// Explain why nothing was proposed.
+    pub diagnose: bool,
+    /// Where to cache scored permutations, or `None` to always re-score.
+    pub cache_path: Option<PathBuf>,
+    /// How sides are labeled in the output.
+    pub side_labels: SideLabels,
+    /// How proposals are rendered.
+    pub format: OutputFormat,
+    /// Print run statistics below the proposals.
+    pub stats: bool,
+    /// Print the time spent generating, scoring and ranking permutations.
+    pub measure: bool,
+    /// Print the score and rank of the stored order below the proposals.
+    pub include_original: bool,
+    /// Among equal scores, show the permutations closest to the stored order first.
+    pub prefer_original: bool,
+    /// What the top permutations are ordered by.
+    pub sort_by: SortBy,
+    /// How the top permutations are grouped, if at all.
+    pub group_by: Option<GroupBy>,
+    /// Reference tracklist and strength of an anchor: scored like a `stayclose`
+    /// constraint of that weight, added to the context's constraints for this run.
+    pub anchor: Option<(String, usize)>,
+    /// Points a permutation earns for a perfect fit (see `fit_quality`), added to
+    /// its constraint score. Zero leaves the ranking to the constraints.
+    pub fit_weight: Score,
+    /// When the tracks run longer than the medium holds, propose orders of the subsets
+    /// of tracks that fill it best instead.
+    pub allow_partial: bool,
+}
+
+/// Handles proposing top scoring tracklist permutations for a tracklist & medium.
+/// `sides` and `max_duration` replace the medium's values for this run only.
+/// Now with prettytable output.
+pub fn handle_propose(
+    ctx: &ProgramContext,
+    tracklist_name: &str,
+    medium_name: &str,
+    sides: Option<usize>,
+    max_duration: Option<Duration>,
+    opts: &ProposeOptions,
+) -> CliResult<StoredProposals> {
+    let ser_tl = ctx.tracklist(tracklist_name)?;
+    ensure_has_tracks(ser_tl)?;
+    if let Some((anchor, _)) = &opts.anchor {
+        ctx.tracklist(anchor)?;
+    }
+    let mut ser_medium = ctx.medium(medium_name)?.clone();
+
+    if opts.allow_partial && ser_tl.tracks.0.len() > MAX_PARTIAL_TRACKS {
+        return Err(CliError::User(format!(
+            "--allow-partial considers every subset of tracks and supports up to {} tracks",
+            MAX_PARTIAL_TRACKS
+        )));
+    }
+    if sides == Some(0) {
+        return Err(CliError::User("--sides must be at least 1".to_string()));
+    }
+    if sides.is_some() || max_duration.is_some() {
+        ser_medium.sides = sides.unwrap_or(ser_medium.sides);
+        ser_medium.max_duration_per_side = max_duration.unwrap_or(ser_medium.max_duration_per_side);
+        status!(
+            "Using medium '{}' as {} sides x {} for this run",
+            ser_medium.name,
+            ser_medium.sides,
+            format_duration(ser_medium.max_duration_per_side)
+        );
+    }
+
+    propose_on_medium(ctx, ser_tl, &ser_medium, opts)
+}
+
+/// Handles printing the proposals remembered by the last `propose --remember` run.
+/// Returns an error if none were remembered.
+pub fn handle_last_proposals(ctx: &ProgramContext) -> CliResult {
+    let Some(last) = &ctx.last_proposals else {
+        return Err(CliError::User(
+            "No proposals remembered; run propose with --remember".to_string(),
+        ));
+    };
+
+    println!(
+        "{}",
+        format!(
+            "Last proposals for tracklist '{}' on medium '{}':",
+            last.tracklist, last.medium
+        )
+        .bold()
+        .cyan()
+    );
+    if last.proposals.is_empty() {
+        println!("  (none)");
+    }
+    for (idx, proposal) in last.proposals.iter().enumerate() {
+        print_proposal_header(idx + 1, proposal.score);
+        for (pos, title) in proposal.titles.iter().enumerate() {
+            println!("  {:>2}. {}", pos + 1, title);
+        }
+    }
+
+    Ok(())
+}
+
+/// Handles proposing a tracklist on every medium in the context, one section per medium.
+pub fn handle_propose_all(
+    ctx: &ProgramContext,
+    tracklist_name: &str,
+    opts: &ProposeOptions,
+) -> CliResult {
+    let ser_tl = ctx.tracklist(tracklist_name)?;
+    ensure_has_tracks(ser_tl)?;
+
+    if ctx.mediums.is_empty() {
+        return Err(CliError::User("No media in the context".to_string()));
+    }
+
+    for ser_medium in &ctx.mediums {
+        println!(
+            "{}",
+            format!("=== Medium: {} ===", ser_medium.name)
+                .bold()
+                .magenta()
+        );
+        propose_on_medium(ctx, ser_tl, ser_medium, opts)?;
+    }
+
+    Ok(())
+}
+
+/// Returns a user error if the tracklist has no tracks, as there is nothing to propose.
+fn ensure_has_tracks(tracklist: &NamedSerTracklist) -> CliResult {
+    if tracklist.tracks.0.is_empty() {
+        return Err(CliError::User(format!(
+            "Tracklist '{}' has no tracks; add some with add-tracklist",
+            tracklist.name
+        )));
+    }
+    Ok(())
+}
+
+/// Scores every permutation of a stored tracklist on a medium and prints the top proposals.
+/// Returns the proposals shown, to be remembered in the context.
+fn propose_on_medium(
+    ctx: &ProgramContext,
+    ser_tl: &NamedSerTracklist,
+    ser_medium: &SerMedium,
+    opts: &ProposeOptions,
+) -> CliResult<StoredProposals> {
+    let count = opts.count;
+    let min_score = &opts.min_score;
+    let tracklist = Tracklist::from(&ser_tl.tracks);
+    let medium = ser_medium.to_album_medium();
+    let gaps = Gaps::of(&ser_tl.tracks);
+
+    let anchor = opts.anchor.as_ref().map(|(name, strength)| SerConstraint {
+        kind: SerConstraintKind::StayCloseTo(name.clone()),
+        weight: *strength,
+        enabled: true,
+    });
+    let mut constraints = ctx.constraints_on(ser_medium);
+    constraints.extend(anchor);
+    let scorer = Scorer::new(&constraints, &medium, &ser_tl.tracks, &ctx.tracklists);
+
+    debug!(
+        "Proposing for {} tracks on '{}' ({} sides x {}) with {} constraints",
+        tracklist.0.len(),
+        medium.name,
+        medium.sides,
+        format_duration(medium.max_duration_per_side),
+        constraints.len()
+    );
+
+    let side_imbalance = |tl: &Tracklist| {
+        let durations = split_tracklist_by_side(tl, &medium, &gaps)
+            .iter()
+            .map(|side| gaps.side_duration(side))
+            .collect::<Vec<_>>();
+        let longest = durations.iter().copied().fold(0.0, Duration::max);
+        let shortest = durations.iter().copied().fold(longest, Duration::min);
+        longest - shortest
+    };
+
+    let fit_bonus = |tl: &Tracklist| {
+        if opts.fit_weight > 0.0 {
+            opts.fit_weight * fit_quality(tl, &medium, &gaps)
+        } else {
+            0.0
+        }
+    };
+
+    // A tracklist too long for the medium is proposed as its best-filling subsets
+    let all: Vec<&Track> = tracklist.0.iter().collect();
+    let capacity = medium.sides as Duration * medium.max_duration_per_side;
+    let partial = (opts.allow_partial && gaps.side_duration(&all) > capacity + FIT_TOLERANCE)
+        .then(|| filling_subsets(&all, capacity));
+    if let Some(subsets) = &partial {
+        status!(
+            "Tracklist '{}' runs {}, more than medium '{}' holds ({}); proposing orders of the {} subsets of tracks that fill it best",
+            ser_tl.name,
+            format_duration(gaps.side_duration(&all)),
+            ser_medium.name,
+            format_duration(capacity),
+            subsets.len()
+        );
+    }
+
+    let started = Instant::now();
+    // StayCloseTo scores against the order of another tracklist, which is an input too
+    let references: Vec<&SerTracklist> = constraints
+        .iter()
+        .filter_map(|c| match &c.kind {
+            SerConstraintKind::StayCloseTo(name) => ctx
+                .tracklists
+                .iter()
+                .find(|tl| tl.name.eq_ignore_ascii_case(name))
+                .map(|tl| &tl.tracks),
+            _ => None,
+        })
+        .collect();
+    let key = match opts.search {
+        Search::Exhaustive => cache_key(&(&ser_tl.tracks, ser_medium, &constraints, &references)),
+        search => cache_key(&(
+            &ser_tl.tracks,
+            ser_medium,
+            &constraints,
+            &references,
+            search,
+        )),
+    };
+    let key = if partial.is_some() {
+        cache_key(&(key, "partial"))
+    } else {
+        key
+    };
+    let key = if opts.fit_weight > 0.0 {
+        cache_key(&(key, opts.fit_weight))
+    } else {
+        key
+    };
+    // Only the top proposals are cached, and which ones they are depends on the ranking
+    let key = cache_key(&(
+        key,
+        opts.min_score,
+        format!("{:?}", opts.sort_by),
+        opts.prefer_original,
+    ));
+    let mut cache = opts.cache_path.as_deref().map(ProposalCache::load);
+    // --stats needs every score, which the cache doesn't hold
+    let cached = cache
+        .as_ref()
+        .and_then(|c| c.get(&key))
+        .filter(|entry| entry.covers(count) && !opts.stats);
+    let from_cache = cached.is_some();
+
+    let mut tally = RunTally {
+        min_score: *min_score,
+        top: if opts.prefer_original {
+            TopProposals::new(count).preferring(&tracklist)
+        } else {
+            TopProposals::new(count)
+        },
+        fitting: 0,
+        kept: 0,
+        best: None,
+        scores: opts.stats.then(Vec::new),
+        // Also counted when caching, so a cached run can report it later
+        original: (opts.include_original || cache.is_some())
+            .then(|| scorer.score(&tracklist) + fit_bonus(&tracklist)),
+        above_original: 0,
+        imbalance: match opts.sort_by {
+            SortBy::Score => None,
+            SortBy::Balance => Some(&side_imbalance),
+        },
+    };
+
+    // Score every fitting permutation, or read the scores back from the cache
+    let mut times = PhaseTimes::default();
+    let evaluated = match cached {
+        Some(entry) => {
+            let scoring = Instant::now();
+            tally.restore(entry, |order| {
+                Tracklist(order.iter().map(|&i| tracklist.0[i].clone()).collect())
+            });
+            times.scoring = scoring.elapsed();
+            entry.evaluated
+        }
+        None => {
+            let generating = Instant::now();
+            let candidates: Box<dyn Iterator<Item = Vec<&Track>>> = match (&partial, opts.search) {
+                (None, Search::Exhaustive) => Box::new(TracklistPermutations::new(&tracklist.0)),
+                (Some(subsets), Search::Exhaustive) => Box::new(
+                    subsets
+                        .iter()
+                        .flat_map(|subset| permutations_of(subset.clone())),
+                ),
+                (
+                    _,
+                    Search::Sample {
+                        samples,
+                        seed,
+                        fix_first,
+                        fix_last,
+                    },
+                ) => {
+                    let subsets = partial.clone().unwrap_or_else(|| vec![all.clone()]);
+                    let per_subset = (samples / subsets.len().max(1)).max(1);
+                    Box::new(subsets.into_iter().flat_map(move |subset| {
+                        sample_permutations(&subset, per_subset, seed, fix_first, fix_last)
+                    }))
+                }
+            };
+            times.generation = generating.elapsed();
+
+            // Permutations are generated lazily, so time each one as it's drawn
+            let mut generation = TimeDuration::ZERO;
+            let mut candidates = candidates;
+            let candidates: Box<dyn Iterator<Item = Vec<&Track>>> = if opts.measure {
+                Box::new(std::iter::from_fn(|| {
+                    let drawing = Instant::now();
+                    let next = candidates.next();
+                    generation += drawing.elapsed();
+                    next
+                }))
+            } else {
+                candidates
+            };
+
+            let scoring = Instant::now();
+            let evaluated =
+                score_permutations(candidates, &medium, &gaps, &scorer, |score, tl, _| {
+                    tally.record(score + fit_bonus(&tl), || tl);
+                });
+            times.scoring = scoring.elapsed().saturating_sub(generation);
+            times.generation += generation;
+
+            evaluated
+        }
+    };
+
+    debug!(
+        "Evaluated {} permutations ({}), {} fit the medium, {} kept, in {:?}",
+        evaluated,
+        if from_cache { "cached" } else { "scored" },
+        tally.fitting,
+        tally.kept,
+        started.elapsed()
+    );
+
+    if tally.kept == 0 {
+        if opts.diagnose {
+            print_fit_diagnosis(
+                &tracklist,
+                &medium,
+                &gaps,
+                tally.fitting,
+                tally.best,
+                min_score,
+            )?;
+        } else {
+            eprintln!("No permutation matched; run again with --diagnose for details");
+        }
+    }
+
+    let top_what = match opts.sort_by {
+        SortBy::Score => format!("Top {}", count),
+        SortBy::Balance => format!("{} most balanced", count),
+    };
+    let heading = match min_score {
+        Some(min) => format!(
+            "{} permutations for tracklist '{}' on medium '{}' with score >= {}:",
+            top_what,
+            ser_tl.name,
+            ser_medium.name,
+            format_score(*min)
+        ),
+        None => format!(
+            "{} permutations for tracklist '{}' on medium '{}':",
+            top_what, ser_tl.name, ser_medium.name
+        ),
+    };
+    match opts.format {
+        OutputFormat::Table => println!("{}", heading.bold().cyan()),
+        OutputFormat::Markdown => println!("## {}\n", heading.trim_end_matches(':')),
+        OutputFormat::Ndjson => {}
+    }
+
+    let RunTally {
+        top,
+        fitting,
+        kept,
+        best,
+        scores,
+        original,
+        above_original,
+        ..
+    } = tally;
+
+    let ranking = Instant::now();
+    let proposals = top.into_sorted_vec();
+    let top_orders = (cache.is_some() && !from_cache).then(|| {
+        proposals
+            .iter()
+            .map(|(score, tl)| (*score, stored_order(&tracklist, tl)))
+            .collect()
+    });
+    let groups = match opts.group_by {
+        Some(GroupBy::Side1) => group_by_first_side(proposals, &medium, &gaps),
+        None => proposals.into_iter().map(|p| (p, 1)).collect(),
+    };
+    times.ranking = ranking.elapsed();
+
+    if let (Some(cache), Some(path), Some(top)) =
+        (cache.as_mut(), opts.cache_path.as_deref(), top_orders)
+    {
+        cache.insert(CacheEntry {
+            key,
+            evaluated,
+            fitting,
+            kept,
+            best,
+            above_original,
+            top,
+        });
+        cache.save(path);
+    }
+
+    let shown = StoredProposals {
+        tracklist: ser_tl.name.clone(),
+        medium: ser_medium.name.clone(),
+        proposals: groups
+            .iter()
+            .map(|((score, tl), _)| StoredProposal {
+                score: *score,
+                titles: tl.0.iter().map(|t| t.title.clone()).collect(),
+            })
+            .collect(),
+    };
+    for (idx, ((score, tl), size)) in groups.into_iter().enumerate() {
+        match opts.format {
+            OutputFormat::Table => {
+                print_proposal(idx + 1, score, &tl, &medium, &gaps, opts.side_labels)
+            }
+            OutputFormat::Markdown => {
+                print_proposal_markdown(idx + 1, score, &tl, &medium, &gaps, opts.side_labels)
+            }
+            OutputFormat::Ndjson => {
+                let group_size = opts.group_by.map(|_| size);
+                print_proposal_ndjson(idx + 1, score, &tl, &medium, &gaps, group_size);
+                continue;
+            }
+        }
+        if partial.is_some() {
+            // By stored position, so a track sharing its title with a kept one is listed
+            let kept = stored_order(&tracklist, &tl);
+            let left_off: Vec<&str> = tracklist
+                .0
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| !kept.contains(i))
+                .map(|(_, t)| t.title.as_str())
+                .collect();
+            println!("Left off: {}\n", left_off.join(", "));
+        }
+        if size > 1 {
+            println!(
+                "({} more of the top permutations open with the same side {})\n",
+                size - 1,
+                opts.side_labels.label(0)
+            );
+        }
+    }
+
+    // Printed even when quiet, as it explains the output
+    if kept < count {
+        match min_score {
+            Some(min) => eprintln!(
+                "Only {} of {} requested permutations fit the medium with score >= {}.",
+                kept,
+                count,
+                format_score(*min)
+            ),
+            None => eprintln!(
+                "Only {} of {} requested permutations fit the medium.",
+                kept, count
+            ),
+        }
+    }
+
+    if let Some(score) = original.filter(|_| opts.include_original) {
+        print_original_rank(&tracklist, &medium, &gaps, score, above_original, fitting);
+    }
+
+    if let Some(mut scores) = scores {
+        scores.sort_unstable_by(|a, b| a.total_cmp(b));
+        print_run_stats(evaluated, &scores, from_cache, started.elapsed());
+    }
+
+    if opts.measure {
+        print_phase_times(&times, from_cache);
+    }
+
+    Ok(shown)
+}
+
+/// Wall-clock time spent in each phase of a proposal run, for `--measure`.
+#[derive(Default)]
+struct PhaseTimes {
+    /// Building the candidate permutations.
+    generation: TimeDuration,
+    /// Checking the fit and scoring each candidate, keeping the best ones as it goes.
+    scoring: TimeDuration,
+    /// Sorting (and grouping) the kept permutations.
+    ranking: TimeDuration,
+}
+
+/// Prints the time spent in each phase of a proposal run.
+fn print_phase_times(times: &PhaseTimes, from_cache: bool) {
+    println!("{}", "Timing:".bold().cyan());
+    if from_cache {
+        println!("  Generation: - (scores read from cache)");
+    } else {
+        println!("  Generation: {:.3}s", times.generation.as_secs_f64());
+    }
+    println!("  Scoring: {:.3}s", times.scoring.as_secs_f64());
+    println!("  Ranking: {:.3}s", times.ranking.as_secs_f64());
+}
+
+/// Rates how well a permutation uses the medium, from 0 to 1: the mean of how full
+/// its used sides are and how balanced they are (one minus the difference between
+/// the longest and shortest used side, relative to the max duration per side).
+fn fit_quality(tracklist: &Tracklist, medium: &AlbumMedium, gaps: &Gaps) -> Score {
+    let durations = split_tracklist_by_side(tracklist, medium, gaps)
+        .iter()
+        .filter(|side| !side.is_empty())
+        .map(|side| gaps.side_duration(side))
+        .collect::<Vec<_>>();
+    if durations.is_empty() || medium.max_duration_per_side <= 0.0 {
+        return 0.0;
+    }
+
+    let capacity = durations.len() as Duration * medium.max_duration_per_side;
+    let fill = (durations.iter().sum::<Duration>() / capacity).min(1.0);
+    let longest = durations.iter().copied().fold(0.0, Duration::max);
+    let shortest = durations.iter().copied().fold(longest, Duration::min);
+    let balance = 1.0 - ((longest - shortest) / medium.max_duration_per_side).min(1.0);
+
+    (fill + balance) / 2.0
+}
+
+/// Groups proposals, best first, by the set of tracks on their first side.
+/// Returns the best proposal of each group with the group's size, in the order of
+/// their best proposals.
+fn group_by_first_side(
+    proposals: Vec<(Score, Tracklist)>,
+    medium: &AlbumMedium,
+    gaps: &Gaps,
+) -> Vec<((Score, Tracklist), usize)> {
+    let mut groups: Vec<(Vec<String>, (Score, Tracklist), usize)> = Vec::new();
+    for (score, tl) in proposals {
+        let mut first_side: Vec<String> = split_tracklist_by_side(&tl, medium, gaps)
+            .first()
+            .map(|side| side.iter().map(|t| t.title.clone()).collect())
+            .unwrap_or_default();
+        first_side.sort();
+
+        match groups.iter_mut().find(|(key, _, _)| *key == first_side) {
+            Some((_, _, size)) => *size += 1,
+            None => groups.push((first_side, (score, tl), 1)),
+        }
+    }
+    groups
+        .into_iter()
+        .map(|(_, best, size)| (best, size))
+        .collect()
+}
+
+/// Tallies the fitting permutations of a proposal run.
+/// Only the best `count` permutations are kept; the others only count toward the totals.
+struct RunTally<'a> {
+    /// Permutations below this score are not proposed.
+    min_score: Option<Score>,
+    /// The best permutations at or above `min_score`.
+    top: TopProposals,
+    /// Number of permutations that fit the medium.
+    fitting: usize,
+    /// Number of fitting permutations at or above `min_score`.
+    kept: usize,
+    /// Best score among the fitting permutations.
+    best: Option<Score>,
+    /// Every fitting score, collected only for `--stats`.
+    scores: Option<Vec<Score>>,
+    /// Score of the stored order, for `--include-original` and the cache.
+    original: Option<Score>,
+    /// Number of fitting permutations scoring above the stored order.
+    above_original: usize,
+    /// Side imbalance of a permutation, to rank by instead of the score for
+    /// `--sort-by balance`.
+    imbalance: Option<&'a dyn Fn(&Tracklist) -> Duration>,
+}
+
+impl RunTally<'_> {
+    /// Records a fitting permutation. Its tracklist is only built if it may be proposed.
+    fn record(&mut self, score: Score, tracklist: impl FnOnce() -> Tracklist) {
+        self.fitting += 1;
+        self.best = Some(self.best.map_or(score, |best| best.max(score)));
+        if let Some(scores) = self.scores.as_mut() {
+            scores.push(score);
+        }
+        if self.original.is_some_and(|original| score > original) {
+            self.above_original += 1;
+        }
+
+        if self.min_score.is_none_or(|min| score >= min) {
+            self.kept += 1;
+            self.keep(score, tracklist);
+        }
+    }
+
+    /// Restores the totals and top proposals of a cached run. `tracklist` builds a
+    /// proposal from its track order.
+    fn restore(&mut self, entry: &CacheEntry, tracklist: impl Fn(&[usize]) -> Tracklist) {
+        self.fitting = entry.fitting;
+        self.kept = entry.kept;
+        self.best = entry.best;
+        self.above_original = entry.above_original;
+        for (score, order) in &entry.top {
+            self.keep(*score, || tracklist(order));
+        }
+    }
+
+    /// Offers a kept permutation to the top proposals, ranked by score or balance.
+    fn keep(&mut self, score: Score, tracklist: impl FnOnce() -> Tracklist) {
+        match self.imbalance {
+            None => {
+                if self.top.would_keep(score) {
+                    self.top.push(score, tracklist());
+                }
+            }
+            Some(imbalance) => {
+                let tracklist = tracklist();
+                let key = -imbalance(&tracklist);
+                if self.top.would_keep(key) {
+                    self.top.push_ranked(key, score, tracklist);
+                }
+            }
+        }
+    }
+}
+
+/// Prints the score of the stored order and its rank among the fitting permutations.
+/// Equal scores share a rank.
+fn print_original_rank(
+    tracklist: &Tracklist,
+    medium: &AlbumMedium,
+    gaps: &Gaps,
+    score: Score,
+    above: usize,
+    fitting: usize,
+) {
+    println!("{}", "Original order:".bold().cyan());
+
+    if !fits_medium(tracklist, medium, gaps) {
+        println!(
+            "  Score {}, but the stored order doesn't fit the medium.",
+            format_score(score)
+        );
         return;
     }
-    let ser_tl = ser_tl.unwrap();
+
+    println!(
+        "  Score {}, ranked {} of {} fitting permutations.",
+        format_score(score),
+        above + 1,
+        fitting
+    );
+}
+
+/// Prints a summary of a proposal run: permutations evaluated and fitting, score
+/// distribution among the fitting ones, and wall-clock time.
+/// `fitting_scores` must be sorted in ascending order.
+fn print_run_stats(
+    evaluated: usize,
+    fitting_scores: &[Score],
+    from_cache: bool,
+    elapsed: TimeDuration,
+) {
+    println!("{}", "Run statistics:".bold().cyan());
+    println!(
+        "  Permutations evaluated: {}{}",
+        evaluated,
+        if from_cache { " (from cache)" } else { "" }
+    );
+    println!("  Fitting the medium: {}", fitting_scores.len());
+
+    if let (Some(min), Some(max)) = (fitting_scores.first(), fitting_scores.last()) {
+        let mean = fitting_scores.iter().sum::<Score>() / fitting_scores.len() as Score;
+        let mid = fitting_scores.len() / 2;
+        let median = if fitting_scores.len().is_multiple_of(2) {
+            (fitting_scores[mid - 1] + fitting_scores[mid]) / 2.0
+        } else {
+            fitting_scores[mid]
+        };
+        println!(
+            "  Scores: min {}, max {}, mean {}, median {}",
+            format_score(*min),
+            format_score(*max),
+            format_score(mean),
+            format_score(median)
+        );
+    }
+
+    println!("  Time: {:.3}s", elapsed.as_secs_f64());
+}
+
+/// Scores the candidate permutations of a tracklist. Each one that fits the medium
+/// (gaps between tracks included) is passed to `on_fitting` with its score, as a
+/// tracklist, and as references to the stored tracks. Returns the number of
+/// permutations evaluated.
+fn score_permutations<'t>(
+    candidates: impl Iterator<Item = Vec<&'t Track>>,
+    medium: &AlbumMedium,
+    gaps: &Gaps,
+    scorer: &Scorer,
+    mut on_fitting: impl FnMut(Score, Tracklist, &[&'t Track]),
+) -> usize {
+    let mut evaluated = 0usize;
+    for perm in candidates {
+        evaluated += 1;
+        let tl = Tracklist(perm.iter().map(|t| (*t).clone()).collect());
+        if fits_medium(&tl, medium, gaps) {
+            on_fitting(scorer.score(&tl), tl, &perm);
+        }
+    }
+
+    evaluated
+}
+
+/// Most combinations of disc orders `propose-set` scores; beyond it, this many are
+/// drawn at random.
+const MAX_SET_COMBINATIONS: usize = 100_000;
+
+/// Seed of the draw of set combinations, so that the same set is proposed every run.
+const SET_SAMPLE_SEED: u64 = 0;
+
+/// Most fitting orders of one disc `propose-set` combines; a 12-track disc alone has
+/// 479M orders, too many to hold.
+const MAX_DISC_ORDERS: usize = 1_000;
+
+/// Returns the orders of a disc's tracks that fit the medium on its own, at most
+/// [`MAX_DISC_ORDERS`] of them, found while generating the permutations one by one.
+fn fitting_disc_orders(disc: &SerTracklist, medium: &AlbumMedium) -> Vec<Tracklist> {
+    let tracklist = Tracklist::from(disc);
+    let gaps = Gaps::of(disc);
+    TracklistPermutations::new(&tracklist.0)
+        .filter_map(|perm| {
+            let tl = Tracklist(perm.iter().map(|t| (*t).clone()).collect());
+            fits_medium(&tl, medium, &gaps).then_some(tl)
+        })
+        .take(MAX_DISC_ORDERS)
+        .collect()
+}
+
+/// Returns the number of combinations of disc orders, saturating at `usize::MAX`.
+fn set_combination_count(orders_per_disc: &[usize]) -> usize {
+    orders_per_disc
+        .iter()
+        .try_fold(1usize, |acc, &n| acc.checked_mul(n))
+        .unwrap_or(usize::MAX)
+}
+
+/// Returns the combinations of disc orders to score, as the index of the order of
+/// each disc: all of them when there are at most `limit`, otherwise `limit` distinct
+/// ones drawn at random with `seed`.
+fn set_combinations(orders_per_disc: &[usize], limit: usize, seed: u64) -> Vec<Vec<usize>> {
+    // Past usize::MAX, every index below it is still a distinct combination
+    let total = set_combination_count(orders_per_disc);
+    let indices = if total <= limit {
+        (0..total).collect()
+    } else {
+        let mut rng = StdRng::seed_from_u64(seed);
+        rand::seq::index::sample(&mut rng, total, limit).into_vec()
+    };
+
+    // The last disc's order varies fastest
+    indices
+        .into_iter()
+        .map(|mut index| {
+            let mut choice = vec![0; orders_per_disc.len()];
+            for (disc, &orders) in orders_per_disc.iter().enumerate().rev() {
+                choice[disc] = index % orders;
+                index /= orders;
+            }
+            choice
+        })
+        .collect()
+}
+
+/// Handles proposing a box set: each named tracklist is one disc on the medium.
+///
+/// Tracks are permuted within their disc only, so the disc grouping is kept. Each
+/// combination of fitting disc orders (at most [`MAX_SET_COMBINATIONS`]) is scored
+/// as one tracklist, the discs played in the given order, so constraints can span
+/// discs; sides are split disc by disc (see [`Scorer::with_discs`]).
+pub fn handle_propose_set(
+    ctx: &ProgramContext,
+    tracklist_names: &[String],
+    medium_name: &str,
+    count: usize,
+    side_labels: SideLabels,
+) -> CliResult {
+    if tracklist_names.is_empty() {
+        return Err(CliError::User(
+            "No tracklists given for the set".to_string(),
+        ));
+    }
+
+    let discs = tracklist_names
+        .iter()
+        .map(|name| ctx.tracklist(name))
+        .collect::<CliResult<Vec<_>>>()?;
+    for disc in &discs {
+        ensure_has_tracks(disc)?;
+    }
+    let ser_medium = ctx.medium(medium_name)?;
+    let medium = ser_medium.to_album_medium();
+
+    // The orders of each disc that fit the medium on its own
+    let mut disc_orders: Vec<Vec<Tracklist>> = Vec::new();
+    for disc in &discs {
+        let orders = fitting_disc_orders(&disc.tracks, &medium);
+        if orders.is_empty() {
+            return Err(CliError::User(format!(
+                "Tracklist '{}' doesn't fit on medium '{}' in any order",
+                disc.name, medium.name
+            )));
+        }
+        if orders.len() == MAX_DISC_ORDERS {
+            warn!(
+                "Disc '{}' has too many fitting orders; using the first {}",
+                disc.name, MAX_DISC_ORDERS
+            );
+        }
+        debug!("Disc '{}': {} fitting orders", disc.name, orders.len());
+        disc_orders.push(orders);
+    }
+
+    let source = SerTracklist(
+        discs
+            .iter()
+            .flat_map(|disc| disc.tracks.0.iter().cloned())
+            .collect(),
+    );
+    let constraints = ctx.constraints_on(ser_medium);
+    let scorer = Scorer::new(&constraints, &medium, &source, &ctx.tracklists)
+        .with_discs(discs.iter().map(|disc| disc.tracks.0.len()).collect());
+
+    let orders_per_disc: Vec<usize> = disc_orders.iter().map(Vec::len).collect();
+    if set_combination_count(&orders_per_disc) > MAX_SET_COMBINATIONS {
+        warn!(
+            "Too many combinations of disc orders; scoring {} drawn at random",
+            MAX_SET_COMBINATIONS
+        );
+    }
+    let combinations = set_combinations(&orders_per_disc, MAX_SET_COMBINATIONS, SET_SAMPLE_SEED);
+
+    let started = Instant::now();
+    let mut top = TopProposals::new(count);
+    for choice in &combinations {
+        let tl = Tracklist(
+            choice
+                .iter()
+                .zip(&disc_orders)
+                .flat_map(|(&i, orders)| orders[i].0.iter().cloned())
+                .collect(),
+        );
+        let score = scorer.score(&tl);
+        if top.would_keep(score) {
+            top.push(score, tl);
+        }
+    }
+
+    debug!(
+        "Scored {} set combinations in {:?}",
+        combinations.len(),
+        started.elapsed()
+    );
+
+    println!(
+        "{}",
+        format!(
+            "Top {} sets for tracklists '{}' on medium '{}':",
+            count,
+            tracklist_names.join("', '"),
+            medium.name
+        )
+        .bold()
+        .cyan()
+    );
+
+    for (idx, (score, tl)) in top.into_sorted_vec().into_iter().enumerate() {
+        print_proposal_header(idx + 1, score);
+
+        let mut start = 0;
+        for (disc_idx, disc) in discs.iter().enumerate() {
+            let end = start + disc.tracks.0.len();
+            println!("{}", format!("Disc {}: {}", disc_idx + 1, disc.name).bold());
+            print_sides_table(
+                &Tracklist(tl.0[start..end].to_vec()),
+                &medium,
+                &Gaps::of(&disc.tracks),
+                side_labels,
+            );
+            start = end;
+        }
+    }
+
+    Ok(())
+}
+
+/// Most tracks `--allow-partial` picks subsets from, as it considers every subset.
+const MAX_PARTIAL_TRACKS: usize = 20;
+
+/// Number of best-filling subsets whose orders `--allow-partial` scores.
+const PARTIAL_SUBSETS: usize = 16;
+
+/// Returns the subsets of the tracks (in stored order) that fill `capacity` best: those
+/// whose total duration fits it and leaves no room for any of the remaining tracks,
+/// fullest first, at most [`PARTIAL_SUBSETS`] of them. Gaps are left to the fit check
+/// of each order.
+fn filling_subsets<'t>(tracks: &[&'t Track], capacity: Duration) -> Vec<Vec<&'t Track>> {
+    let limit = capacity + FIT_TOLERANCE;
+    let in_subset = |mask: u32, i: usize| mask & (1 << i) != 0;
+
+    let mut subsets: Vec<(Duration, u32)> = Vec::new();
+    for mask in 1..(1u32 << tracks.len()) {
+        let total: Duration = (0..tracks.len())
+            .filter(|&i| in_subset(mask, i))
+            .map(|i| tracks[i].duration)
+            .sum();
+        let filled = (0..tracks.len())
+            .filter(|&i| !in_subset(mask, i))
+            .all(|i| total + tracks[i].duration > limit);
+        if total <= limit && filled {
+            subsets.push((total, mask));
+        }
+    }
+    subsets.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+    subsets
+        .into_iter()
+        .take(PARTIAL_SUBSETS)
+        .map(|(_, mask)| {
+            (0..tracks.len())
+                .filter(|&i| in_subset(mask, i))
+                .map(|i| tracks[i])
+                .collect()
+        })
+        .collect()
+}
+
+/// Lazily yields every permutation of the tracks, starting with their given order.
+fn permutations_of(tracks: Vec<&Track>) -> impl Iterator<Item = Vec<&Track>> {
+    let mut order: Vec<usize> = (0..tracks.len()).collect();
+    let mut done = false;
+    std::iter::from_fn(move || {
+        if done {
+            return None;
+        }
+        let current = order.iter().map(|&i| tracks[i]).collect();
+        done = !next_permutation(&mut order);
+        Some(current)
+    })
+}
+
+/// Rearranges the indices into the next permutation in lexicographic order.
+/// Returns false, leaving them unchanged, if they were the last one.
+fn next_permutation(order: &mut [usize]) -> bool {
+    let Some(pivot) = (1..order.len()).rev().find(|&i| order[i - 1] < order[i]) else {
+        return false;
+    };
+    let successor = (pivot..order.len())
+        .rev()
+        .find(|&i| order[i] > order[pivot - 1])
+        .expect("the element at the pivot is larger");
+    order.swap(pivot - 1, successor);
+    order[pivot..].reverse();
+    true
+}
+
+/// Draws up to `samples` distinct random permutations of the tracks.
+/// With `fix_first` / `fix_last`, the first / last track stays in place and only the
+/// others are shuffled. The same seed always draws the same permutations. When the
+/// tracks have at most `samples` permutations in total, all of them are returned, in
+/// the order of [`permutations_of`].
+fn sample_permutations<'t>(
+    tracks: &[&'t Track],
+    samples: usize,
+    seed: u64,
+    fix_first: bool,
+    fix_last: bool,
+) -> Vec<Vec<&'t Track>> {
+    let start = usize::from(fix_first).min(tracks.len());
+    let end = tracks
+        .len()
+        .saturating_sub(usize::from(fix_last))
+        .max(start);
+    let total = (1..=end - start)
+        .try_fold(1usize, |acc, n| acc.checked_mul(n))
+        .unwrap_or(usize::MAX);
+    if samples >= total {
+        // Drawing them would take ever more repeats to find the last ones
+        return permutations_of(tracks[start..end].to_vec())
+            .map(|middle| [&tracks[..start], &middle[..], &tracks[end..]].concat())
+            .collect();
+    }
+
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut order: Vec<usize> = (0..tracks.len()).collect();
+    let mut seen = HashSet::new();
+    let mut drawn = Vec::with_capacity(samples);
+
+    while drawn.len() < samples {
+        order[start..end].shuffle(&mut rng);
+        if !seen.contains(&order) {
+            drawn.push(order.iter().map(|&i| tracks[i]).collect());
+            seen.insert(order.clone());
+        }
+    }
+
+    drawn
+}
+
+/// Prints one proposal: its number, score, and a table of tracks grouped by side.
+fn print_proposal(
+    number: usize,
+    score: Score,
+    tl: &Tracklist,
+    medium: &AlbumMedium,
+    gaps: &Gaps,
+    side_labels: SideLabels,
+) {
+    print_proposal_header(number, score);
+    print_sides_table(tl, medium, gaps, side_labels);
+}
+
+/// One proposal as printed by `--format ndjson`.
+#[derive(Serialize)]
+struct NdjsonProposal<'a> {
+    rank: usize,
+    score: Score,
+    /// The tracks of each side, in order.
+    sides: Vec<Vec<NdjsonTrack<'a>>>,
+    /// Number of top permutations the proposal stands for under `--group-by`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    group_size: Option<usize>,
+}
+
+/// A track of an [`NdjsonProposal`], with its duration in minutes.
+#[derive(Serialize)]
+struct NdjsonTrack<'a> {
+    title: &'a str,
+    duration: Duration,
+}
+
+/// Prints one proposal as a single line of compact JSON and flushes it, so that
+/// consumers of the output see each proposal as soon as it's printed.
+fn print_proposal_ndjson(
+    rank: usize,
+    score: Score,
+    tl: &Tracklist,
+    medium: &AlbumMedium,
+    gaps: &Gaps,
+    group_size: Option<usize>,
+) {
+    let sides = split_tracklist_by_side(tl, medium, gaps)
+        .iter()
+        .map(|side| {
+            side.iter()
+                .map(|t| NdjsonTrack {
+                    title: &t.title,
+                    duration: t.duration,
+                })
+                .collect()
+        })
+        .collect();
+    let proposal = NdjsonProposal {
+        rank,
+        score,
+        sides,
+        group_size,
+    };
+    match serde_json::to_string(&proposal) {
+        Ok(line) => println!("{}", line),
+        Err(e) => warn!("Failed to serialize proposal #{}: {}", rank, e),
+    }
+    let _ = io::stdout().flush();
+}
+
+/// Prints one proposal as GitHub-flavored Markdown: a heading with its number and
+/// score, then one subheading and pipe table per side, each with a TOTAL row.
+fn print_proposal_markdown(
+    number: usize,
+    score: Score,
+    tl: &Tracklist,
+    medium: &AlbumMedium,
+    gaps: &Gaps,
+    side_labels: SideLabels,
+) {
+    println!(
+        "### Permutation #{} (score {})\n",
+        number,
+        format_score(score)
+    );
+
+    let sides = split_tracklist_by_side(tl, medium, gaps);
+    let mut track_idx = 1;
+    for (side_idx, side_tracks) in sides.iter().enumerate() {
+        println!("#### Side {}\n", side_labels.label(side_idx));
+        println!("| # | Title | Duration |");
+        println!("|---:|---|---:|");
+        for t in side_tracks {
+            println!(
+                "| {} | {} | {} |",
+                track_idx,
+                t.title.replace('|', "\\|"),
+                format_duration(t.duration)
+            );
+            track_idx += 1;
+        }
+        let side_duration = gaps.side_duration(side_tracks);
+        println!("| | **TOTAL** | **{}** |\n", format_duration(side_duration));
+    }
+
+    let total_duration: Duration = sides.iter().map(|side| gaps.side_duration(side)).sum();
+    println!("**Total runtime:** {}\n", format_duration(total_duration));
+}
+
+/// Prints the number and score of a proposal.
+fn print_proposal_header(number: usize, score: Score) {
+    println!(
+        "{} {}",
+        "Permutation".yellow().bold(),
+        format!("#{}", number).yellow().bold()
+    );
+    println!(
+        "{} {}",
+        "Score:".green().bold(),
+        format_score(score).green().bold()
+    );
+}
+
+/// Prints a table of the tracks of a tracklist grouped by side, with a total row.
+/// The total includes the gaps between tracks on the same side.
+fn print_sides_table(tl: &Tracklist, medium: &AlbumMedium, gaps: &Gaps, side_labels: SideLabels) {
+    let sides = split_tracklist_by_side(tl, medium, gaps);
+
+    let mut table = Table::new();
+    table.set_format(*format::consts::FORMAT_BOX_CHARS);
+    table.set_titles(Row::new(vec![
+        Cell::new("#").style_spec("bFc"),
+        Cell::new("Title").style_spec("bFc"),
+        Cell::new("Duration").style_spec("bFc"),
+        Cell::new("Side").style_spec("bFc"),
+    ]));
+
+    let mut track_idx = 1;
+    for (side_idx, side_tracks) in sides.iter().enumerate() {
+        for t in side_tracks {
+            table.add_row(Row::new(vec![
+                Cell::new(&format!("{}", track_idx)),
+                Cell::new(&t.title),
+                Cell::new(&format_duration(t.duration)),
+                Cell::new(&side_labels.label(side_idx)),
+            ]));
+            track_idx += 1;
+        }
+    }
+
+    // Add total row
+    let total_duration: Duration = sides.iter().map(|side| gaps.side_duration(side)).sum();
+    table.add_row(Row::new(vec![
+        Cell::new(""),
+        Cell::new("TOTAL").style_spec("bFc"),
+        Cell::new(&format_duration(total_duration)).style_spec("bFc"),
+        Cell::new(""),
+    ]));
+
+    table.printstd();
+    println!();
+}
+
+/// How often each track lands at each position over sampled permutations that fit
+/// the medium.
+struct PositionCounts {
+    /// Number of permutations drawn.
+    drawn: usize,
+    /// Number of drawn permutations that fit the medium.
+    fitting: usize,
+    /// `counts[track][position]`, tracks by stored index, over the fitting
+    /// permutations; every row and column sums to `fitting`.
+    counts: Vec<Vec<usize>>,
+}
+
+/// Counts the positions of the tracks over up to `samples` random permutations of
+/// the tracklist (drawn with `seed`) that fit the medium.
+fn count_positions(
+    tracklist: &Tracklist,
+    medium: &AlbumMedium,
+    gaps: &Gaps,
+    samples: usize,
+    seed: u64,
+) -> PositionCounts {
+    let all: Vec<&Track> = tracklist.0.iter().collect();
+    let n = all.len();
+
+    let mut counts = vec![vec![0usize; n]; n];
+    let mut drawn = 0;
+    let mut fitting = 0;
+    for perm in sample_permutations(&all, samples, seed, false, false) {
+        drawn += 1;
+        let tl = Tracklist(perm.iter().map(|t| (*t).clone()).collect());
+        if !fits_medium(&tl, medium, gaps) {
+            continue;
+        }
+        fitting += 1;
+        for (pos, track) in permutation_order(tracklist, &perm).into_iter().enumerate() {
+            counts[track][pos] += 1;
+        }
+    }
+
+    PositionCounts {
+        drawn,
+        fitting,
+        counts,
+    }
+}
+
+/// Formats a heatmap cell: the percentage of `fitting` that `count` is, `.` when it's
+/// zero and `<1%` when it rounds down to zero.
+fn heatmap_cell(count: usize, fitting: usize) -> String {
+    match count * 100 / fitting {
+        0 if count == 0 => format!("{:>5}", "."),
+        0 => format!("{:>5}", "<1%"),
+        share => format!("{:>4}%", share),
+    }
+}
+
+/// Handles printing, over up to `samples` random permutations of a tracklist that fit
+/// the medium, the share of them placing each track at each position: one row per
+/// track (in stored order), one column per position. Shows which positions are
+/// contested, e.g. by several `atpos` constraints. Nothing is scored.
+pub fn handle_heatmap(
+    ctx: &ProgramContext,
+    tracklist_name: &str,
+    medium_name: &str,
+    samples: usize,
+    seed: u64,
+) -> CliResult {
+    let ser_tl = ctx.tracklist(tracklist_name)?;
+    ensure_has_tracks(ser_tl)?;
+    let medium = ctx.medium(medium_name)?.to_album_medium();
     let tracklist = Tracklist::from(&ser_tl.tracks);
+    let gaps = Gaps::of(&ser_tl.tracks);
+    let PositionCounts {
+        drawn,
+        fitting,
+        counts,
+    } = count_positions(&tracklist, &medium, &gaps, samples, seed);
+
+    println!(
+        "{}",
+        format!(
+            "Position heatmap of '{}' on medium '{}' ({} of {} sampled permutations fit):",
+            ser_tl.name, medium.name, fitting, drawn
+        )
+        .bold()
+        .cyan()
+    );
+    if fitting == 0 {
+        println!("  (no fitting permutation)");
+        return Ok(());
+    }
 
-    // Find the medium by name
-    let ser_medium = ctx
-        .mediums
+    let width = tracklist
+        .0
         .iter()
-        .find(|m| m.name.eq_ignore_ascii_case(medium_name));
-    if ser_medium.is_none() {
-        eprintln!("Medium '{}' not found", medium_name);
-        return;
+        .map(|t| t.title.chars().count())
+        .max()
+        .unwrap_or(0);
+    let header: String = (1..=counts.len())
+        .map(|pos| format!("{:>5}", pos))
+        .collect();
+    println!("{:width$}{}", "", header.bold(), width = width);
+    for (track, row) in tracklist.0.iter().zip(&counts) {
+        let cells: String = row
+            .iter()
+            .map(|&count| heatmap_cell(count, fitting))
+            .collect();
+        println!("{:width$}{}", track.title, cells, width = width);
     }
-    let ser_medium = ser_medium.unwrap();
-    let medium = ser_medium.to_album_medium();
 
-    // Convert constraints to albumseq constraints
-    let constraints: Vec<AlbumConstraint> =
-        ctx.constraints.iter().cloned().map(|c| c.into()).collect();
+    Ok(())
+}
 
-    // Create permutations iterator
-    let perms = TracklistPermutations::new(&tracklist.0);
+/// Handles showing how a tracklist's stored order splits on a medium, without scoring
+/// or permuting: the tracks of each side with the side's duration and how full it is.
+pub fn handle_layout(
+    ctx: &ProgramContext,
+    tracklist_name: &str,
+    medium_name: &str,
+    side_labels: SideLabels,
+) -> CliResult {
+    let ser_tl = ctx.tracklist(tracklist_name)?;
+    let medium = ctx.medium(medium_name)?.to_album_medium();
+    let tracklist = Tracklist::from(&ser_tl.tracks);
+    let gaps = Gaps::of(&ser_tl.tracks);
+    let sides = split_tracklist_by_side(&tracklist, &medium, &gaps);
 
-    // Score permutations, filter by min_score if provided, keep top `count` by descending score
-    let mut scored_perms: Vec<(usize, Tracklist)> = perms
-        .map(|perm| {
-            let tl = Tracklist(perm.into_iter().cloned().collect());
-            let score = score_tracklist(&tl, &constraints, &medium);
-            (score, tl)
-        })
-        .filter(|(score, tl)| medium.fits(tl) && min_score.map_or(true, |min| *score >= min))
-        .collect();
+    println!(
+        "{}",
+        format!(
+            "Layout of the stored order of '{}' on medium '{}' ({} sides x {}):",
+            ser_tl.name,
+            medium.name,
+            medium.sides,
+            format_duration(medium.max_duration_per_side)
+        )
+        .bold()
+        .cyan()
+    );
 
-    scored_perms.sort_by(|a, b| b.0.cmp(&a.0)); // descending by score
+    let mut table = Table::new();
+    table.set_format(*format::consts::FORMAT_BOX_CHARS);
+    table.set_titles(Row::new(vec![
+        Cell::new("Side").style_spec("bFc"),
+        Cell::new("Tracks").style_spec("bFc"),
+        Cell::new("Duration").style_spec("bFc"),
+        Cell::new("Fill").style_spec("bFc"),
+    ]));
+    for (side_idx, side_tracks) in sides.iter().enumerate() {
+        let titles: Vec<String> = side_tracks
+            .iter()
+            .map(|t| format!("{} ({})", t.title, format_duration(t.duration)))
+            .collect();
+        let duration = gaps.side_duration(side_tracks);
+        let fill = if medium.max_duration_per_side > 0.0 {
+            format!("{:.0}%", duration / medium.max_duration_per_side * 100.0)
+        } else {
+            "-".to_string()
+        };
+        let over =
+            side_idx >= medium.sides || duration > medium.max_duration_per_side + FIT_TOLERANCE;
+        table.add_row(Row::new(vec![
+            Cell::new(&side_labels.label(side_idx)),
+            Cell::new(&titles.join("\n")),
+            Cell::new(&format_duration(duration)),
+            Cell::new(&fill).style_spec(if over { "Fr" } else { "" }),
+        ]));
+    }
+    table.printstd();
+    println!();
 
-    if let Some(min) = min_score {
+    if !fits_medium(&tracklist, &medium, &gaps) {
         println!(
             "{}",
-            format!(
-                "Top {} permutations for tracklist '{}' on medium '{}' with score >= {}:",
-                count, tracklist_name, medium_name, min
-            )
-            .bold()
-            .cyan()
+            "The stored order doesn't fit this medium; see propose --diagnose.".red()
         );
-    } else {
-        println!(
-            "{}",
-            format!(
-                "Top {} permutations for tracklist '{}' on medium '{}':",
-                count, tracklist_name, medium_name
-            )
-            .bold()
-            .cyan()
+    }
+
+    Ok(())
+}
+
+/// Handles rendering the printable sheet of a tracklist's stored order on a medium,
+/// written to `output` or printed when no path is given.
+pub fn handle_sheet(
+    ctx: &ProgramContext,
+    tracklist_name: &str,
+    medium_name: &str,
+    output: &Option<PathBuf>,
+    side_labels: SideLabels,
+) -> CliResult {
+    let ser_tl = ctx.tracklist(tracklist_name)?;
+    let medium = ctx.medium(medium_name)?.to_album_medium();
+    let tracklist = Tracklist::from(&ser_tl.tracks);
+    let gaps = Gaps::of(&ser_tl.tracks);
+
+    if !fits_medium(&tracklist, &medium, &gaps) {
+        warn!(
+            "The stored order of '{}' doesn't fit medium '{}'; the sheet shows extra sides",
+            ser_tl.name, medium.name
         );
     }
 
-    for (idx, (score, tl)) in scored_perms.into_iter().take(*count).enumerate() {
+    let sides = split_tracklist_by_side(&tracklist, &medium, &gaps);
+    let sheet = render_sheet(ser_tl, &sides, &gaps, side_labels);
+
+    match output {
+        Some(path) => {
+            fs::write(path, sheet)
+                .map_err(|e| CliError::Io(format!("Failed to write sheet {:?}: {}", path, e)))?;
+            status!("Wrote sheet for '{}' to {:?}", ser_tl.name, path);
+        }
+        None => print!("{}", sheet),
+    }
+
+    Ok(())
+}
+
+/// Handles showing the capacity of a medium.
+/// Prints sides, per-side max, total capacity, and any stored track that is
+/// longer than a single side and therefore can never be placed.
+pub fn handle_capacity(ctx: &ProgramContext, medium_name: &str) -> CliResult {
+    let medium = ctx.medium(medium_name)?.to_album_medium();
+
+    let total_capacity = medium.max_duration_per_side * medium.sides as Duration;
+
+    println!("{}", format!("Medium: {}", medium.name).bold().cyan());
+    println!("{} {}", "Sides:".bold(), medium.sides);
+    println!(
+        "{} {}",
+        "Max duration per side:".bold(),
+        format_duration(medium.max_duration_per_side)
+    );
+    println!(
+        "{} {} ({} x {})",
+        "Total capacity:".bold(),
+        format_duration(total_capacity),
+        medium.sides,
+        format_duration(medium.max_duration_per_side)
+    );
+
+    let longest = ctx
+        .tracklists
+        .iter()
+        .flat_map(|tl| tl.tracks.0.iter().map(move |t| (tl, t)))
+        .max_by(|(_, a), (_, b)| a.duration.total_cmp(&b.duration));
+
+    if let Some((tl, track)) = longest {
         println!(
-            "{} {}",
-            "Permutation".yellow().bold(),
-            format!("#{}", idx + 1).yellow().bold()
+            "{} {} ({}) in tracklist '{}'",
+            "Longest track:".bold(),
+            track.title,
+            format_duration(track.duration),
+            tl.name
         );
+    }
+
+    let unplaceable: Vec<_> = ctx
+        .tracklists
+        .iter()
+        .flat_map(|tl| tl.tracks.0.iter().map(move |t| (tl, t)))
+        .filter(|(_, t)| t.duration > medium.max_duration_per_side + FIT_TOLERANCE)
+        .collect();
+
+    if unplaceable.is_empty() {
+        println!("{}", "Every stored track fits on a single side.".green());
+    } else {
         println!(
-            "{} {}",
-            "Score:".green().bold(),
-            score.to_string().green().bold()
+            "{}",
+            "Tracks longer than one side (can never be placed):"
+                .red()
+                .bold()
         );
+        for (tl, t) in unplaceable {
+            println!(
+                "  {} ({}) in tracklist '{}', exceeds a side by {}",
+                t.title,
+                format_duration(t.duration),
+                tl.name,
+                format_duration(t.duration - medium.max_duration_per_side)
+            );
+        }
+    }
 
-        let sides = split_tracklist_by_side(&tl, &medium);
+    Ok(())
+}
 
-        let mut table = Table::new();
-        table.set_format(*format::consts::FORMAT_BOX_CHARS);
-        table.set_titles(Row::new(vec![
-            Cell::new("#").style_spec("bFc"),
-            Cell::new("Title").style_spec("bFc"),
-            Cell::new("Duration").style_spec("bFc"),
-            Cell::new("Side").style_spec("bFc"),
-        ]));
+/// Maximum number of orders tried per medium by `fit-medium` before giving up.
+const FIT_SEARCH_LIMIT: usize = 100_000;
+
+/// Handles finding the stored media a tracklist fits on in at least one order.
+///
+/// Media are listed by total capacity, smallest first. A medium is ruled out
+/// quickly when the total duration exceeds its capacity or a track is longer
+/// than a side; otherwise up to [`FIT_SEARCH_LIMIT`] orders are tried.
+pub fn handle_fit_medium(ctx: &ProgramContext, tracklist_name: &str) -> CliResult {
+    let ser_tl = ctx.tracklist(tracklist_name)?;
+    let tracklist = Tracklist::from(&ser_tl.tracks);
+    let gaps = Gaps::of(&ser_tl.tracks);
+    let total: Duration = tracklist.0.iter().map(|t| t.duration).sum();
+    let longest: Duration = tracklist.0.iter().map(|t| t.duration).fold(0.0, f64::max);
+
+    let mut media: Vec<AlbumMedium> = ctx.mediums.iter().map(|m| m.to_album_medium()).collect();
+    media.sort_by(|a, b| {
+        let capacity = |m: &AlbumMedium| m.max_duration_per_side * m.sides as Duration;
+        capacity(a).total_cmp(&capacity(b))
+    });
 
-        let mut track_idx = 1;
-        for (side_idx, side_tracks) in sides.iter().enumerate() {
-            for t in side_tracks {
-                table.add_row(Row::new(vec![
-                    Cell::new(&format!("{}", track_idx)),
-                    Cell::new(&t.title),
-                    Cell::new(&format_duration(t.duration)),
-                    Cell::new(&format!("{}", side_idx + 1)),
-                ]));
-                track_idx += 1;
+    println!(
+        "{}",
+        format!(
+            "Media for tracklist '{}' ({}), smallest first:",
+            ser_tl.name,
+            format_duration(total)
+        )
+        .bold()
+        .cyan()
+    );
+
+    let mut table = Table::new();
+    table.set_format(*format::consts::FORMAT_BOX_CHARS);
+    table.set_titles(Row::new(vec![
+        Cell::new("Medium").style_spec("bFc"),
+        Cell::new("Sides").style_spec("bFc"),
+        Cell::new("Per side").style_spec("bFc"),
+        Cell::new("Capacity").style_spec("bFc"),
+        Cell::new("Fits").style_spec("bFc"),
+    ]));
+
+    let mut smallest = None;
+    for medium in &media {
+        let capacity = medium.max_duration_per_side * medium.sides as Duration;
+        let verdict = if total > capacity + FIT_TOLERANCE * medium.sides as Duration {
+            "no (too long)".to_string()
+        } else if longest > medium.max_duration_per_side + FIT_TOLERANCE {
+            "no (track longer than a side)".to_string()
+        } else {
+            let mut tried = 0usize;
+            let found = TracklistPermutations::new(&tracklist.0)
+                .take(FIT_SEARCH_LIMIT)
+                .inspect(|_| tried += 1)
+                .any(|perm| {
+                    let tl = Tracklist(perm.iter().map(|t| (*t).clone()).collect());
+                    fits_medium(&tl, medium, &gaps)
+                });
+            debug!("Tried {} orders on '{}'", tried, medium.name);
+            if found {
+                smallest.get_or_insert(medium.name.as_str());
+                "yes".to_string()
+            } else if tried < FIT_SEARCH_LIMIT {
+                "no (no order fits)".to_string()
+            } else {
+                format!("unknown (first {} orders don't fit)", FIT_SEARCH_LIMIT)
             }
-        }
+        };
 
-        // Add total row
-        let total_duration: Duration = tl.0.iter().map(|t| t.duration).sum();
         table.add_row(Row::new(vec![
-            Cell::new(""),
-            Cell::new("TOTAL").style_spec("bFc"),
-            Cell::new(&format_duration(total_duration)).style_spec("bFc"),
-            Cell::new(""),
+            Cell::new(&medium.name),
+            Cell::new(&medium.sides.to_string()),
+            Cell::new(&format_duration(medium.max_duration_per_side)),
+            Cell::new(&format_duration(capacity)),
+            Cell::new(&verdict),
         ]));
+    }
 
-        table.printstd();
-        println!();
+    table.printstd();
+    println!();
+
+    match smallest {
+        Some(name) => println!("{} {}", "Smallest fitting medium:".bold(), name),
+        None => println!("{}", "No stored medium fits this tracklist.".red()),
+    }
+
+    Ok(())
+}
+
+/// Handles splitting a tracklist into `into` consecutive parts that each fit the medium.
+/// Tracks are packed greedily in their stored order.
+/// Returns an error if the tracklist can't be split into at most `into` fitting parts.
+pub fn handle_split(
+    ctx: &mut ProgramContext,
+    tracklist_name: &str,
+    medium_name: &str,
+    into: usize,
+) -> CliResult {
+    let ser_tl = ctx.tracklist(tracklist_name)?;
+    let ser_medium = ctx.medium(medium_name)?;
+    let medium = ser_medium.to_album_medium();
+
+    let mut parts: Vec<SerTracklist> = Vec::new();
+    let mut current = SerTracklist::default();
+
+    for track in &ser_tl.tracks.0 {
+        let mut candidate = current.clone();
+        candidate.0.push(track.clone());
+
+        if fits_medium(&Tracklist::from(&candidate), &medium, &Gaps::of(&candidate)) {
+            current = candidate;
+            continue;
+        }
+
+        let single = SerTracklist(vec![track.clone()]);
+        if !fits_medium(&Tracklist::from(&single), &medium, &Gaps::default()) {
+            return Err(CliError::User(format!(
+                "Track '{}' ({}) doesn't fit on medium '{}' on its own",
+                track.title,
+                format_duration(track.duration),
+                ser_medium.name
+            )));
+        }
+
+        parts.push(current);
+        current = single;
+    }
+
+    if !current.0.is_empty() {
+        parts.push(current);
+    }
+
+    if parts.len() > into {
+        return Err(CliError::User(format!(
+            "Tracklist '{}' needs {} parts on medium '{}', more than the {} requested",
+            ser_tl.name,
+            parts.len(),
+            ser_medium.name,
+            into
+        )));
+    }
+
+    if parts.len() < into {
+        status!(
+            "Tracklist '{}' only needs {} of the {} requested parts",
+            ser_tl.name,
+            parts.len(),
+            into
+        );
+    }
+
+    let base_name = ser_tl.name.clone();
+    for (i, part) in parts.into_iter().enumerate() {
+        let total: Duration = part.0.iter().map(|t| t.duration).sum();
+        let name = format!("{}-disc{}", base_name, i + 1);
+        status!(
+            "{}: {} tracks, {}",
+            name,
+            part.0.len(),
+            format_duration(total)
+        );
+        ctx.add_or_replace_ser_tracklist(name, part);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn track(title: &str, duration: Duration) -> SerTrack {
+        SerTrack {
+            title: title.to_string(),
+            duration,
+            ..Default::default()
+        }
+    }
+
+    fn constraint(kind: SerConstraintKind, weight: usize) -> SerConstraint {
+        SerConstraint {
+            kind,
+            weight,
+            enabled: true,
+        }
+    }
+
+    /// A context with tracklist "Album" (A 4:00, B 3:00, C 5:00, D 2:00), medium "LP"
+    /// (2 sides x 8:00) and a constraint opening with C.
+    fn context() -> ProgramContext {
+        let mut ctx = ProgramContext::default();
+        ctx.tracklists.push(NamedSerTracklist {
+            name: "Album".to_string(),
+            tracks: SerTracklist(vec![
+                track("A", 4.0),
+                track("B", 3.0),
+                track("C", 5.0),
+                track("D", 2.0),
+            ]),
+            ..Default::default()
+        });
+        ctx.mediums.push(SerMedium {
+            name: "LP".to_string(),
+            sides: 2,
+            max_duration_per_side: 8.0,
+            ..Default::default()
+        });
+        ctx.constraints.push(constraint(
+            SerConstraintKind::AtPosition("C".to_string(), 0),
+            2,
+        ));
+        ctx
+    }
+
+    fn options(cache_path: Option<PathBuf>) -> ProposeOptions {
+        ProposeOptions {
+            count: 3,
+            search: Search::Exhaustive,
+            min_score: None,
+            diagnose: false,
+            cache_path,
+            side_labels: SideLabels::Numbers,
+            format: OutputFormat::Table,
+            stats: false,
+            measure: false,
+            include_original: false,
+            prefer_original: false,
+            sort_by: SortBy::Score,
+            group_by: None,
+            anchor: None,
+            fit_weight: 0.0,
+            allow_partial: false,
+        }
+    }
+
+    /// Proposes "Album" on "LP", returning the score and titles of each proposal.
+    fn propose(ctx: &ProgramContext, opts: &ProposeOptions) -> Vec<(Score, Vec<String>)> {
+        let shown = propose_on_medium(
+            ctx,
+            ctx.tracklist("Album").unwrap(),
+            ctx.medium("LP").unwrap(),
+            opts,
+        )
+        .unwrap();
+        shown
+            .proposals
+            .into_iter()
+            .map(|p| (p.score, p.titles))
+            .collect()
+    }
+
+    fn titles(tracks: &[Track]) -> Vec<String> {
+        let mut titles: Vec<String> = tracks.iter().map(|t| t.title.clone()).collect();
+        titles.sort();
+        titles
+    }
+
+    #[test]
+    fn set_tracks_never_cross_disc_boundaries() {
+        // Each disc is too long for one side of "LP", so each takes both of its sides
+        let discs = [
+            SerTracklist(vec![track("A", 4.0), track("B", 3.0), track("C", 5.0)]),
+            SerTracklist(vec![track("X", 3.0), track("Y", 4.0), track("Z", 2.0)]),
+        ];
+        let medium = context().mediums[0].to_album_medium();
+        let disc_orders: Vec<Vec<Tracklist>> = discs
+            .iter()
+            .map(|disc| fitting_disc_orders(disc, &medium))
+            .collect();
+        let source = SerTracklist(discs.iter().flat_map(|d| d.0.clone()).collect());
+        let constraints = [constraint(
+            SerConstraintKind::OnSameSide("C".to_string(), "X".to_string()),
+            1,
+        )];
+        let scorer = Scorer::new(&constraints, &medium, &source, &[]).with_discs(vec![3, 3]);
+
+        let orders_per_disc: Vec<usize> = disc_orders.iter().map(Vec::len).collect();
+        let combinations = set_combinations(&orders_per_disc, MAX_SET_COMBINATIONS, 0);
+        assert_eq!(
+            combinations.len(),
+            orders_per_disc.iter().product::<usize>()
+        );
+
+        for choice in &combinations {
+            let tl = Tracklist(
+                choice
+                    .iter()
+                    .zip(&disc_orders)
+                    .flat_map(|(&i, orders)| orders[i].0.iter().cloned())
+                    .collect(),
+            );
+            assert_eq!(titles(&tl.0[..3]), ["A", "B", "C"]);
+            assert_eq!(titles(&tl.0[3..]), ["X", "Y", "Z"]);
+
+            let sides = scorer.sides(&tl);
+            assert_eq!(sides.len(), 4);
+            for (side, disc) in sides.iter().zip(["ABC", "ABC", "XYZ", "XYZ"]) {
+                assert!(!side.is_empty());
+                assert!(side.iter().all(|t| disc.contains(t.title.as_str())));
+            }
+            assert_eq!(scorer.score(&tl), 0.0);
+        }
+    }
+
+    #[test]
+    fn set_combinations_beyond_the_limit_are_distinct_draws() {
+        let orders_per_disc = [3, 4, 5];
+        let all = set_combinations(&orders_per_disc, 100, 7);
+        assert_eq!(all.len(), 60);
+        assert_eq!(all.iter().collect::<HashSet<_>>().len(), 60);
+        assert_eq!(all[0], [0, 0, 0]);
+        assert_eq!(all[1], [0, 0, 1]);
+
+        let drawn = set_combinations(&orders_per_disc, 10, 7);
+        assert_eq!(drawn.len(), 10);
+        assert_eq!(drawn.iter().collect::<HashSet<_>>().len(), 10);
+        assert!(drawn.iter().all(|choice| all.contains(choice)));
+        assert_eq!(drawn, set_combinations(&orders_per_disc, 10, 7));
+
+        // Too many to count
+        let huge = set_combinations(&[usize::MAX, 2], 3, 7);
+        assert_eq!(huge.iter().collect::<HashSet<_>>().len(), 3);
+        assert!(huge.iter().all(|choice| choice[1] < 2));
+    }
+
+    #[test]
+    fn preset_adds_its_constraints() {
+        let mut ctx = ProgramContext::default();
+        handle_add_preset(&mut ctx, "Vinyl-Audiophile").unwrap();
+        // Applying it again replaces rather than duplicates
+        handle_add_preset(&mut ctx, "vinyl-audiophile").unwrap();
+
+        let added: Vec<(&str, usize)> = ctx
+            .constraints
+            .iter()
+            .map(|c| (constraint_keyword(&c.kind), c.weight))
+            .collect();
+        assert_eq!(
+            added,
+            [
+                ("longestnotcloser", 3),
+                ("sidebalance", 2),
+                ("fillsides", 2)
+            ]
+        );
+        assert_eq!(ctx.constraints[1].kind, SerConstraintKind::SideBalance(2.0));
+        assert!(ctx.constraints.iter().all(|c| c.enabled));
+
+        assert!(matches!(
+            handle_add_preset(&mut ctx, "cd-basic"),
+            Err(CliError::User(_))
+        ));
+        assert_eq!(ctx.constraints.len(), 3);
+    }
+
+    #[test]
+    fn sampling_draws_exactly_the_requested_distinct_permutations() {
+        let tracks: Vec<Track> = (1..=6)
+            .map(|i| Track {
+                title: format!("T{}", i),
+                duration: i as Duration,
+            })
+            .collect();
+        let tracks: Vec<&Track> = tracks.iter().collect();
+        let titles = |drawn: &[Vec<&Track>]| -> Vec<Vec<String>> {
+            drawn
+                .iter()
+                .map(|perm| perm.iter().map(|t| t.title.clone()).collect())
+                .collect()
+        };
+        let distinct =
+            |drawn: &[Vec<&Track>]| titles(drawn).into_iter().collect::<HashSet<_>>().len();
+
+        let drawn = sample_permutations(&tracks, 100, 42, false, false);
+        assert_eq!(drawn.len(), 100);
+        assert_eq!(distinct(&drawn), 100);
+        assert_eq!(
+            titles(&drawn),
+            titles(&sample_permutations(&tracks, 100, 42, false, false))
+        );
+
+        // As many samples as permutations, or more: every one of them
+        for samples in [720, 1000] {
+            let drawn = sample_permutations(&tracks, samples, 42, false, false);
+            assert_eq!(drawn.len(), 720);
+            assert_eq!(distinct(&drawn), 720);
+        }
+
+        let drawn = sample_permutations(&tracks, 500, 42, true, true);
+        assert_eq!(drawn.len(), 24);
+        assert_eq!(distinct(&drawn), 24);
+        assert!(
+            drawn
+                .iter()
+                .all(|perm| perm[0].title == "T1" && perm[5].title == "T6")
+        );
+    }
+
+    #[test]
+    fn heatmap_rows_and_columns_sum_to_the_fitting_permutations() {
+        let ctx = context();
+        let tracklist = Tracklist::from(&ctx.tracklists[0].tracks);
+        let gaps = Gaps::of(&ctx.tracklists[0].tracks);
+        let medium = ctx.mediums[0].to_album_medium();
+
+        for samples in [10, 1000] {
+            let PositionCounts {
+                drawn,
+                fitting,
+                counts,
+            } = count_positions(&tracklist, &medium, &gaps, samples, 3);
+            assert_eq!(drawn, samples.min(24));
+            assert!(fitting > 0);
+            assert_eq!(counts.len(), 4);
+            for row in &counts {
+                assert_eq!(row.len(), 4);
+                assert_eq!(row.iter().sum::<usize>(), fitting);
+            }
+            for pos in 0..4 {
+                assert_eq!(counts.iter().map(|row| row[pos]).sum::<usize>(), fitting);
+            }
+        }
+    }
+
+    #[test]
+    fn heatmap_cells_tell_rare_from_never() {
+        assert_eq!(heatmap_cell(0, 300), "    .");
+        assert_eq!(heatmap_cell(1, 300), "  <1%");
+        assert_eq!(heatmap_cell(3, 300), "   1%");
+        assert_eq!(heatmap_cell(300, 300), " 100%");
+    }
+
+    #[test]
+    fn split_partitions_a_long_tracklist_into_fitting_parts() {
+        let mut ctx = context();
+        ctx.tracklists.push(NamedSerTracklist {
+            name: "Long".to_string(),
+            tracks: SerTracklist(vec![
+                track("A", 4.0),
+                track("B", 3.0),
+                track("C", 5.0),
+                track("D", 2.0),
+                track("E", 4.0),
+                track("F", 4.0),
+            ]),
+            ..Default::default()
+        });
+
+        assert!(matches!(
+            handle_split(&mut ctx, "Long", "LP", 1),
+            Err(CliError::User(_))
+        ));
+        handle_split(&mut ctx, "Long", "LP", 2).unwrap();
+
+        let medium = ctx.mediums[0].to_album_medium();
+        let mut titles = Vec::new();
+        for name in ["Long-disc1", "Long-disc2"] {
+            let part = &ctx.tracklist(name).unwrap().tracks;
+            assert!(fits_medium(
+                &Tracklist::from(part),
+                &medium,
+                &Gaps::of(part)
+            ));
+            titles.extend(part.0.iter().map(|t| t.title.clone()));
+        }
+        assert_eq!(titles, ["A", "B", "C", "D", "E", "F"]);
+    }
+
+    #[test]
+    fn copy_medium_inherits_unless_overridden() {
+        let mut ctx = context();
+        ctx.mediums[0].rpm = Some(45.0);
+        ctx.mediums[0]
+            .constraints
+            .push(constraint(SerConstraintKind::ShortToInner(2), 1));
+
+        handle_copy_medium(&mut ctx, "LP", &"Copy".to_string(), None, None).unwrap();
+        let copy = ctx.medium("Copy").unwrap();
+        assert_eq!(copy.sides, 2);
+        assert_eq!(copy.max_duration_per_side, 8.0);
+        assert_eq!(copy.rpm, Some(45.0));
+        assert_eq!(copy.constraints.len(), 1);
+
+        handle_copy_medium(&mut ctx, "LP", &"Short".to_string(), Some(4), Some(6.0)).unwrap();
+        let short = ctx.medium("Short").unwrap();
+        assert_eq!(short.sides, 4);
+        assert_eq!(short.max_duration_per_side, 6.0);
+        assert_eq!(short.rpm, Some(45.0));
+        assert_eq!(short.constraints.len(), 1);
+
+        assert!(handle_copy_medium(&mut ctx, "LP", &"copy".to_string(), None, None).is_err());
+        assert!(handle_copy_medium(&mut ctx, "CD", &"Other".to_string(), None, None).is_err());
+    }
+
+    #[test]
+    fn proposing_an_empty_tracklist_is_a_user_error() {
+        let mut ctx = context();
+        ctx.tracklists.push(NamedSerTracklist {
+            name: "Empty".to_string(),
+            ..Default::default()
+        });
+        let opts = options(None);
+
+        let err = handle_propose(&ctx, "Empty", "LP", None, None, &opts).unwrap_err();
+        assert!(matches!(
+            &err,
+            CliError::User(message) if message.contains("Tracklist 'Empty' has no tracks")
+        ));
+        assert!(handle_propose_all(&ctx, "Empty", &opts).is_err());
+        assert!(handle_propose(&ctx, "Album", "LP", None, None, &opts).is_ok());
+    }
+
+    #[test]
+    fn sampling_can_keep_the_first_and_last_tracks_in_place() {
+        let tracks: Vec<Track> = (1..=6)
+            .map(|i| Track {
+                title: format!("T{}", i),
+                duration: i as Duration,
+            })
+            .collect();
+        let tracks: Vec<&Track> = tracks.iter().collect();
+
+        let drawn = sample_permutations(&tracks, 50, 7, true, false);
+        assert_eq!(drawn.len(), 50);
+        assert!(drawn.iter().all(|perm| perm[0].title == "T1"));
+        assert!(drawn.iter().any(|perm| perm[5].title != "T6"));
+
+        let drawn = sample_permutations(&tracks, 10, 7, true, true);
+        assert!(
+            drawn
+                .iter()
+                .all(|perm| perm[0].title == "T1" && perm[5].title == "T6")
+        );
+
+        // Only the 24 orders of the four middle tracks exist
+        let drawn = sample_permutations(&tracks, 100, 7, true, true);
+        assert_eq!(drawn.len(), 24);
+        assert!(drawn.iter().all(|perm| perm.len() == 6));
+    }
+
+    #[test]
+    fn group_by_first_side_counts_each_group() {
+        let ctx = context();
+        let album = Tracklist::from(&ctx.tracklists[0].tracks);
+        let tracks = |positions: [usize; 4]| {
+            Tracklist(positions.iter().map(|&i| album.0[i].clone()).collect())
+        };
+        let medium = ctx.mediums[0].to_album_medium();
+        let groups = group_by_first_side(
+            vec![
+                (2.0, tracks([2, 1, 0, 3])),
+                (1.0, tracks([0, 1, 2, 3])),
+                (1.0, tracks([1, 2, 3, 0])),
+                (0.0, tracks([1, 0, 3, 2])),
+            ],
+            &medium,
+            &Gaps::of(&ctx.tracklists[0].tracks),
+        );
+        let sizes: Vec<(Score, usize)> = groups
+            .iter()
+            .map(|((score, _), size)| (*score, *size))
+            .collect();
+        assert_eq!(sizes, [(2.0, 2), (1.0, 2)]);
+    }
+
+    #[test]
+    fn scale_weights_of_all_constraints_or_one_kind() {
+        let mut ctx = context();
+        ctx.constraints.push(constraint(
+            SerConstraintKind::Adjacent("A".to_string(), "B".to_string()),
+            3,
+        ));
+        let weights = |ctx: &ProgramContext| -> Vec<usize> {
+            ctx.constraints.iter().map(|c| c.weight).collect()
+        };
+
+        handle_scale_weights(&mut ctx, 2.0, &None).unwrap();
+        assert_eq!(weights(&ctx), [4, 6]);
+
+        handle_scale_weights(&mut ctx, 0.5, &Some("ADJACENT".to_string())).unwrap();
+        assert_eq!(weights(&ctx), [4, 3]);
+
+        // Rounded to the nearest, but never below 1
+        handle_scale_weights(&mut ctx, 0.1, &None).unwrap();
+        assert_eq!(weights(&ctx), [1, 1]);
+
+        handle_scale_weights(&mut ctx, 3.0, &Some("onsameside".to_string())).unwrap();
+        assert_eq!(weights(&ctx), [1, 1]);
+
+        assert!(handle_scale_weights(&mut ctx, 0.0, &None).is_err());
+        assert!(handle_scale_weights(&mut ctx, f64::NAN, &None).is_err());
+    }
+
+    #[test]
+    fn side_count_is_inferred_from_the_total() {
+        let sides =
+            |total: Duration| SerMedium::for_total_duration("M".to_string(), total, 22.0).sides;
+        assert_eq!(sides(44.0), 2);
+        assert_eq!(sides(45.0), 3);
+        assert_eq!(sides(0.0), 1);
+
+        let ctx = context();
+        assert_eq!(sides_needed_for(&ctx, "Album", 8.0).unwrap(), 2);
+        // 14:00 fits in 3 sides of 5:00, but A | B | C | D doesn't pack into them
+        assert_eq!(sides_needed_for(&ctx, "Album", 5.0).unwrap(), 4);
+        assert!(sides_needed_for(&ctx, "Album", 4.5).is_err());
+    }
+
+    #[test]
+    fn side_logic_is_a_no_op_on_a_single_side() {
+        let same_side = SerConstraintKind::OnSameSide("A".to_string(), "B".to_string());
+        assert_eq!(
+            side_logic_noop(&same_side, 1).as_deref(),
+            Some("always satisfied with a single side")
+        );
+        assert_eq!(side_logic_noop(&same_side, 2), None);
+
+        assert!(side_logic_noop(&SerConstraintKind::DiscBalance(1.0), 2).is_some());
+        assert_eq!(
+            side_logic_noop(&SerConstraintKind::DiscBalance(1.0), 4),
+            None
+        );
+        assert_eq!(
+            side_logic_noop(&SerConstraintKind::SideSortedByDuration(1, true), 1).as_deref(),
+            Some("always satisfied, the medium has no side 1")
+        );
+
+        // Position constraints don't depend on sides at all
+        let at_position = SerConstraintKind::AtPosition("A".to_string(), 0);
+        assert!(!is_side_based(&at_position));
+        assert_eq!(side_logic_noop(&at_position, 1), None);
+        assert!(is_side_based(&same_side));
+    }
+
+    #[test]
+    fn standard_media_are_added_without_replacing_existing_ones() {
+        let mut ctx = context();
+        ctx.mediums.push(SerMedium {
+            name: "cd".to_string(),
+            sides: 1,
+            max_duration_per_side: 74.0,
+            ..Default::default()
+        });
+        let cd = |ctx: &ProgramContext| {
+            let cd = ctx.medium("CD").unwrap();
+            (cd.name.clone(), cd.max_duration_per_side)
+        };
+
+        handle_add_standard_media(&mut ctx, false).unwrap();
+        assert_eq!(ctx.mediums.len(), 2 + STANDARD_MEDIA.len() - 1);
+        for (name, sides, max_duration) in STANDARD_MEDIA.iter().filter(|m| m.0 != "CD") {
+            let medium = ctx.medium(name).unwrap();
+            assert_eq!(
+                (medium.sides, medium.max_duration_per_side),
+                (*sides, *max_duration)
+            );
+        }
+        assert_eq!(cd(&ctx), ("cd".to_string(), 74.0));
+        assert!(ctx.medium("LP").is_ok());
+
+        handle_add_standard_media(&mut ctx, true).unwrap();
+        assert_eq!(ctx.mediums.len(), 2 + STANDARD_MEDIA.len() - 1);
+        assert_eq!(cd(&ctx), ("CD".to_string(), 80.0));
+    }
+
+    #[test]
+    fn allow_partial_proposes_subsets_that_fill_the_medium() {
+        let mut ctx = context();
+        // 20:00 in all, for an LP holding 16:00
+        ctx.tracklists[0].tracks.0.push(track("E", 6.0));
+        let durations: std::collections::HashMap<&str, Duration> =
+            [("A", 4.0), ("B", 3.0), ("C", 5.0), ("D", 2.0), ("E", 6.0)].into();
+
+        assert!(propose(&ctx, &options(None)).is_empty());
+
+        let proposals = propose(
+            &ctx,
+            &ProposeOptions {
+                allow_partial: true,
+                ..options(None)
+            },
+        );
+        assert_eq!(proposals.len(), 3);
+        for (_, titles) in &proposals {
+            let total: Duration = titles.iter().map(|t| durations[t.as_str()]).sum();
+            assert!(total <= 16.0, "{:?}", titles);
+            // None of the tracks left off would still fit
+            for (title, duration) in &durations {
+                if !titles.iter().any(|t| t == title) {
+                    assert!(
+                        total + duration > 16.0,
+                        "{:?} leaves room for {}",
+                        titles,
+                        title
+                    );
+                }
+            }
+        }
+        // Subsets opening with C lead
+        assert_eq!(proposals[0].0, 2.0);
+        assert_eq!(proposals[0].1[0], "C");
+    }
+
+    #[test]
+    fn disc_orders_stop_at_the_cap() {
+        // 8! = 40320 orders, all fitting
+        let disc = SerTracklist(
+            ["A", "B", "C", "D", "E", "F", "G", "H"]
+                .iter()
+                .map(|title| track(title, 1.0))
+                .collect(),
+        );
+        let medium = AlbumMedium {
+            sides: 2,
+            max_duration_per_side: 20.0,
+            name: "Long".to_string(),
+        };
+        let orders = fitting_disc_orders(&disc, &medium);
+        assert_eq!(orders.len(), MAX_DISC_ORDERS);
+        assert!(orders.iter().all(|order| order.0.len() == 8));
+    }
+
+    #[test]
+    fn scale_weights_covers_medium_constraints() {
+        let mut ctx = context();
+        ctx.mediums[0].constraints.push(constraint(
+            SerConstraintKind::Adjacent("A".to_string(), "B".to_string()),
+            3,
+        ));
+
+        handle_scale_weights(&mut ctx, 2.0, &None).unwrap();
+        assert_eq!(ctx.constraints[0].weight, 4);
+        assert_eq!(ctx.mediums[0].constraints[0].weight, 6);
+
+        handle_scale_weights(&mut ctx, 0.5, &Some("adjacent".to_string())).unwrap();
+        assert_eq!(ctx.constraints[0].weight, 4);
+        assert_eq!(ctx.mediums[0].constraints[0].weight, 3);
     }
 }
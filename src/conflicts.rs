@@ -0,0 +1,135 @@
+//! # Constraint Conflicts
+//!
+//! This module detects pairs of constraints that obviously contradict each other,
+//! without scoring any permutation: two tracks pinned to the same position, a track
+//! pinned to two positions, or a side sorted both ways. Such pairs can never both
+//! award their points, so one of them is usually a mistake. Disabled constraints
+//! are ignored.
+//!
+//! ## Example
+//! ```rust
+//! for conflict in find_conflicts(&ctx.constraints) {
+//!     println!("#{} and #{}: {}", conflict.first, conflict.second, conflict.reason);
+//! }
+//! ```
+
+use crate::context::{SerConstraint, SerConstraintKind};
+
+/// Two contradictory constraints, by index, and why they contradict each other.
+#[derive(Debug)]
+pub struct Conflict {
+    pub first: usize,
+    pub second: usize,
+    pub reason: String,
+}
+
+/// Returns every pair of enabled constraints that can't both be satisfied,
+/// ordered by the indices of the pair.
+pub fn find_conflicts(constraints: &[SerConstraint]) -> Vec<Conflict> {
+    let mut conflicts = Vec::new();
+    for (i, a) in constraints.iter().enumerate().filter(|(_, c)| c.enabled) {
+        for (j, b) in constraints.iter().enumerate().skip(i + 1) {
+            if !b.enabled {
+                continue;
+            }
+            if let Some(reason) =
+                conflict_between(&a.kind, &b.kind).or_else(|| conflict_between(&b.kind, &a.kind))
+            {
+                conflicts.push(Conflict {
+                    first: i,
+                    second: j,
+                    reason,
+                });
+            }
+        }
+    }
+    conflicts
+}
+
+/// Returns why two constraint kinds contradict each other, if they obviously do.
+/// Only checks one order of the pair; callers check both.
+fn conflict_between(a: &SerConstraintKind, b: &SerConstraintKind) -> Option<String> {
+    use SerConstraintKind::*;
+
+    match (a, b) {
+        (AtPosition(t1, p1), AtPosition(t2, p2)) if t1 != t2 && p1 == p2 => Some(format!(
+            "'{}' and '{}' are both required at position {}",
+            t1, t2, p1
+        )),
+        (AtPosition(t1, p1), AtPosition(t2, p2)) if t1 == t2 && p1 != p2 => Some(format!(
+            "'{}' is required at both position {} and position {}",
+            t1, p1, p2
+        )),
+        (AtPosition(t1, p), AtAnyPosition(t2, positions)) if t1 == t2 && !positions.contains(p) => {
+            Some(format!(
+                "'{}' is required at position {}, which isn't one of its allowed positions",
+                t1, p
+            ))
+        }
+        (AtAnyPosition(t1, ps1), AtAnyPosition(t2, ps2))
+            if t1 == t2 && !ps1.iter().any(|p| ps2.contains(p)) =>
+        {
+            Some(format!("'{}' has no position allowed by both", t1))
+        }
+        (SideSortedByDuration(s1, true), SideSortedByDuration(s2, false)) if s1 == s2 => {
+            Some(format!(
+                "side {} is required to be sorted both ascending and descending, \
+                 which only holds if its tracks all last as long",
+                s1
+            ))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn constraint(kind: SerConstraintKind) -> SerConstraint {
+        SerConstraint {
+            kind,
+            weight: 1,
+            enabled: true,
+        }
+    }
+
+    fn pairs(constraints: &[SerConstraint]) -> Vec<(usize, usize)> {
+        find_conflicts(constraints)
+            .iter()
+            .map(|c| (c.first, c.second))
+            .collect()
+    }
+
+    #[test]
+    fn contradictory_pairs_are_found_in_either_order() {
+        use SerConstraintKind::*;
+
+        let mut constraints = vec![
+            constraint(AtPosition("A".to_string(), 0)),
+            constraint(AtPosition("B".to_string(), 0)),
+            constraint(AtAnyPosition("A".to_string(), vec![2, 3])),
+            constraint(SideSortedByDuration(1, false)),
+            constraint(SideSortedByDuration(1, true)),
+            constraint(AtAnyPosition("A".to_string(), vec![0, 3])),
+            constraint(AtPosition("C".to_string(), 1)),
+        ];
+        assert_eq!(pairs(&constraints), [(0, 1), (0, 2), (3, 4)]);
+
+        let conflicts = find_conflicts(&constraints);
+        assert_eq!(
+            conflicts[0].reason,
+            "'A' and 'B' are both required at position 0"
+        );
+        assert_eq!(
+            conflicts[1].reason,
+            "'A' is required at position 0, which isn't one of its allowed positions"
+        );
+
+        // A pinned to 0 and allowed at 0 or 3 agree; 2 or 3 and 0 or 3 overlap
+        constraints[0].enabled = false;
+        assert_eq!(pairs(&constraints), [(3, 4)]);
+        constraints.push(constraint(AtAnyPosition("A".to_string(), vec![1])));
+        assert_eq!(pairs(&constraints), [(2, 7), (3, 4), (5, 7)]);
+    }
+}
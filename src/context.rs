@@ -2,7 +2,9 @@
 //!
 //! This module defines the data structures for the persistent context used by albumseq_cli.
 //! It provides serialization/deserialization for tracklists, media, and constraints,
-//! as well as loading and saving the context to disk.
+//! as well as loading and saving the context to disk. Context files are JSON, or TOML
+//! when the path ends in `.toml`. The path `-` reads a JSON context from stdin and
+//! writes it to stdout instead.
 //!
 //! ## Main Types
 //! - [`ProgramContext`]: The root struct containing all user data.
@@ -10,26 +12,83 @@
 //!
 //! ## Example
 //! ```rust
-//! let ctx = ProgramContext::load_or_create("context.json");
-//! ctx.save("context.json");
+//! let ctx = ProgramContext::load_or_create("context.json")?;
+//! ctx.save("context.json")?;
 //! ```
 
+use crate::error::{CliError, CliResult};
+use crate::scoring::Score;
+use crate::utils::{TrackOrder, closest_name, format_duration, split_duration_qualifier, status};
 use albumseq::{
     Constraint as AlbumConstraint, ConstraintKind as AlbumConstraintKind, Duration,
     Medium as AlbumMedium, Track, Tracklist,
 };
+use log::debug;
 use serde::{Deserialize, Serialize};
 use std::fs;
+use std::io::{self, Read, Write};
 use std::path::Path;
+use std::sync::OnceLock;
 
 /// The default path for the context file.
 pub const DEFAULT_CONTEXT_PATH: &str = "context.json";
 
+/// The context path standing for stdin (when loading) and stdout (when saving).
+pub const STDIO_CONTEXT_PATH: &str = "-";
+
+/// Returns true if the context path stands for stdin/stdout rather than a file.
+pub fn is_stdio_path(path: &Path) -> bool {
+    path == Path::new(STDIO_CONTEXT_PATH)
+}
+
+/// Whether JSON contexts are saved without indentation, set once at startup.
+static MINIFIED: OnceLock<bool> = OnceLock::new();
+
+/// Saves JSON contexts on a single line, without indentation, for the rest of the run.
+/// TOML contexts are unaffected. Only the first call has an effect.
+pub fn set_minified(minified: bool) {
+    let _ = MINIFIED.set(minified);
+}
+
+/// Maximum duration difference (in minutes, i.e. one second) between two tracks
+/// with the same title for them to count as duplicates.
+pub const DUPLICATE_DURATION_EPSILON: Duration = 1.0 / 60.0;
+
 /// Serializable representation of a track.
 #[derive(Serialize, Deserialize, Debug, Default, Clone)]
 pub struct SerTrack {
     pub title: String,
     pub duration: Duration,
+    /// Free-form labels such as "uptempo" or "acoustic".
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// Energy rating of the track, e.g. 1 (calm) to 10 (intense).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub energy: Option<u8>,
+    /// International Standard Recording Code, stored as 12 characters without hyphens.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub isrc: Option<String>,
+    /// Part of a continuous flow (e.g. a live set); a side break between two such
+    /// neighbouring tracks is a hard cut.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub continuous: bool,
+    /// Silence after the track (in minutes), counted toward the side's duration
+    /// when another track follows on the same side.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gap_after: Option<Duration>,
+    /// Performing artist, for compilations and samplers.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub artist: Option<String>,
+    /// Integrated loudness in LUFS, e.g. -9.5.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub loudness: Option<f64>,
+}
+
+impl SerTrack {
+    /// Returns true if the track carries the given tag (case-insensitive).
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.iter().any(|t| t.eq_ignore_ascii_case(tag))
+    }
 }
 
 impl From<&Track> for SerTrack {
@@ -38,6 +97,7 @@ impl From<&Track> for SerTrack {
         SerTrack {
             title: track.title.clone(),
             duration: track.duration,
+            ..Default::default()
         }
     }
 }
@@ -75,6 +135,30 @@ impl From<&SerTracklist> for Tracklist {
 pub struct NamedSerTracklist {
     pub name: String,
     pub tracks: SerTracklist,
+    /// Free-form notes such as "needs remastering".
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notes: Option<String>,
+    /// Catalog number of the release, e.g. the UPC/EAN barcode.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub catalog: Option<String>,
+}
+
+/// Speed (in RPM) of the side lengths that are scaled to a medium's `rpm`.
+pub const BASE_RPM: f64 = 100.0 / 3.0;
+
+/// Side length of a 12-inch record at [`BASE_RPM`], scaled to the speed of a medium
+/// added with an `rpm` but no side length.
+pub const BASE_SIDE_LENGTH: Duration = 22.0;
+
+/// Scales a side length given at [`BASE_RPM`] to the given speed. A groove of the
+/// same length plays for less time at a higher speed, so the side length is
+/// multiplied by `BASE_RPM / rpm`: 22:00 at 33⅓ RPM is about 16:18 at 45 RPM.
+/// Without a speed, the side length is returned as is.
+pub fn scale_for_rpm(max_duration_per_side: Duration, rpm: Option<f64>) -> Duration {
+    match rpm {
+        Some(rpm) if rpm > 0.0 => max_duration_per_side * BASE_RPM / rpm,
+        _ => max_duration_per_side,
+    }
 }
 
 /// Serializable representation of a medium (e.g., vinyl, CD).
@@ -83,9 +167,38 @@ pub struct SerMedium {
     pub name: String,
     pub sides: usize,
     pub max_duration_per_side: Duration,
+    /// Playback speed of a vinyl medium (e.g. 45). Unless a side length was given
+    /// with it, `max_duration_per_side` is [`BASE_SIDE_LENGTH`] scaled to this speed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rpm: Option<f64>,
+    /// Constraints applied, on top of the context's, whenever this medium is used
+    /// (e.g. inner-groove constraints for vinyl).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub constraints: Vec<SerConstraint>,
 }
 
 impl SerMedium {
+    /// Creates a medium with as many sides of `max_duration_per_side` as needed to
+    /// hold `total_duration` (at least one), and no constraints.
+    pub fn for_total_duration(
+        name: String,
+        total_duration: Duration,
+        max_duration_per_side: Duration,
+    ) -> Self {
+        let sides = if max_duration_per_side > 0.0 {
+            (total_duration / max_duration_per_side).ceil().max(1.0) as usize
+        } else {
+            1
+        };
+        SerMedium {
+            name,
+            sides,
+            max_duration_per_side,
+            rpm: None,
+            constraints: Vec::new(),
+        }
+    }
+
     /// Converts this `SerMedium` into an `AlbumMedium`.
     pub fn to_album_medium(&self) -> AlbumMedium {
         AlbumMedium {
@@ -97,12 +210,67 @@ impl SerMedium {
 }
 
 /// Serializable constraint kind.
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+///
+/// The first three kinds map directly onto `albumseq` constraints; the others are
+/// specific to albumseq_cli and scored in [`crate::scoring`].
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 #[serde(tag = "kind", content = "data")]
 pub enum SerConstraintKind {
     AtPosition(String, usize),
     Adjacent(String, String),
     OnSameSide(String, String),
+    /// Side index (0-based) and whether durations should be ascending (`true`) or descending.
+    SideSortedByDuration(usize, bool),
+    /// Two parts of a continuous piece that shouldn't be split by a side break.
+    MaxSideBreaksBetween(String, String),
+    /// Tag and the minimum number of other tracks between two tracks carrying it.
+    SpreadTag(String, usize),
+    /// No side closes with its longest track (inner-groove distortion).
+    LongestNotSideCloser,
+    /// Graded: rewards sides whose durations decrease toward the inner groove.
+    /// The value multiplies the constraint weight.
+    ShortToInner(usize),
+    /// The track starts playing before the given duration (in minutes) has elapsed.
+    StartsBefore(String, Duration),
+    /// Graded: rewards orders close to the order of the named reference tracklist.
+    StayCloseTo(String),
+    /// Every side opens with a track of at least the given energy.
+    StrongSideOpeners(u8),
+    /// Graded: rewards sides filled to at least the given fraction of the side's max duration.
+    FillSides(f64),
+    /// At most the given number of side breaks fall between two continuous tracks.
+    MaxHardCuts(usize),
+    /// The track is at any of the given positions (0-based).
+    AtAnyPosition(String, Vec<usize>),
+    /// Graded: full weight when the two tracks share a side, part of it when they
+    /// are on neighbouring sides.
+    PreferSameSide(String, String),
+    /// Side index (0-based), the tracks it holds in any order, and whether it holds
+    /// exactly those tracks (`true`) or at least them.
+    SideContains(usize, Vec<String>, bool),
+    /// Every side is filled to within the tolerance (second value) of the target
+    /// fraction (first value) of the side's max duration, neither under nor over.
+    SideNearFull(f64, f64),
+    /// The durations of the sides differ by at most the given duration (in minutes).
+    SideBalance(Duration),
+    /// The total runtimes of the discs (pairs of consecutive sides) differ by at most
+    /// the given duration (in minutes).
+    DiscBalance(Duration),
+    /// No side has more than the given number of tracks by the same artist.
+    MaxArtistPerSide(usize),
+    /// Consecutive tracks differ in loudness by at most the given amount (in LU).
+    SmoothLoudness(f64),
+    /// The first side lasts at least the given duration (in minutes), so the first
+    /// side break doesn't come too early.
+    FirstBreakAfter(Duration),
+    /// The first title opens the tracklist and the second one closes it.
+    Bookend(String, String),
+    /// Graded: full weight when the track is at the given position (0-based), less the
+    /// further it is from it.
+    NearPosition(String, usize),
+    /// Tracks shorter than the given duration (in minutes) are short, the others long;
+    /// no two consecutive tracks are of the same class.
+    AlternatePacing(Duration),
 }
 
 /// Serializable constraint with weight.
@@ -110,78 +278,356 @@ pub enum SerConstraintKind {
 pub struct SerConstraint {
     pub kind: SerConstraintKind,
     pub weight: usize,
+    /// Disabled constraints are kept in the context but ignored when scoring.
+    #[serde(default = "enabled_by_default", skip_serializing_if = "is_enabled")]
+    pub enabled: bool,
 }
 
-/// Convert from SerConstraint to albumseq Constraint.
-impl From<SerConstraint> for AlbumConstraint {
-    fn from(serc: SerConstraint) -> Self {
-        let kind = match serc.kind {
-            SerConstraintKind::AtPosition(title, pos) => {
-                AlbumConstraintKind::AtPosition(title, pos)
+/// Constraints stored before `enabled` existed are enabled.
+fn enabled_by_default() -> bool {
+    true
+}
+
+/// Only disabled constraints store the `enabled` field.
+fn is_enabled(enabled: &bool) -> bool {
+    *enabled
+}
+
+impl SerConstraintKind {
+    /// Returns the track titles this constraint refers to.
+    /// Tracklist names (e.g. of `StayCloseTo`) are not included.
+    pub fn titles(&self) -> Vec<&str> {
+        match self {
+            SerConstraintKind::AtPosition(title, _)
+            | SerConstraintKind::NearPosition(title, _)
+            | SerConstraintKind::StartsBefore(title, _)
+            | SerConstraintKind::AtAnyPosition(title, _) => vec![title],
+            SerConstraintKind::Adjacent(t1, t2)
+            | SerConstraintKind::OnSameSide(t1, t2)
+            | SerConstraintKind::MaxSideBreaksBetween(t1, t2)
+            | SerConstraintKind::PreferSameSide(t1, t2)
+            | SerConstraintKind::Bookend(t1, t2) => vec![t1, t2],
+            SerConstraintKind::SideContains(_, titles, _) => {
+                titles.iter().map(String::as_str).collect()
             }
-            SerConstraintKind::Adjacent(t1, t2) => AlbumConstraintKind::Adjacent(t1, t2),
-            SerConstraintKind::OnSameSide(t1, t2) => AlbumConstraintKind::OnSameSide(t1, t2),
-        };
-        AlbumConstraint {
-            kind,
-            weight: serc.weight,
+            _ => Vec::new(),
+        }
+    }
+
+    /// Returns the track titles this constraint refers to, for in-place edits.
+    /// Tracklist names (e.g. of `StayCloseTo`) are not included.
+    pub fn titles_mut(&mut self) -> Vec<&mut String> {
+        match self {
+            SerConstraintKind::AtPosition(title, _)
+            | SerConstraintKind::NearPosition(title, _)
+            | SerConstraintKind::StartsBefore(title, _)
+            | SerConstraintKind::AtAnyPosition(title, _) => vec![title],
+            SerConstraintKind::Adjacent(t1, t2)
+            | SerConstraintKind::OnSameSide(t1, t2)
+            | SerConstraintKind::MaxSideBreaksBetween(t1, t2)
+            | SerConstraintKind::PreferSameSide(t1, t2)
+            | SerConstraintKind::Bookend(t1, t2) => vec![t1, t2],
+            SerConstraintKind::SideContains(_, titles, _) => titles.iter_mut().collect(),
+            _ => Vec::new(),
         }
     }
 }
 
-/// Convert from albumseq Constraint to SerConstraint.
-impl From<&AlbumConstraint> for SerConstraint {
-    fn from(c: &AlbumConstraint) -> Self {
-        let kind = match &c.kind {
-            AlbumConstraintKind::AtPosition(title, pos) => {
-                SerConstraintKind::AtPosition(title.clone(), *pos)
+impl SerConstraint {
+    /// Returns the most points this constraint can award: its weight, scaled for
+    /// `ShortToInner`.
+    pub fn max_points(&self) -> usize {
+        match self.kind {
+            SerConstraintKind::ShortToInner(scale) => self.weight * scale,
+            _ => self.weight,
+        }
+    }
+
+    /// Converts this `SerConstraint` into an `AlbumConstraint`.
+    /// Returns `None` for kinds that albumseq doesn't know about.
+    pub fn to_album_constraint(&self) -> Option<AlbumConstraint> {
+        // albumseq matches plain titles only
+        if self
+            .kind
+            .titles()
+            .into_iter()
+            .any(|title| split_duration_qualifier(title).1.is_some())
+        {
+            return None;
+        }
+        let kind = match &self.kind {
+            SerConstraintKind::AtPosition(title, pos) => {
+                AlbumConstraintKind::AtPosition(title.clone(), *pos)
             }
-            AlbumConstraintKind::Adjacent(t1, t2) => {
-                SerConstraintKind::Adjacent(t1.clone(), t2.clone())
+            SerConstraintKind::Adjacent(t1, t2) => {
+                AlbumConstraintKind::Adjacent(t1.clone(), t2.clone())
             }
-            AlbumConstraintKind::OnSameSide(t1, t2) => {
-                SerConstraintKind::OnSameSide(t1.clone(), t2.clone())
+            SerConstraintKind::OnSameSide(t1, t2) => {
+                AlbumConstraintKind::OnSameSide(t1.clone(), t2.clone())
             }
+            _ => return None,
         };
-        SerConstraint {
+        Some(AlbumConstraint {
             kind,
-            weight: c.weight,
+            weight: self.weight,
+        })
+    }
+}
+
+/// File formats a context can be stored in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ContextFormat {
+    Json,
+    Toml,
+}
+
+impl ContextFormat {
+    /// Returns TOML for a path with a `.toml` extension (case-insensitive), JSON otherwise.
+    fn for_path(path: &Path) -> Self {
+        match path.extension() {
+            Some(ext) if ext.eq_ignore_ascii_case("toml") => ContextFormat::Toml,
+            _ => ContextFormat::Json,
         }
     }
 }
 
+/// A file of constraints shared between contexts, as written by `export-constraints`
+/// and read by `import-constraints`.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct ConstraintSet {
+    pub constraints: Vec<SerConstraint>,
+}
+
+/// One remembered proposal: its score and its track order, by title.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct StoredProposal {
+    pub score: Score,
+    pub titles: Vec<String>,
+}
+
+/// The proposals shown by the last `propose --remember` run.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct StoredProposals {
+    pub tracklist: String,
+    pub medium: String,
+    /// Best first.
+    pub proposals: Vec<StoredProposal>,
+}
+
 /// The persistent context for the CLI, containing all tracklists, media, and constraints.
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Default)]
 pub struct ProgramContext {
     pub tracklists: Vec<NamedSerTracklist>,
     pub mediums: Vec<SerMedium>,
     pub constraints: Vec<SerConstraint>,
+    /// Absent from contexts saved before `--remember` existed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_proposals: Option<StoredProposals>,
 }
 
 impl ProgramContext {
     /// Loads the context from the given path, or creates a new one if it doesn't exist.
-    pub fn load_or_create<P: AsRef<Path>>(path: P) -> Self {
-        if path.as_ref().exists() {
-            let data = fs::read_to_string(path).expect("Failed to read context file");
-            serde_json::from_str(&data).expect("Failed to parse context file")
+    /// Returns a user error if the path is a directory or another non-regular file.
+    /// With [`STDIO_CONTEXT_PATH`], the context is read as JSON from stdin; empty input
+    /// gives a new context.
+    pub fn load_or_create<P: AsRef<Path>>(path: P) -> CliResult<Self> {
+        let path = path.as_ref();
+        if is_stdio_path(path) {
+            debug!("Loading context from stdin");
+            let mut data = String::new();
+            io::stdin()
+                .read_to_string(&mut data)
+                .map_err(|e| CliError::Io(format!("Failed to read context from stdin: {}", e)))?;
+            if data.trim().is_empty() {
+                return Ok(Self::default());
+            }
+            return serde_json::from_str(&data).map_err(|e| {
+                CliError::Parse(format!("Failed to parse context from stdin: {}", e))
+            });
+        }
+        if path.is_dir() {
+            return Err(CliError::User(format!(
+                "Context path {:?} is a directory; pass a file such as {:?}",
+                path,
+                path.join(DEFAULT_CONTEXT_PATH)
+            )));
+        }
+        if path.exists() && !path.is_file() {
+            return Err(CliError::User(format!(
+                "Context path {:?} is not a regular file",
+                path
+            )));
+        }
+        if path.exists() {
+            debug!("Loading context from {:?}", path);
+            let data = fs::read_to_string(path).map_err(|e| {
+                CliError::Io(format!("Failed to read context file {:?}: {}", path, e))
+            })?;
+            let parsed = match ContextFormat::for_path(path) {
+                ContextFormat::Json => serde_json::from_str(&data).map_err(|e| e.to_string()),
+                ContextFormat::Toml => toml::from_str(&data).map_err(|e| e.to_string()),
+            };
+            let ctx: Self = parsed.map_err(|e| {
+                CliError::Parse(format!("Failed to parse context file {:?}: {}", path, e))
+            })?;
+            debug!(
+                "Loaded {} tracklists, {} media, {} constraints",
+                ctx.tracklists.len(),
+                ctx.mediums.len(),
+                ctx.constraints.len()
+            );
+            Ok(ctx)
         } else {
+            debug!("No context at {:?}, creating a new one", path);
             let ctx = Self::default();
-            ctx.save(path);
-            ctx
+            ctx.save(path)?;
+            Ok(ctx)
+        }
+    }
+
+    /// Saves the context to the given path, or as JSON to stdout with
+    /// [`STDIO_CONTEXT_PATH`].
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> CliResult {
+        let path = path.as_ref();
+        debug!("Saving context to {:?}", path);
+        let minified = MINIFIED.get().copied().unwrap_or_default();
+        let format = if is_stdio_path(path) {
+            ContextFormat::Json
+        } else {
+            ContextFormat::for_path(path)
+        };
+        let serialized = match format {
+            ContextFormat::Json if minified => {
+                serde_json::to_string(self).map_err(|e| e.to_string())
+            }
+            ContextFormat::Json => serde_json::to_string_pretty(self).map_err(|e| e.to_string()),
+            ContextFormat::Toml => toml::to_string_pretty(self).map_err(|e| e.to_string()),
+        };
+        let data = serialized
+            .map_err(|e| CliError::Parse(format!("Failed to serialize context: {}", e)))?;
+        if is_stdio_path(path) {
+            let mut stdout = io::stdout().lock();
+            return writeln!(stdout, "{}", data)
+                .map_err(|e| CliError::Io(format!("Failed to write context to stdout: {}", e)));
+        }
+        fs::write(path, data)
+            .map_err(|e| CliError::Io(format!("Failed to write context file {:?}: {}", path, e)))
+    }
+
+    /// Writes all constraints to a file, JSON or TOML by its extension, in the form
+    /// read by [`ProgramContext::import_constraints`].
+    pub fn export_constraints<P: AsRef<Path>>(&self, path: P) -> CliResult {
+        let path = path.as_ref();
+        let set = ConstraintSet {
+            constraints: self.constraints.clone(),
+        };
+        let serialized = match ContextFormat::for_path(path) {
+            ContextFormat::Json => serde_json::to_string_pretty(&set).map_err(|e| e.to_string()),
+            ContextFormat::Toml => toml::to_string_pretty(&set).map_err(|e| e.to_string()),
+        };
+        let data = serialized
+            .map_err(|e| CliError::Parse(format!("Failed to serialize constraints: {}", e)))?;
+        fs::write(path, data).map_err(|e| {
+            CliError::Io(format!(
+                "Failed to write constraints file {:?}: {}",
+                path, e
+            ))
+        })?;
+        status!(
+            "Exported {} constraints to {:?}",
+            set.constraints.len(),
+            path
+        );
+        Ok(())
+    }
+
+    /// Reads constraints from a file written by [`ProgramContext::export_constraints`]
+    /// and adds them, replacing constraints of the same kind. Returns how many were read.
+    pub fn import_constraints<P: AsRef<Path>>(&mut self, path: P) -> CliResult<usize> {
+        let path = path.as_ref();
+        let data = fs::read_to_string(path).map_err(|e| {
+            CliError::Io(format!("Failed to read constraints file {:?}: {}", path, e))
+        })?;
+        let parsed = match ContextFormat::for_path(path) {
+            ContextFormat::Json => serde_json::from_str(&data).map_err(|e| e.to_string()),
+            ContextFormat::Toml => toml::from_str(&data).map_err(|e| e.to_string()),
+        };
+        let set: ConstraintSet = parsed.map_err(|e| {
+            CliError::Parse(format!(
+                "Failed to parse constraints file {:?}: {}",
+                path, e
+            ))
+        })?;
+
+        let count = set.constraints.len();
+        for constraint in set.constraints {
+            self.add_or_replace_constraint(constraint);
+        }
+        Ok(count)
+    }
+
+    /// Find a tracklist by name (case-insensitive)
+    pub fn tracklist(&self, name: &str) -> CliResult<&NamedSerTracklist> {
+        self.tracklists
+            .iter()
+            .find(|tl| tl.name.eq_ignore_ascii_case(name))
+            .ok_or_else(|| self.tracklist_not_found(name))
+    }
+
+    /// Find a tracklist by name (case-insensitive), for modification
+    pub fn tracklist_mut(&mut self, name: &str) -> CliResult<&mut NamedSerTracklist> {
+        match self
+            .tracklists
+            .iter()
+            .position(|tl| tl.name.eq_ignore_ascii_case(name))
+        {
+            Some(index) => Ok(&mut self.tracklists[index]),
+            None => Err(self.tracklist_not_found(name)),
         }
     }
 
-    /// Saves the context to the given path.
-    pub fn save<P: AsRef<Path>>(&self, path: P) {
-        let json = serde_json::to_string_pretty(self).expect("Failed to serialize context");
-        fs::write(path, json).expect("Failed to write context file");
+    /// Builds the error for a missing tracklist, suggesting the closest stored name.
+    fn tracklist_not_found(&self, name: &str) -> CliError {
+        let names = self.tracklists.iter().map(|tl| tl.name.as_str());
+        match closest_name(name, names) {
+            Some(suggestion) => CliError::User(format!(
+                "Tracklist '{}' not found (did you mean '{}'?)",
+                name, suggestion
+            )),
+            None => CliError::User(format!("Tracklist '{}' not found", name)),
+        }
+    }
+
+    /// Find a medium by name (case-insensitive)
+    pub fn medium(&self, name: &str) -> CliResult<&SerMedium> {
+        self.mediums
+            .iter()
+            .find(|m| m.name.eq_ignore_ascii_case(name))
+            .ok_or_else(|| CliError::User(format!("Medium '{}' not found", name)))
+    }
+
+    /// Find a medium by name (case-insensitive), for editing.
+    fn medium_mut(&mut self, name: &str) -> CliResult<&mut SerMedium> {
+        self.mediums
+            .iter_mut()
+            .find(|m| m.name.eq_ignore_ascii_case(name))
+            .ok_or_else(|| CliError::User(format!("Medium '{}' not found", name)))
     }
 
     /// Add or replace a tracklist by name
     pub fn add_or_replace_tracklist(&mut self, name: String, tracks: Vec<Track>) {
-        let new_list = NamedSerTracklist {
+        let tracks = SerTracklist(tracks.iter().map(|t| t.into()).collect());
+        self.add_or_replace_ser_tracklist(name, tracks);
+    }
+
+    /// Add or replace a tracklist by name, keeping per-track metadata
+    /// Notes and catalog number of a replaced tracklist are kept.
+    pub fn add_or_replace_ser_tracklist(&mut self, name: String, tracks: SerTracklist) {
+        let mut new_list = NamedSerTracklist {
             name: name.clone(),
-            tracks: SerTracklist(tracks.iter().map(|t| t.into()).collect()),
+            tracks,
+            notes: None,
+            catalog: None,
         };
 
         if let Some(existing) = self
@@ -189,11 +635,13 @@ impl ProgramContext {
             .iter_mut()
             .find(|tl| tl.name.eq_ignore_ascii_case(&name))
         {
+            new_list.notes = existing.notes.take();
+            new_list.catalog = existing.catalog.take();
             *existing = new_list;
-            println!("Replaced tracklist '{}'", name);
+            status!("Replaced tracklist '{}'", name);
         } else {
             self.tracklists.push(new_list);
-            println!("Added tracklist '{}'", name);
+            status!("Added tracklist '{}'", name);
         }
     }
 
@@ -203,44 +651,1027 @@ impl ProgramContext {
         name: String,
         sides: usize,
         max_duration_per_side: Duration,
+        rpm: Option<f64>,
     ) {
-        let new_medium = SerMedium {
-            name: name.clone(),
-            sides,
-            max_duration_per_side,
-        };
-
         if let Some(existing) = self
             .mediums
             .iter_mut()
             .find(|m| m.name.eq_ignore_ascii_case(&name))
         {
-            *existing = new_medium;
-            println!("Replaced medium '{}'", name);
+            // Keep the medium's constraints
+            existing.name = name.clone();
+            existing.sides = sides;
+            existing.max_duration_per_side = max_duration_per_side;
+            existing.rpm = rpm;
+            status!("Replaced medium '{}'", name);
+        } else {
+            self.mediums.push(SerMedium {
+                name: name.clone(),
+                sides,
+                max_duration_per_side,
+                rpm,
+                constraints: Vec::new(),
+            });
+            status!("Added medium '{}'", name);
+        }
+    }
+
+    /// Add a constraint to a named medium, replacing one of the same kind.
+    pub fn add_or_replace_medium_constraint(
+        &mut self,
+        medium: &str,
+        constraint: SerConstraint,
+    ) -> CliResult {
+        let medium = self.medium_mut(medium)?;
+        let kind = constraint.kind.clone();
+
+        if let Some(existing) = medium.constraints.iter_mut().find(|c| c.kind == kind) {
+            *existing = constraint;
+            status!("Replaced constraint {:?} of medium '{}'", kind, medium.name);
+        } else {
+            medium.constraints.push(constraint);
+            status!("Added constraint {:?} to medium '{}'", kind, medium.name);
+        }
+        Ok(())
+    }
+
+    /// Remove a constraint of a named medium by index.
+    pub fn remove_medium_constraint(&mut self, medium: &str, index: usize) -> CliResult {
+        let medium = self.medium_mut(medium)?;
+        if index >= medium.constraints.len() {
+            return Err(CliError::User(format!(
+                "Index {} out of range ({} constraints on medium '{}')",
+                index,
+                medium.constraints.len(),
+                medium.name
+            )));
+        }
+
+        let removed = medium.constraints.remove(index);
+        status!(
+            "Removed constraint {:?} (weight {}) from medium '{}'",
+            removed.kind,
+            removed.weight,
+            medium.name
+        );
+        Ok(())
+    }
+
+    /// Returns the context's constraints followed by those of the medium, i.e.
+    /// every constraint that applies when proposing on that medium.
+    pub fn constraints_on(&self, medium: &SerMedium) -> Vec<SerConstraint> {
+        self.constraints
+            .iter()
+            .chain(&medium.constraints)
+            .cloned()
+            .collect()
+    }
+
+    /// Returns every stored constraint for in-place edits: the context's, then those
+    /// of each medium.
+    fn all_constraints_mut(&mut self) -> impl Iterator<Item = &mut SerConstraint> {
+        self.constraints.iter_mut().chain(
+            self.mediums
+                .iter_mut()
+                .flat_map(|m| m.constraints.iter_mut()),
+        )
+    }
+
+    /// Set or clear the notes of a named tracklist.
+    pub fn set_tracklist_notes(&mut self, tracklist: &str, notes: Option<String>) -> CliResult {
+        let tl = self.tracklist_mut(tracklist)?;
+
+        match &notes {
+            Some(n) => status!("Set notes for tracklist '{}': {}", tl.name, n),
+            None => status!("Cleared notes for tracklist '{}'", tl.name),
+        }
+        tl.notes = notes;
+
+        Ok(())
+    }
+
+    /// Set or clear the catalog number of a named tracklist.
+    pub fn set_tracklist_catalog(&mut self, tracklist: &str, catalog: Option<String>) -> CliResult {
+        let tl = self.tracklist_mut(tracklist)?;
+
+        match &catalog {
+            Some(c) => status!("Set catalog number for tracklist '{}': {}", tl.name, c),
+            None => status!("Cleared catalog number for tracklist '{}'", tl.name),
+        }
+        tl.catalog = catalog;
+
+        Ok(())
+    }
+
+    /// Find a track by title (case-insensitive) in a named tracklist, for modification
+    fn track_mut(&mut self, tracklist: &str, title: &str) -> CliResult<&mut SerTrack> {
+        let tl = self.tracklist_mut(tracklist)?;
+
+        let name = &tl.name;
+        tl.tracks
+            .0
+            .iter_mut()
+            .find(|t| t.title.eq_ignore_ascii_case(title))
+            .ok_or_else(|| {
+                CliError::User(format!(
+                    "Track '{}' not found in tracklist '{}'",
+                    title, name
+                ))
+            })
+    }
+
+    /// Sort the tracks of a named tracklist in place. The sort is stable, so tracks
+    /// that compare equal keep their relative order.
+    pub fn sort_tracklist(&mut self, tracklist: &str, order: TrackOrder) -> CliResult {
+        let tl = self.tracklist_mut(tracklist)?;
+        match order {
+            TrackOrder::Title => tl.tracks.0.sort_by_cached_key(|t| t.title.to_lowercase()),
+            TrackOrder::DurationAsc => tl
+                .tracks
+                .0
+                .sort_by(|a, b| a.duration.total_cmp(&b.duration)),
+            TrackOrder::DurationDesc => tl
+                .tracks
+                .0
+                .sort_by(|a, b| b.duration.total_cmp(&a.duration)),
+        }
+        debug!("Sorted tracklist '{}' by {:?}", tl.name, order);
+        Ok(())
+    }
+
+    /// Swap the positions of two tracks of a named tracklist, found by title
+    /// (case-insensitive).
+    pub fn swap_tracks(&mut self, tracklist: &str, title1: &str, title2: &str) -> CliResult {
+        let tl = self.tracklist_mut(tracklist)?;
+        let position = |title: &str| {
+            tl.tracks
+                .0
+                .iter()
+                .position(|t| t.title.eq_ignore_ascii_case(title))
+                .ok_or_else(|| {
+                    CliError::User(format!(
+                        "Track '{}' not found in tracklist '{}'",
+                        title, tl.name
+                    ))
+                })
+        };
+        let (i, j) = (position(title1)?, position(title2)?);
+
+        tl.tracks.0.swap(i, j);
+        status!(
+            "Swapped '{}' (#{}) and '{}' (#{}) in tracklist '{}'",
+            tl.tracks.0[j].title,
+            i + 1,
+            tl.tracks.0[i].title,
+            j + 1,
+            tl.name
+        );
+        Ok(())
+    }
+
+    /// Add or remove tags on a track of a named tracklist.
+    pub fn tag_track(
+        &mut self,
+        tracklist: &str,
+        title: &str,
+        tags: &[String],
+        remove: bool,
+    ) -> CliResult {
+        let track = self.track_mut(tracklist, title)?;
+
+        for tag in tags {
+            if remove {
+                track.tags.retain(|t| !t.eq_ignore_ascii_case(tag));
+            } else if !track.has_tag(tag) {
+                track.tags.push(tag.clone());
+            }
+        }
+
+        status!("Tags for '{}': {}", track.title, track.tags.join(", "));
+        Ok(())
+    }
+
+    /// Set the energy rating of a track of a named tracklist.
+    pub fn set_track_energy(&mut self, tracklist: &str, title: &str, energy: u8) -> CliResult {
+        let track = self.track_mut(tracklist, title)?;
+        track.energy = Some(energy);
+
+        status!("Set energy of '{}' to {}", track.title, energy);
+        Ok(())
+    }
+
+    /// Mark or unmark a track of a named tracklist as continuous.
+    pub fn set_track_continuous(
+        &mut self,
+        tracklist: &str,
+        title: &str,
+        continuous: bool,
+    ) -> CliResult {
+        let track = self.track_mut(tracklist, title)?;
+        track.continuous = continuous;
+
+        status!(
+            "{} '{}' as continuous",
+            if continuous { "Marked" } else { "Unmarked" },
+            track.title
+        );
+        Ok(())
+    }
+
+    /// Set or clear (with an empty name) the artist of a track of a named tracklist.
+    pub fn set_track_artist(&mut self, tracklist: &str, title: &str, artist: &str) -> CliResult {
+        let track = self.track_mut(tracklist, title)?;
+        let artist = artist.trim();
+        if artist.is_empty() {
+            track.artist = None;
+            status!("Cleared artist of '{}'", track.title);
         } else {
-            self.mediums.push(new_medium);
-            println!("Added medium '{}'", name);
+            track.artist = Some(artist.to_string());
+            status!("Set artist of '{}' to {}", track.title, artist);
+        }
+        Ok(())
+    }
+
+    /// Set the loudness (LUFS) of a track of a named tracklist.
+    pub fn set_track_loudness(&mut self, tracklist: &str, title: &str, lufs: f64) -> CliResult {
+        let track = self.track_mut(tracklist, title)?;
+        track.loudness = Some(lufs);
+
+        status!("Set loudness of '{}' to {} LUFS", track.title, lufs);
+        Ok(())
+    }
+
+    /// Set the gap after a track of a named tracklist. A zero gap removes it.
+    pub fn set_track_gap(&mut self, tracklist: &str, title: &str, gap: Duration) -> CliResult {
+        let track = self.track_mut(tracklist, title)?;
+        track.gap_after = (gap > 0.0).then_some(gap);
+
+        status!(
+            "Set gap after '{}' to {}",
+            track.title,
+            format_duration(gap)
+        );
+        Ok(())
+    }
+
+    /// Set the ISRC of a track of a named tracklist.
+    /// Hyphens are dropped and letters upper-cased; the result must be 12 letters or digits.
+    pub fn set_track_isrc(&mut self, tracklist: &str, title: &str, isrc: &str) -> CliResult {
+        let normalized: String = isrc
+            .chars()
+            .filter(|c| *c != '-')
+            .map(|c| c.to_ascii_uppercase())
+            .collect();
+        if normalized.len() != 12 || !normalized.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return Err(CliError::User(format!(
+                "Invalid ISRC: {} (expected 12 letters or digits, e.g. US-S1Z-99-00001)",
+                isrc
+            )));
+        }
+
+        let track = self.track_mut(tracklist, title)?;
+        status!("Set ISRC of '{}' to {}", track.title, normalized);
+        track.isrc = Some(normalized);
+        Ok(())
+    }
+
+    /// Rename a track in every tracklist and in every constraint referring to it,
+    /// including those of media. Titles are matched case-insensitively. Returns the
+    /// number of tracks and the number of constraint arguments renamed.
+    pub fn rename_track(&mut self, old: &str, new: &str) -> (usize, usize) {
+        let mut tracks = 0;
+        for tl in &mut self.tracklists {
+            for track in tl.tracks.0.iter_mut() {
+                if track.title.eq_ignore_ascii_case(old) {
+                    debug!("Renaming '{}' in tracklist '{}'", track.title, tl.name);
+                    track.title = new.to_string();
+                    tracks += 1;
+                }
+            }
+        }
+
+        // A duration qualifier ("Song@3:45") is kept
+        let mut args = 0;
+        for c in self.all_constraints_mut() {
+            for arg in c.kind.titles_mut() {
+                let (title, _) = split_duration_qualifier(arg);
+                if title.eq_ignore_ascii_case(old) {
+                    *arg = format!("{}{}", new, &arg[title.len()..]);
+                    args += 1;
+                }
+            }
+        }
+
+        (tracks, args)
+    }
+
+    /// Rewrites every track title of a named tracklist with `normalize`, and the
+    /// constraint arguments (of the context and of media) that normalize to one of
+    /// the new titles.
+    ///
+    /// Returns the titles changed, as (old, new) pairs, and the number of constraint
+    /// arguments rewritten.
+    pub fn normalize_titles(
+        &mut self,
+        tracklist: &str,
+        normalize: impl Fn(&str) -> String,
+    ) -> CliResult<(Vec<(String, String)>, usize)> {
+        let tl = self.tracklist_mut(tracklist)?;
+        let mut renamed = Vec::new();
+        for track in tl.tracks.0.iter_mut() {
+            let title = normalize(&track.title);
+            if title != track.title {
+                renamed.push((std::mem::replace(&mut track.title, title.clone()), title));
+            }
         }
+        let titles: Vec<String> = tl.tracks.0.iter().map(|t| t.title.clone()).collect();
+
+        // Also fixes arguments that didn't match before, e.g. in another case
+        let mut args = 0;
+        for c in self.all_constraints_mut() {
+            for arg in c.kind.titles_mut() {
+                let (title, _) = split_duration_qualifier(arg);
+                let normalized = normalize(title);
+                if normalized != title && titles.contains(&normalized) {
+                    *arg = format!("{}{}", normalized, &arg[title.len()..]);
+                    args += 1;
+                }
+            }
+        }
+
+        Ok((renamed, args))
+    }
+
+    /// Remove duplicate tracks from a named tracklist, keeping the first occurrence.
+    ///
+    /// Tracks are duplicates when their titles match (case-insensitive) and their
+    /// durations are within [`DUPLICATE_DURATION_EPSILON`], or on the title alone
+    /// when `by_title` is set. Returns the number of tracks removed.
+    pub fn dedup_tracklist(&mut self, tracklist: &str, by_title: bool) -> CliResult<usize> {
+        let tl = self.tracklist_mut(tracklist)?;
+        let before = tl.tracks.0.len();
+
+        let mut kept: Vec<SerTrack> = Vec::with_capacity(before);
+        for track in tl.tracks.0.drain(..) {
+            let duplicate = kept.iter().any(|k| {
+                k.title.eq_ignore_ascii_case(&track.title)
+                    && (by_title
+                        || (k.duration - track.duration).abs() <= DUPLICATE_DURATION_EPSILON)
+            });
+            if duplicate {
+                debug!("Removing duplicate track '{}'", track.title);
+            } else {
+                kept.push(track);
+            }
+        }
+        tl.tracks.0 = kept;
+
+        let removed = before - tl.tracks.0.len();
+        status!(
+            "Removed {} duplicate tracks from tracklist '{}'",
+            removed,
+            tl.name
+        );
+        Ok(removed)
+    }
+
+    /// Returns the tracks whose duration isn't positive, as (tracklist name, position,
+    /// track), in tracklist order.
+    pub fn non_positive_durations(&self) -> Vec<(&str, usize, &SerTrack)> {
+        self.tracklists
+            .iter()
+            .flat_map(|tl| {
+                tl.tracks
+                    .0
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, track)| track.duration <= 0.0 || track.duration.is_nan())
+                    .map(|(i, track)| (tl.name.as_str(), i, track))
+            })
+            .collect()
+    }
+
+    /// Remove the tracks whose duration isn't positive from every tracklist.
+    /// Returns the number of tracks removed.
+    pub fn remove_non_positive_durations(&mut self) -> usize {
+        let mut removed = 0;
+        for tl in &mut self.tracklists {
+            let before = tl.tracks.0.len();
+            tl.tracks.0.retain(|track| track.duration > 0.0);
+            if tl.tracks.0.len() < before {
+                status!(
+                    "Removed {} tracks from tracklist '{}'",
+                    before - tl.tracks.0.len(),
+                    tl.name
+                );
+            }
+            removed += before - tl.tracks.0.len();
+        }
+        removed
+    }
+
+    /// Add `delta` (in minutes, possibly negative) to the duration of every track of a
+    /// named tracklist. Durations are clamped at zero.
+    pub fn offset_durations(&mut self, tracklist: &str, delta: Duration) -> CliResult {
+        let tl = self.tracklist_mut(tracklist)?;
+
+        for track in &mut tl.tracks.0 {
+            track.duration = (track.duration + delta).max(0.0);
+        }
+
+        status!(
+            "Offset {} tracks of tracklist '{}' by {}{}",
+            tl.tracks.0.len(),
+            tl.name,
+            if delta < 0.0 { "-" } else { "+" },
+            format_duration(delta.abs())
+        );
+        Ok(())
     }
 
     /// Add or replace a constraint
-    pub fn add_or_replace_constraint(&mut self, constraint: AlbumConstraint) {
-        let ser_constraint = SerConstraint::from(&constraint);
-        let kind = ser_constraint.kind;
+    pub fn add_or_replace_constraint(&mut self, constraint: SerConstraint) {
+        let kind = constraint.kind.clone();
 
         if let Some(existing) = self.constraints.iter_mut().find(|c| c.kind == kind) {
-            *existing = SerConstraint::from(&constraint);
-            println!("Replaced constraint {:?}", kind);
+            *existing = constraint;
+            status!("Replaced constraint {:?}", kind);
         } else {
-            self.constraints.push(SerConstraint::from(&constraint));
-            println!("Added constraint {:?}", kind);
+            self.constraints.push(constraint);
+            status!("Added constraint {:?}", kind);
         }
     }
 }
 
-impl Default for ProgramContext {
-    fn default() -> Self {
-        let path = Path::new("context.json");
-        Self::load_or_create(path)
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn track(title: &str, duration: Duration) -> Track {
+        Track {
+            title: title.to_string(),
+            duration,
+        }
+    }
+
+    /// A context with tracklist "Album" (A 4:00, B 3:00, C 5:00, D 2:00).
+    fn album() -> ProgramContext {
+        let mut ctx = ProgramContext::default();
+        ctx.add_or_replace_tracklist(
+            "Album".to_string(),
+            vec![
+                track("A", 4.0),
+                track("B", 3.0),
+                track("C", 5.0),
+                track("D", 2.0),
+            ],
+        );
+        ctx
+    }
+
+    /// A context file path unique to the test, removed when dropped.
+    struct TempPath(std::path::PathBuf);
+
+    impl TempPath {
+        fn new(name: &str, extension: &str) -> Self {
+            TempPath(std::env::temp_dir().join(format!(
+                "albumseq_cli_context_{}_{}.{}",
+                name,
+                std::process::id(),
+                extension
+            )))
+        }
+    }
+
+    impl Drop for TempPath {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    #[test]
+    fn tracklist_notes_survive_a_save_and_load() {
+        let path = TempPath::new("notes", "json");
+        let mut ctx = album();
+        ctx.set_tracklist_notes("album", Some("needs remastering".to_string()))
+            .unwrap();
+        ctx.add_or_replace_tracklist("Other".to_string(), vec![track("E", 3.0)]);
+        // Replacing the tracks keeps the notes
+        ctx.add_or_replace_tracklist("Album".to_string(), vec![track("A", 4.0)]);
+        ctx.save(&path.0).unwrap();
+
+        let data = fs::read_to_string(&path.0).unwrap();
+        assert_eq!(data.matches("\"notes\"").count(), 1);
+
+        let loaded = ProgramContext::load_or_create(&path.0).unwrap();
+        assert_eq!(
+            loaded.tracklist("Album").unwrap().notes.as_deref(),
+            Some("needs remastering")
+        );
+        assert_eq!(loaded.tracklist("Other").unwrap().notes, None);
+
+        let mut cleared = loaded;
+        cleared.set_tracklist_notes("Album", None).unwrap();
+        assert_eq!(cleared.tracklist("Album").unwrap().notes, None);
+    }
+
+    fn titles_and_durations(ctx: &ProgramContext, tracklist: &str) -> Vec<(String, Duration)> {
+        ctx.tracklist(tracklist)
+            .unwrap()
+            .tracks
+            .0
+            .iter()
+            .map(|t| (t.title.clone(), t.duration))
+            .collect()
+    }
+
+    #[test]
+    fn dedup_removes_exact_or_same_titled_duplicates() {
+        let tracks = vec![
+            track("Intro", 1.0),
+            track("Song", 3.0),
+            // Within a second of the first "Song"
+            track("song", 3.0 + 0.5 / 60.0),
+            track("Song", 4.0),
+            track("Outro", 2.0),
+        ];
+        let mut ctx = ProgramContext::default();
+        ctx.add_or_replace_tracklist("Exact".to_string(), tracks.clone());
+        ctx.add_or_replace_tracklist("Titles".to_string(), tracks);
+
+        assert_eq!(ctx.dedup_tracklist("Exact", false).unwrap(), 1);
+        assert_eq!(
+            titles_and_durations(&ctx, "Exact"),
+            [
+                ("Intro".to_string(), 1.0),
+                ("Song".to_string(), 3.0),
+                ("Song".to_string(), 4.0),
+                ("Outro".to_string(), 2.0),
+            ]
+        );
+
+        assert_eq!(ctx.dedup_tracklist("Titles", true).unwrap(), 2);
+        assert_eq!(
+            titles_and_durations(&ctx, "Titles"),
+            [
+                ("Intro".to_string(), 1.0),
+                ("Song".to_string(), 3.0),
+                ("Outro".to_string(), 2.0),
+            ]
+        );
+
+        assert!(ctx.dedup_tracklist("Missing", false).is_err());
+    }
+
+    #[test]
+    fn negative_offset_stops_durations_at_zero() {
+        let mut ctx = album();
+        ctx.offset_durations("Album", -2.5).unwrap();
+        let durations: Vec<Duration> = titles_and_durations(&ctx, "Album")
+            .into_iter()
+            .map(|(_, duration)| duration)
+            .collect();
+        assert_eq!(durations, [1.5, 0.5, 2.5, 0.0]);
+
+        ctx.offset_durations("Album", 0.5).unwrap();
+        assert_eq!(titles_and_durations(&ctx, "Album")[3].1, 0.5);
+    }
+
+    /// The album context with a medium, constraints and track metadata.
+    fn full_context() -> ProgramContext {
+        let mut ctx = album();
+        ctx.add_or_replace_medium("LP".to_string(), 2, 20.0, Some(45.0));
+        ctx.add_or_replace_constraint(SerConstraint {
+            kind: SerConstraintKind::AtPosition("A".to_string(), 0),
+            weight: 3,
+            enabled: true,
+        });
+        ctx.add_or_replace_constraint(SerConstraint {
+            kind: SerConstraintKind::SideNearFull(0.9, 0.05),
+            weight: 1,
+            enabled: false,
+        });
+        ctx.tag_track("Album", "B", &["slow".to_string()], false)
+            .unwrap();
+        ctx.set_track_gap("Album", "C", 0.1).unwrap();
+        ctx.set_tracklist_notes("Album", Some("draft".to_string()))
+            .unwrap();
+        ctx
+    }
+
+    #[test]
+    fn toml_context_round_trips() {
+        let path = TempPath::new("round_trip", "toml");
+        let ctx = full_context();
+        ctx.save(&path.0).unwrap();
+
+        let data = fs::read_to_string(&path.0).unwrap();
+        assert!(serde_json::from_str::<ProgramContext>(&data).is_err());
+
+        let loaded = ProgramContext::load_or_create(&path.0).unwrap();
+        assert_eq!(
+            serde_json::to_string(&loaded).unwrap(),
+            serde_json::to_string(&ctx).unwrap()
+        );
+    }
+
+    #[test]
+    fn isrc_is_normalized_and_validated() {
+        let mut ctx = album();
+        ctx.set_track_isrc("Album", "a", "us-s1z-99-00001").unwrap();
+        let track = &ctx.tracklist("Album").unwrap().tracks.0[0];
+        assert_eq!(track.isrc.as_deref(), Some("USS1Z9900001"));
+
+        assert!(ctx.set_track_isrc("Album", "B", "US-S1Z-99-0001").is_err());
+        assert!(ctx.set_track_isrc("Album", "B", "US-S1Z-99-0000!").is_err());
+        assert!(
+            ctx.set_track_isrc("Album", "Missing", "USS1Z9900001")
+                .is_err()
+        );
+        assert_eq!(ctx.tracklist("Album").unwrap().tracks.0[1].isrc, None);
+    }
+
+    #[test]
+    fn contexts_without_metadata_still_load() {
+        let ctx: ProgramContext = serde_json::from_str(
+            r#"{
+                "tracklists": [
+                    {"name": "Album", "tracks": [{"title": "A", "duration": 4.0}]}
+                ],
+                "mediums": [],
+                "constraints": []
+            }"#,
+        )
+        .unwrap();
+
+        let tracklist = ctx.tracklist("Album").unwrap();
+        assert_eq!(tracklist.catalog, None);
+        assert_eq!(tracklist.tracks.0[0].isrc, None);
+    }
+
+    #[test]
+    fn rename_track_updates_titles_and_constraint_args() {
+        let mut ctx = album();
+        ctx.add_or_replace_tracklist("Live".to_string(), vec![track("a", 5.0), track("E", 3.0)]);
+        ctx.add_or_replace_constraint(SerConstraint {
+            kind: SerConstraintKind::OnSameSide("A".to_string(), "D".to_string()),
+            weight: 1,
+            enabled: true,
+        });
+        ctx.add_or_replace_constraint(SerConstraint {
+            kind: SerConstraintKind::Adjacent("B".to_string(), "C".to_string()),
+            weight: 1,
+            enabled: true,
+        });
+
+        assert_eq!(ctx.rename_track("A", "Intro"), (2, 1));
+        assert_eq!(
+            titles_and_durations(&ctx, "Album")[0],
+            ("Intro".to_string(), 4.0)
+        );
+        assert_eq!(
+            titles_and_durations(&ctx, "Live")[0],
+            ("Intro".to_string(), 5.0)
+        );
+        assert!(matches!(
+            &ctx.constraints[0].kind,
+            SerConstraintKind::OnSameSide(t1, t2) if t1 == "Intro" && t2 == "D"
+        ));
+        assert!(matches!(
+            &ctx.constraints[1].kind,
+            SerConstraintKind::Adjacent(t1, t2) if t1 == "B" && t2 == "C"
+        ));
+
+        assert_eq!(ctx.rename_track("Missing", "Other"), (0, 0));
+    }
+
+    #[test]
+    fn exported_constraints_import_back_unchanged() {
+        for extension in ["json", "toml"] {
+            let path = TempPath::new("exported_constraints", extension);
+            let mut ctx = full_context();
+            let exported = serde_json::to_string(&ctx.constraints).unwrap();
+
+            ctx.export_constraints(&path.0).unwrap();
+            ctx.constraints.clear();
+            assert_eq!(ctx.import_constraints(&path.0).unwrap(), 2);
+            assert_eq!(serde_json::to_string(&ctx.constraints).unwrap(), exported);
+
+            // Importing again replaces rather than duplicates
+            assert_eq!(ctx.import_constraints(&path.0).unwrap(), 2);
+            assert_eq!(ctx.constraints.len(), 2);
+        }
+    }
+
+    #[test]
+    fn a_directory_is_not_a_context_path() {
+        let dir = std::env::temp_dir();
+        let err = ProgramContext::load_or_create(&dir).unwrap_err();
+        assert!(matches!(
+            &err,
+            CliError::User(message) if message.contains("is a directory")
+                && message.contains(DEFAULT_CONTEXT_PATH)
+        ));
+
+        // A missing file is created
+        let path = TempPath::new("created", "json");
+        assert!(ProgramContext::load_or_create(&path.0).is_ok());
+        assert!(path.0.is_file());
+    }
+
+    #[test]
+    fn swap_tracks_exchanges_positions_or_reports_the_missing_title() {
+        let mut ctx = album();
+        ctx.swap_tracks("album", "d", "A").unwrap();
+        let titles: Vec<String> = titles_and_durations(&ctx, "Album")
+            .into_iter()
+            .map(|(title, _)| title)
+            .collect();
+        assert_eq!(titles, ["D", "B", "C", "A"]);
+
+        let err = ctx.swap_tracks("Album", "A", "Missing").unwrap_err();
+        assert!(matches!(
+            &err,
+            CliError::User(message) if message == "Track 'Missing' not found in tracklist 'Album'"
+        ));
+        assert_eq!(titles_and_durations(&ctx, "Album")[0].0, "D");
+    }
+
+    #[test]
+    fn normalize_titles_rewrites_titles_and_constraint_args() {
+        use crate::utils::TitleNormalization;
+
+        let messy = || {
+            let mut ctx = album();
+            ctx.add_or_replace_tracklist(
+                "Messy".to_string(),
+                vec![track("  song   one ", 3.0), track("SIDE b", 4.0)],
+            );
+            ctx.add_or_replace_constraint(SerConstraint {
+                kind: SerConstraintKind::Adjacent("song  one".to_string(), "side B".to_string()),
+                weight: 1,
+                enabled: true,
+            });
+            ctx
+        };
+        let adjacent = |ctx: &ProgramContext| match &ctx.constraints[0].kind {
+            SerConstraintKind::Adjacent(t1, t2) => (t1.clone(), t2.clone()),
+            kind => panic!("unexpected constraint {:?}", kind),
+        };
+
+        let mut ctx = messy();
+        let (renamed, args) = ctx
+            .normalize_titles("Messy", |title| TitleNormalization::Trim.apply(title))
+            .unwrap();
+        assert_eq!(
+            renamed,
+            [("  song   one ".to_string(), "song one".to_string())]
+        );
+        assert_eq!(args, 1);
+        assert_eq!(
+            adjacent(&ctx),
+            ("song one".to_string(), "side B".to_string())
+        );
+
+        let mut ctx = messy();
+        let (renamed, args) = ctx
+            .normalize_titles("Messy", |title| TitleNormalization::Titlecase.apply(title))
+            .unwrap();
+        assert_eq!(renamed.len(), 2);
+        assert_eq!(args, 2);
+        assert_eq!(
+            titles_and_durations(&ctx, "Messy"),
+            [("Song One".to_string(), 3.0), ("Side B".to_string(), 4.0)]
+        );
+        assert_eq!(
+            adjacent(&ctx),
+            ("Song One".to_string(), "Side B".to_string())
+        );
+        // Other tracklists are left alone
+        assert_eq!(titles_and_durations(&ctx, "Album")[0].0, "A");
+    }
+
+    #[test]
+    fn normalize_titles_rewrites_medium_constraint_args() {
+        use crate::utils::TitleNormalization;
+
+        let mut ctx = album();
+        ctx.add_or_replace_tracklist("Messy".to_string(), vec![track(" SONG one", 3.0)]);
+        ctx.mediums.push(SerMedium {
+            name: "LP".to_string(),
+            sides: 2,
+            max_duration_per_side: 20.0,
+            ..Default::default()
+        });
+        ctx.add_or_replace_medium_constraint(
+            "LP",
+            SerConstraint {
+                kind: SerConstraintKind::AtPosition("song ONE".to_string(), 0),
+                weight: 1,
+                enabled: true,
+            },
+        )
+        .unwrap();
+
+        let (_, args) = ctx
+            .normalize_titles("Messy", |title| TitleNormalization::Titlecase.apply(title))
+            .unwrap();
+        assert_eq!(args, 1);
+        assert!(matches!(
+            &ctx.medium("LP").unwrap().constraints[0].kind,
+            SerConstraintKind::AtPosition(title, 0) if title == "Song One"
+        ));
+    }
+
+    #[test]
+    fn remembered_proposals_survive_a_save_and_load() {
+        for extension in ["json", "toml"] {
+            let path = TempPath::new("remembered", extension);
+            let mut ctx = album();
+            ctx.last_proposals = Some(StoredProposals {
+                tracklist: "Album".to_string(),
+                medium: "LP".to_string(),
+                proposals: vec![
+                    StoredProposal {
+                        score: 2.5,
+                        titles: ["C", "B", "A", "D"].map(String::from).to_vec(),
+                    },
+                    StoredProposal {
+                        score: 0.0,
+                        titles: ["A", "B", "C", "D"].map(String::from).to_vec(),
+                    },
+                ],
+            });
+            ctx.save(&path.0).unwrap();
+
+            let loaded = ProgramContext::load_or_create(&path.0).unwrap();
+            let last = loaded.last_proposals.unwrap();
+            assert_eq!(
+                (last.tracklist.as_str(), last.medium.as_str()),
+                ("Album", "LP")
+            );
+            let proposals: Vec<(Score, Vec<String>)> = last
+                .proposals
+                .into_iter()
+                .map(|p| (p.score, p.titles))
+                .collect();
+            assert_eq!(
+                proposals,
+                [
+                    (2.5, ["C", "B", "A", "D"].map(String::from).to_vec()),
+                    (0.0, ["A", "B", "C", "D"].map(String::from).to_vec()),
+                ]
+            );
+        }
+
+        // Contexts without proposals don't mention them and load as before
+        let path = TempPath::new("not_remembered", "json");
+        album().save(&path.0).unwrap();
+        assert!(
+            !fs::read_to_string(&path.0)
+                .unwrap()
+                .contains("last_proposals")
+        );
+        assert!(
+            ProgramContext::load_or_create(&path.0)
+                .unwrap()
+                .last_proposals
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn faster_media_have_shorter_sides() {
+        let at_33 = scale_for_rpm(BASE_SIDE_LENGTH, Some(BASE_RPM));
+        let at_45 = scale_for_rpm(BASE_SIDE_LENGTH, Some(45.0));
+        assert!((at_33 - 22.0).abs() < 1e-9);
+        // About 16:18
+        assert!((at_45 - 22.0 * 100.0 / 135.0).abs() < 1e-9);
+        assert!(at_45 < at_33);
+
+        // Without a speed, or a speed of zero or less, nothing is scaled
+        assert_eq!(scale_for_rpm(22.0, None), 22.0);
+        assert_eq!(scale_for_rpm(22.0, Some(0.0)), 22.0);
+
+        // The stored side length is used as is, whatever the speed
+        let medium = SerMedium {
+            name: "12in".to_string(),
+            sides: 2,
+            max_duration_per_side: 15.0,
+            rpm: Some(45.0),
+            ..Default::default()
+        };
+        assert_eq!(medium.to_album_medium().max_duration_per_side, 15.0);
+    }
+
+    #[test]
+    fn zero_durations_are_flagged_and_removed() {
+        let mut ctx = album();
+        ctx.add_or_replace_tracklist(
+            "Import".to_string(),
+            vec![track("E", 3.0), track("Silence", 0.0)],
+        );
+        let flagged: Vec<(&str, usize, &str)> = ctx
+            .non_positive_durations()
+            .into_iter()
+            .map(|(tracklist, position, track)| (tracklist, position, track.title.as_str()))
+            .collect();
+        assert_eq!(flagged, [("Import", 1, "Silence")]);
+
+        assert_eq!(ctx.remove_non_positive_durations(), 1);
+        assert!(ctx.non_positive_durations().is_empty());
+        assert_eq!(
+            titles_and_durations(&ctx, "Import"),
+            [("E".to_string(), 3.0)]
+        );
+        assert_eq!(titles_and_durations(&ctx, "Album").len(), 4);
+    }
+
+    #[test]
+    fn sort_tracklist_by_each_order() {
+        let sorted = |tracklist: &str, order: TrackOrder| {
+            let mut ctx = album();
+            ctx.add_or_replace_tracklist(
+                "Mixed".to_string(),
+                vec![track("alpha", 3.0), track("Gamma", 1.0), track("Beta", 2.0)],
+            );
+            ctx.sort_tracklist(tracklist, order).unwrap();
+            titles_and_durations(&ctx, tracklist)
+                .into_iter()
+                .map(|(title, _)| title)
+                .collect::<Vec<_>>()
+        };
+
+        // Case-insensitive
+        assert_eq!(
+            sorted("Mixed", TrackOrder::Title),
+            ["alpha", "Beta", "Gamma"]
+        );
+        assert_eq!(
+            sorted("Album", TrackOrder::DurationAsc),
+            ["D", "B", "A", "C"]
+        );
+        assert_eq!(
+            sorted("Album", TrackOrder::DurationDesc),
+            ["C", "A", "B", "D"]
+        );
+        assert!(
+            album()
+                .sort_tracklist("Missing", TrackOrder::Title)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn rename_track_updates_medium_constraints() {
+        let mut ctx = album();
+        ctx.mediums.push(SerMedium {
+            name: "LP".to_string(),
+            sides: 2,
+            max_duration_per_side: 20.0,
+            ..Default::default()
+        });
+        ctx.add_or_replace_medium_constraint(
+            "LP",
+            SerConstraint {
+                kind: SerConstraintKind::Adjacent("A".to_string(), "B".to_string()),
+                weight: 1,
+                enabled: true,
+            },
+        )
+        .unwrap();
+
+        assert_eq!(ctx.rename_track("a", "Intro"), (1, 1));
+        assert!(matches!(
+            &ctx.medium("LP").unwrap().constraints[0].kind,
+            SerConstraintKind::Adjacent(t1, t2) if t1 == "Intro" && t2 == "B"
+        ));
+    }
+
+    #[test]
+    fn rename_and_normalize_keep_duration_qualifiers() {
+        use crate::utils::TitleNormalization;
+
+        let mut ctx = album();
+        ctx.add_or_replace_constraint(SerConstraint {
+            kind: SerConstraintKind::Adjacent("a@4:00".to_string(), "B".to_string()),
+            weight: 1,
+            enabled: true,
+        });
+
+        assert_eq!(ctx.rename_track("A", "song one"), (1, 1));
+        assert!(matches!(
+            &ctx.constraints[0].kind,
+            SerConstraintKind::Adjacent(t1, _) if t1 == "song one@4:00"
+        ));
+
+        let (_, args) = ctx
+            .normalize_titles("Album", |title| TitleNormalization::Titlecase.apply(title))
+            .unwrap();
+        assert_eq!(args, 1);
+        assert!(matches!(
+            &ctx.constraints[0].kind,
+            SerConstraintKind::Adjacent(t1, _) if t1 == "Song One@4:00"
+        ));
     }
 }
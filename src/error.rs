@@ -0,0 +1,55 @@
+//! # Errors and Exit Codes
+//!
+//! This module defines the error type returned by command handlers and context I/O,
+//! and the exit code each kind of error maps to, so scripts can tell failures apart.
+//!
+//! ## Exit Codes
+//! - `0`: Success.
+//! - `2`: User error (bad arguments, unknown tracklist/medium/track, ...). Clap also uses `2` for usage errors.
+//! - `3`: I/O error while reading or writing the context file.
+//! - `4`: The context file exists but couldn't be parsed.
+//!
+//! ## Example
+//! ```rust
+//! if let Err(e) = run(&cli) {
+//!     eprintln!("{}", e);
+//!     std::process::exit(e.exit_code());
+//! }
+//! ```
+
+use std::fmt;
+
+/// Errors reported by albumseq_cli commands.
+#[derive(Debug)]
+pub enum CliError {
+    /// Bad arguments or a reference to something that doesn't exist.
+    User(String),
+    /// Reading or writing the context file failed.
+    Io(String),
+    /// The context file couldn't be parsed or serialized.
+    Parse(String),
+}
+
+impl CliError {
+    /// Returns the process exit code for this error.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            CliError::User(_) => 2,
+            CliError::Io(_) => 3,
+            CliError::Parse(_) => 4,
+        }
+    }
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CliError::User(msg) | CliError::Io(msg) | CliError::Parse(msg) => {
+                write!(f, "{}", msg)
+            }
+        }
+    }
+}
+
+/// Result type returned by command handlers.
+pub type CliResult<T = ()> = Result<T, CliError>;
@@ -0,0 +1,135 @@
+//! # M3U Playlists
+//!
+//! This module reads extended M3U playlists (`.m3u` / `.m3u8`), as exported by DJ
+//! software such as Rekordbox or Serato, into tracks. Each entry's title and duration
+//! come from the `#EXTINF:<seconds>,<display title>` line preceding its path; entries
+//! without one, or with an unknown duration (`-1`), are skipped.
+//!
+//! ## Example
+//! ```rust
+//! let playlist = parse_m3u(&fs::read_to_string("set.m3u8")?);
+//! handle_add_tracklist(&mut ctx, &name, playlist.tracks)?;
+//! ```
+
+use albumseq::Track;
+
+/// The tracks read from a playlist, and the entries that couldn't be read.
+#[derive(Default)]
+pub struct M3uPlaylist {
+    pub tracks: Vec<Track>,
+    /// Paths of the entries skipped, with the reason.
+    pub skipped: Vec<(String, &'static str)>,
+}
+
+/// Parses the contents of an extended M3U playlist.
+/// Comments and directives other than `#EXTINF` are ignored.
+pub fn parse_m3u(content: &str) -> M3uPlaylist {
+    let mut playlist = M3uPlaylist::default();
+    let mut info: Option<Result<Track, &'static str>> = None;
+
+    for line in content.lines() {
+        // Some exporters start the file with a byte order mark
+        let line = line.trim_start_matches('\u{feff}').trim();
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(extinf) = line.strip_prefix("#EXTINF:") {
+            info = Some(parse_extinf(extinf));
+            continue;
+        }
+        if line.starts_with('#') {
+            continue;
+        }
+
+        // A path: the entry described by the preceding #EXTINF, if any
+        match info.take() {
+            Some(Ok(track)) => playlist.tracks.push(track),
+            Some(Err(reason)) => playlist.skipped.push((line.to_string(), reason)),
+            None => playlist.skipped.push((line.to_string(), "no #EXTINF line")),
+        }
+    }
+
+    playlist
+}
+
+/// Parses the part of an `#EXTINF` line after the colon: the duration in seconds,
+/// optional attributes, a comma and the display title.
+fn parse_extinf(extinf: &str) -> Result<Track, &'static str> {
+    let Some((duration, title)) = extinf.split_once(',') else {
+        return Err("#EXTINF without a title");
+    };
+    // Attributes (e.g. tvg-id="...") may follow the duration
+    let seconds = duration
+        .split_whitespace()
+        .next()
+        .and_then(|d| d.parse::<f64>().ok())
+        .ok_or("bad #EXTINF duration")?;
+    if seconds <= 0.0 {
+        return Err("unknown duration");
+    }
+    let title = title.trim();
+    if title.is_empty() {
+        return Err("#EXTINF without a title");
+    }
+
+    Ok(Track {
+        title: title.to_string(),
+        duration: seconds / 60.0,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fixture_playlist_reads_tracks_and_skips_unreadable_entries() {
+        let playlist = parse_m3u(include_str!("../tests/fixtures/dj_set.m3u8"));
+
+        let tracks: Vec<(&str, f64)> = playlist
+            .tracks
+            .iter()
+            .map(|t| (t.title.as_str(), t.duration))
+            .collect();
+        assert_eq!(
+            tracks,
+            [
+                ("Artist One - Opener", 225.0 / 60.0),
+                ("Artist Two - Long Build", 312.0 / 60.0),
+                ("Artist Three - Closer", 180.5 / 60.0),
+            ]
+        );
+
+        let skipped: Vec<(&str, &str)> = playlist
+            .skipped
+            .iter()
+            .map(|(path, reason)| (path.as_str(), *reason))
+            .collect();
+        assert_eq!(
+            skipped,
+            [
+                ("http://example.com/stream", "unknown duration"),
+                ("Music/Untagged/No Info.mp3", "no #EXTINF line"),
+                ("Music/Broken.mp3", "bad #EXTINF duration"),
+            ]
+        );
+    }
+
+    #[test]
+    fn comment_lines_are_ignored_silently() {
+        let playlist = parse_m3u(
+            "#EXTM3U\n\
+             # Warm-up, check the levels\n\
+             #EXTINF:200,Opener\n\
+             # pitch +2%\n\
+             opener.mp3\n\
+             \t# indented note\n\
+             #EXTINF:100,Closer\n\
+             closer.mp3\n",
+        );
+
+        let titles: Vec<&str> = playlist.tracks.iter().map(|t| t.title.as_str()).collect();
+        assert_eq!(titles, ["Opener", "Closer"]);
+        assert!(playlist.skipped.is_empty());
+    }
+}
@@ -15,107 +15,633 @@
 //! albumseq_cli propose --tracklist "MyAlbum" --medium "Vinyl" --count 10
 //! ```
 
+mod cache;
+mod chapters;
 mod cli;
 mod commands;
+mod conflicts;
 mod context;
+mod error;
+mod m3u;
+mod ranking;
+mod scoring;
+mod sheet;
 mod utils;
+mod wizard;
 
 use std::path::Path;
 
-use crate::cli::{Cli, Commands};
+use crate::cache::cache_path_for;
+use crate::chapters::parse_timestamp;
+use crate::cli::{Algorithm, Cli, Commands};
 use crate::commands::{
-    handle_add_constraint, handle_add_medium, handle_add_tracklist, handle_propose,
-    handle_remove_constraint, handle_show,
+    ProposeOptions, Search, TrackInfoUpdate, handle_add_constraint, handle_add_constraint_wizard,
+    handle_add_medium, handle_add_medium_constraint, handle_add_preset, handle_add_standard_media,
+    handle_add_tracklist, handle_capacity, handle_check_durations, handle_conflicts,
+    handle_constraint_info, handle_constraints_for, handle_copy_medium, handle_dedup,
+    handle_diff_tracklists, handle_explain, handle_export_constraints, handle_fit_medium,
+    handle_heatmap, handle_import_chapters, handle_import_constraints, handle_import_m3u,
+    handle_last_proposals, handle_layout, handle_normalize_titles, handle_offset_durations,
+    handle_preview_constraint, handle_propose, handle_propose_all, handle_propose_set,
+    handle_remove_constraint, handle_remove_medium_constraint, handle_rename_track,
+    handle_scale_weights, handle_set_catalog, handle_set_notes, handle_set_track_info,
+    handle_sheet, handle_show, handle_show_tracklist, handle_sort, handle_split,
+    handle_swap_tracks, handle_tag_track, handle_toggle_constraint, handle_unconstrained,
+    sides_needed_for,
+};
+use crate::context::{
+    BASE_SIDE_LENGTH, ProgramContext, SerMedium, is_stdio_path, scale_for_rpm, set_minified,
+};
+use crate::error::{CliError, CliResult};
+use crate::utils::{
+    OutputFormat, Rounding, SortBy, parse_duration, parse_track, set_duration_rounding, set_quiet,
+    status,
 };
-use crate::context::ProgramContext;
-use crate::utils::parse_duration;
 use albumseq::Track;
 use clap::Parser;
+use log::{LevelFilter, warn};
 
-fn main() {
-    let cli = Cli::parse();
+/// Initializes `env_logger` according to the number of `-v` flags given.
+/// Without any flag only warnings and errors are logged.
+fn init_logger(verbose: u8) {
+    let level = match verbose {
+        0 => LevelFilter::Warn,
+        1 => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
+    };
+
+    env_logger::Builder::new()
+        .filter_level(level)
+        .format_timestamp(None)
+        .init();
+}
 
+/// Runs the parsed command against the context file.
+fn run(cli: &Cli) -> CliResult {
     match &cli.command {
         Commands::Init => {
-            if Path::new(&cli.context).exists() {
-                eprintln!("Context file already exists at {:?}", cli.context);
-            } else {
-                let ctx = ProgramContext::default();
-                ctx.save(&cli.context);
-                println!("Created new context at {:?}", cli.context);
+            if !is_stdio_path(&cli.context) && Path::new(&cli.context).exists() {
+                return Err(CliError::User(format!(
+                    "Context file already exists at {:?}",
+                    cli.context
+                )));
             }
+            let ctx = ProgramContext::default();
+            ctx.save(&cli.context)?;
+            status!("Created new context at {:?}", cli.context);
         }
 
-        Commands::AddTracklist { name, tracks } => {
-            let mut ctx = ProgramContext::load_or_create(&cli.context);
+        Commands::AddTracklist {
+            name,
+            tracks,
+            strict,
+        } => {
+            let mut ctx = ProgramContext::load_or_create(&cli.context)?;
 
-            let parsed_tracks: Vec<Track> = tracks
-                .iter()
-                .filter_map(|s| {
-                    let parts: Vec<_> = s.splitn(2, ':').collect();
-                    if parts.len() == 2 {
-                        let title = parts[0].to_string();
-                        let duration_str = parts[1];
-                        parse_duration(duration_str).map(|duration| Track { title, duration })
-                    } else {
-                        None
+            let mut parsed_tracks: Vec<Track> = Vec::new();
+            let mut rejected: Vec<String> = Vec::new();
+            for entry in tracks {
+                match parse_track(entry) {
+                    Ok(track) => parsed_tracks.push(track),
+                    Err(reason) => {
+                        warn!("Skipping track entry \"{}\": {}", entry, reason);
+                        rejected.push(format!("\"{}\" ({})", entry, reason));
                     }
-                })
-                .collect();
-
-            if !parsed_tracks.is_empty() {
-                if handle_add_tracklist(&mut ctx, name, parsed_tracks) {
-                    ctx.save(&cli.context);
                 }
-            } else {
-                eprintln!("No valid tracks provided for tracklist '{}'", name);
             }
+            if *strict && !rejected.is_empty() {
+                return Err(CliError::User(format!(
+                    "Malformed track entries: {}",
+                    rejected.join(", ")
+                )));
+            }
+
+            handle_add_tracklist(&mut ctx, name, parsed_tracks)?;
+            ctx.save(&cli.context)?;
+        }
+
+        Commands::ImportM3u { name, path } => {
+            let mut ctx = ProgramContext::load_or_create(&cli.context)?;
+            handle_import_m3u(&mut ctx, name, path)?;
+            ctx.save(&cli.context)?;
+        }
+
+        Commands::ImportChapters {
+            name,
+            path,
+            total,
+            comment_char,
+        } => {
+            let total = parse_timestamp(total)
+                .or_else(|| parse_duration(total))
+                .ok_or_else(|| CliError::User(format!("Invalid duration format: {}", total)))?;
+
+            let mut ctx = ProgramContext::load_or_create(&cli.context)?;
+            handle_import_chapters(&mut ctx, name, path, total, *comment_char)?;
+            ctx.save(&cli.context)?;
+        }
+
+        Commands::AddStandardMedia { force } => {
+            let mut ctx = ProgramContext::load_or_create(&cli.context)?;
+            handle_add_standard_media(&mut ctx, *force)?;
+            ctx.save(&cli.context)?;
         }
 
         Commands::AddMedium {
             name,
             sides,
             max_duration,
+            total_duration,
+            for_tracklist,
+            rpm,
         } => {
-            let mut ctx = ProgramContext::load_or_create(&cli.context);
+            let mut ctx = ProgramContext::load_or_create(&cli.context)?;
 
-            if let Some(duration) = parse_duration(max_duration) {
-                if handle_add_medium(&mut ctx, name, *sides, duration) {
-                    ctx.save(&cli.context);
+            // An explicit side length is taken as is, only a derived one is scaled
+            let duration = match (max_duration, rpm) {
+                (Some(max_duration), _) => parse_duration(max_duration).ok_or_else(|| {
+                    CliError::User(format!("Invalid duration format: {}", max_duration))
+                })?,
+                (None, Some(_)) => scale_for_rpm(BASE_SIDE_LENGTH, *rpm),
+                (None, None) => {
+                    return Err(CliError::User(
+                        "Pass --max-duration, or --rpm to derive it".to_string(),
+                    ));
                 }
-            } else {
-                eprintln!("Invalid duration format: {}", max_duration);
+            };
+            let sides = match (sides, total_duration, for_tracklist) {
+                (Some(sides), None, None) => *sides,
+                (None, Some(total), None) => {
+                    let total = parse_duration(total).ok_or_else(|| {
+                        CliError::User(format!("Invalid duration format: {}", total))
+                    })?;
+                    SerMedium::for_total_duration(name.clone(), total, duration).sides
+                }
+                (None, None, Some(tracklist)) => sides_needed_for(&ctx, tracklist, duration)?,
+                _ => {
+                    return Err(CliError::User(
+                        "Pass exactly one of --sides, --total-duration or --for-tracklist"
+                            .to_string(),
+                    ));
+                }
+            };
+            handle_add_medium(&mut ctx, name, sides, duration, *rpm)?;
+            ctx.save(&cli.context)?;
+        }
+
+        Commands::CopyMedium {
+            source,
+            new_name,
+            sides,
+            max_duration,
+        } => {
+            let mut ctx = ProgramContext::load_or_create(&cli.context)?;
+
+            let duration = max_duration
+                .as_deref()
+                .map(|d| {
+                    parse_duration(d)
+                        .ok_or_else(|| CliError::User(format!("Invalid duration format: {}", d)))
+                })
+                .transpose()?;
+            handle_copy_medium(&mut ctx, source, new_name, *sides, duration)?;
+            ctx.save(&cli.context)?;
+        }
+
+        Commands::SetNotes { tracklist, notes } => {
+            let mut ctx = ProgramContext::load_or_create(&cli.context)?;
+            handle_set_notes(&mut ctx, tracklist, notes)?;
+            ctx.save(&cli.context)?;
+        }
+
+        Commands::TagTrack {
+            tracklist,
+            title,
+            tags,
+            remove,
+        } => {
+            let mut ctx = ProgramContext::load_or_create(&cli.context)?;
+            handle_tag_track(&mut ctx, tracklist, title, tags, *remove)?;
+            ctx.save(&cli.context)?;
+        }
+
+        Commands::SetTrackInfo {
+            tracklist,
+            title,
+            energy,
+            isrc,
+            continuous,
+            gap,
+            artist,
+            loudness,
+        } => {
+            let mut ctx = ProgramContext::load_or_create(&cli.context)?;
+            let update = TrackInfoUpdate {
+                energy: *energy,
+                isrc: isrc.clone(),
+                continuous: *continuous,
+                gap: gap.clone(),
+                artist: artist.clone(),
+                loudness: *loudness,
+            };
+            handle_set_track_info(&mut ctx, tracklist, title, &update)?;
+            ctx.save(&cli.context)?;
+        }
+
+        Commands::SetCatalog { tracklist, catalog } => {
+            let mut ctx = ProgramContext::load_or_create(&cli.context)?;
+            handle_set_catalog(&mut ctx, tracklist, catalog)?;
+            ctx.save(&cli.context)?;
+        }
+
+        Commands::OffsetDurations { tracklist, delta } => {
+            let mut ctx = ProgramContext::load_or_create(&cli.context)?;
+            handle_offset_durations(&mut ctx, tracklist, delta)?;
+            ctx.save(&cli.context)?;
+        }
+
+        Commands::Dedup {
+            tracklist,
+            by_title,
+        } => {
+            let mut ctx = ProgramContext::load_or_create(&cli.context)?;
+            handle_dedup(&mut ctx, tracklist, *by_title)?;
+            ctx.save(&cli.context)?;
+        }
+
+        Commands::CheckDurations { fix, yes } => {
+            let mut ctx = ProgramContext::load_or_create(&cli.context)?;
+            if handle_check_durations(&mut ctx, *fix, *yes)? {
+                ctx.save(&cli.context)?;
             }
         }
 
-        Commands::AddConstraint { kind, args, weight } => {
-            let mut ctx = ProgramContext::load_or_create(&cli.context);
+        Commands::DiffTracklists { a, b } => {
+            let ctx = ProgramContext::load_or_create(&cli.context)?;
+            handle_diff_tracklists(&ctx, a, b)?;
+        }
+
+        Commands::NormalizeTitles { tracklist, mode } => {
+            let mut ctx = ProgramContext::load_or_create(&cli.context)?;
+            handle_normalize_titles(&mut ctx, tracklist, *mode)?;
+            ctx.save(&cli.context)?;
+        }
+
+        Commands::Sort { tracklist, by } => {
+            let mut ctx = ProgramContext::load_or_create(&cli.context)?;
+            handle_sort(&mut ctx, tracklist, *by)?;
+            ctx.save(&cli.context)?;
+        }
+
+        Commands::SwapTracks {
+            tracklist,
+            title1,
+            title2,
+        } => {
+            let mut ctx = ProgramContext::load_or_create(&cli.context)?;
+            handle_swap_tracks(&mut ctx, tracklist, title1, title2)?;
+            ctx.save(&cli.context)?;
+        }
+
+        Commands::RenameTrack { old, new } => {
+            let mut ctx = ProgramContext::load_or_create(&cli.context)?;
+            handle_rename_track(&mut ctx, old, new)?;
+            ctx.save(&cli.context)?;
+        }
 
-            if handle_add_constraint(&mut ctx, kind, args, *weight) {
-                ctx.save(&cli.context);
+        Commands::AddConstraint {
+            kind,
+            args,
+            weight,
+            preview,
+        } => {
+            let mut ctx = ProgramContext::load_or_create(&cli.context)?;
+            if let Some([tracklist, medium]) = preview.as_deref() {
+                handle_preview_constraint(&ctx, kind, args, *weight, tracklist, medium)?;
+            } else {
+                handle_add_constraint(&mut ctx, kind, args, *weight)?;
+                ctx.save(&cli.context)?;
             }
         }
 
-        Commands::RemoveConstraint { index } => {
-            let mut ctx = ProgramContext::load_or_create(&cli.context);
-            if handle_remove_constraint(&mut ctx, index) {
-                ctx.save(&cli.context);
+        Commands::AddConstraintWizard { tracklist } => {
+            let mut ctx = ProgramContext::load_or_create(&cli.context)?;
+            handle_add_constraint_wizard(&mut ctx, tracklist)?;
+            ctx.save(&cli.context)?;
+        }
+
+        Commands::AddPreset { name } => {
+            let mut ctx = ProgramContext::load_or_create(&cli.context)?;
+            handle_add_preset(&mut ctx, name)?;
+            ctx.save(&cli.context)?;
+        }
+
+        Commands::RemoveConstraint { index, dry_run } => {
+            let mut ctx = ProgramContext::load_or_create(&cli.context)?;
+            handle_remove_constraint(&mut ctx, index, *dry_run)?;
+            if !dry_run {
+                ctx.save(&cli.context)?;
             }
         }
 
+        Commands::ScaleWeights { factor, kind } => {
+            let mut ctx = ProgramContext::load_or_create(&cli.context)?;
+            handle_scale_weights(&mut ctx, *factor, kind)?;
+            ctx.save(&cli.context)?;
+        }
+
+        Commands::ToggleConstraint { index } => {
+            let mut ctx = ProgramContext::load_or_create(&cli.context)?;
+            handle_toggle_constraint(&mut ctx, *index)?;
+            ctx.save(&cli.context)?;
+        }
+
+        Commands::ExportConstraints { path } => {
+            let ctx = ProgramContext::load_or_create(&cli.context)?;
+            handle_export_constraints(&ctx, path)?;
+        }
+
+        Commands::ImportConstraints { path } => {
+            let mut ctx = ProgramContext::load_or_create(&cli.context)?;
+            handle_import_constraints(&mut ctx, path)?;
+            ctx.save(&cli.context)?;
+        }
+
+        Commands::AddMediumConstraint {
+            medium,
+            kind,
+            args,
+            weight,
+        } => {
+            let mut ctx = ProgramContext::load_or_create(&cli.context)?;
+            handle_add_medium_constraint(&mut ctx, medium, kind, args, *weight)?;
+            ctx.save(&cli.context)?;
+        }
+
+        Commands::RemoveMediumConstraint { medium, index } => {
+            let mut ctx = ProgramContext::load_or_create(&cli.context)?;
+            handle_remove_medium_constraint(&mut ctx, medium, *index)?;
+            ctx.save(&cli.context)?;
+        }
+
+        Commands::Unconstrained { tracklist } => {
+            let ctx = ProgramContext::load_or_create(&cli.context)?;
+            handle_unconstrained(&ctx, tracklist)?;
+        }
+
+        Commands::Conflicts => {
+            let ctx = ProgramContext::load_or_create(&cli.context)?;
+            handle_conflicts(&ctx);
+        }
+
+        Commands::ConstraintInfo { index, medium } => {
+            let ctx = ProgramContext::load_or_create(&cli.context)?;
+            handle_constraint_info(&ctx, *index, medium.as_deref())?;
+        }
+
+        Commands::ConstraintsFor { tracklist } => {
+            let ctx = ProgramContext::load_or_create(&cli.context)?;
+            handle_constraints_for(&ctx, tracklist)?;
+        }
+
         Commands::Show { filter } => {
-            let ctx = ProgramContext::load_or_create(&cli.context);
+            let ctx = ProgramContext::load_or_create(&cli.context)?;
             handle_show(&ctx, filter);
         }
 
+        Commands::ShowTracklist { name, medium } => {
+            let ctx = ProgramContext::load_or_create(&cli.context)?;
+            handle_show_tracklist(&ctx, name, medium)?;
+        }
+
+        Commands::Explain { tracklist, medium } => {
+            let ctx = ProgramContext::load_or_create(&cli.context)?;
+            handle_explain(&ctx, tracklist, medium)?;
+        }
+
         Commands::Propose {
             tracklist,
             medium,
+            sides,
+            max_duration,
             count,
             min_score,
+            diagnose,
+            no_cache,
+            algorithm,
+            samples,
+            seed,
+            anchor,
+            anchor_strength,
+            fix_first,
+            fix_last,
+            side_labels,
+            stats,
+            measure,
+            include_original,
+            prefer_original,
+            sort_by,
+            group_by,
+            fit_weight,
+            allow_partial,
+            remember,
+            format,
+        } => {
+            let mut ctx = ProgramContext::load_or_create(&cli.context)?;
+            if !(*fit_weight >= 0.0 && fit_weight.is_finite()) {
+                return Err(CliError::User(format!(
+                    "Invalid fit weight: {} (expected a non-negative number)",
+                    fit_weight
+                )));
+            }
+            // Quiet is already set for ndjson (see main), keeping stdout to JSON lines
+            if matches!(format, OutputFormat::Ndjson)
+                && (*stats || *measure || *include_original || *diagnose)
+            {
+                return Err(CliError::User(
+                    "--stats, --measure, --include-original and --diagnose can't be combined with --format ndjson"
+                        .to_string(),
+                ));
+            }
+            // The updated context would be written to stdout along with the proposals
+            if *remember && is_stdio_path(&cli.context) {
+                return Err(CliError::User(
+                    "--remember can't be combined with --context -".to_string(),
+                ));
+            }
+            if anchor.is_some() && matches!(algorithm, Algorithm::Exhaustive) {
+                return Err(CliError::User(
+                    "--anchor only applies to --algorithm sample".to_string(),
+                ));
+            }
+            if (*fix_first || *fix_last) && matches!(algorithm, Algorithm::Exhaustive) {
+                return Err(CliError::User(
+                    "--fix-first and --fix-last only apply to --algorithm sample".to_string(),
+                ));
+            }
+            let search = match algorithm {
+                Algorithm::Exhaustive => Search::Exhaustive,
+                Algorithm::Sample => Search::Sample {
+                    samples: *samples,
+                    seed: *seed,
+                    fix_first: *fix_first,
+                    fix_last: *fix_last,
+                },
+            };
+            let opts = ProposeOptions {
+                count: *count,
+                search,
+                min_score: *min_score,
+                diagnose: *diagnose,
+                cache_path: (!no_cache && !is_stdio_path(&cli.context))
+                    .then(|| cache_path_for(&cli.context)),
+                side_labels: *side_labels,
+                stats: *stats,
+                measure: *measure,
+                include_original: *include_original,
+                prefer_original: *prefer_original,
+                sort_by: *sort_by,
+                group_by: *group_by,
+                format: *format,
+                anchor: anchor.clone().map(|name| (name, *anchor_strength)),
+                fit_weight: *fit_weight,
+                allow_partial: *allow_partial,
+            };
+            let max_duration = max_duration
+                .as_deref()
+                .map(|d| {
+                    parse_duration(d)
+                        .ok_or_else(|| CliError::User(format!("Invalid duration format: {}", d)))
+                })
+                .transpose()?;
+            let shown = handle_propose(&ctx, tracklist, medium, *sides, max_duration, &opts)?;
+            if *remember {
+                ctx.last_proposals = Some(shown);
+                ctx.save(&cli.context)?;
+            }
+        }
+
+        Commands::LastProposals => {
+            let ctx = ProgramContext::load_or_create(&cli.context)?;
+            handle_last_proposals(&ctx)?;
+        }
+
+        Commands::ProposeAll {
+            tracklist,
+            count,
+            no_cache,
+            side_labels,
+        } => {
+            let ctx = ProgramContext::load_or_create(&cli.context)?;
+            let opts = ProposeOptions {
+                count: *count,
+                search: Search::Exhaustive,
+                min_score: None,
+                diagnose: false,
+                cache_path: (!no_cache && !is_stdio_path(&cli.context))
+                    .then(|| cache_path_for(&cli.context)),
+                side_labels: *side_labels,
+                stats: false,
+                measure: false,
+                include_original: false,
+                prefer_original: false,
+                sort_by: SortBy::Score,
+                group_by: None,
+                format: OutputFormat::Table,
+                anchor: None,
+                fit_weight: 0.0,
+                allow_partial: false,
+            };
+            handle_propose_all(&ctx, tracklist, &opts)?;
+        }
+
+        Commands::ProposeSet {
+            tracklists,
+            medium,
+            count,
+            side_labels,
+        } => {
+            let ctx = ProgramContext::load_or_create(&cli.context)?;
+            handle_propose_set(&ctx, tracklists, medium, *count, *side_labels)?;
+        }
+
+        Commands::Heatmap {
+            tracklist,
+            medium,
+            samples,
+            seed,
+        } => {
+            let ctx = ProgramContext::load_or_create(&cli.context)?;
+            handle_heatmap(&ctx, tracklist, medium, *samples, *seed)?;
+        }
+
+        Commands::Layout {
+            tracklist,
+            medium,
+            side_labels,
+        } => {
+            let ctx = ProgramContext::load_or_create(&cli.context)?;
+            handle_layout(&ctx, tracklist, medium, *side_labels)?;
+        }
+
+        Commands::Sheet {
+            tracklist,
+            medium,
+            output,
+            side_labels,
+        } => {
+            let ctx = ProgramContext::load_or_create(&cli.context)?;
+            handle_sheet(&ctx, tracklist, medium, output, *side_labels)?;
+        }
+
+        Commands::Capacity { medium } => {
+            let ctx = ProgramContext::load_or_create(&cli.context)?;
+            handle_capacity(&ctx, medium)?;
+        }
+
+        Commands::FitMedium { tracklist } => {
+            let ctx = ProgramContext::load_or_create(&cli.context)?;
+            handle_fit_medium(&ctx, tracklist)?;
+        }
+
+        Commands::Split {
+            tracklist,
+            medium,
+            into,
         } => {
-            let ctx = ProgramContext::load_or_create(&cli.context);
-            handle_propose(&ctx, tracklist, medium, count, min_score);
+            let mut ctx = ProgramContext::load_or_create(&cli.context)?;
+            handle_split(&mut ctx, tracklist, medium, *into)?;
+            ctx.save(&cli.context)?;
         }
     }
+
+    Ok(())
+}
+
+/// Returns true if the command prints newline-delimited JSON, which informational
+/// messages on stdout would break.
+fn writes_json_lines(command: &Commands) -> bool {
+    matches!(
+        command,
+        Commands::Propose {
+            format: OutputFormat::Ndjson,
+            ..
+        }
+    )
+}
+
+fn main() {
+    let cli = Cli::parse();
+    init_logger(cli.verbose);
+    if cli.floor_durations {
+        set_duration_rounding(Rounding::Floor);
+    }
+    // With the context or JSON lines on stdout, keep informational messages out of it
+    set_quiet(cli.quiet || is_stdio_path(&cli.context) || writes_json_lines(&cli.command));
+    set_minified(cli.minified_context);
+
+    if let Err(e) = run(&cli) {
+        eprintln!("{}", e);
+        std::process::exit(e.exit_code());
+    }
 }
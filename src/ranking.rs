@@ -0,0 +1,341 @@
+//! # Proposal Ranking
+//!
+//! This module orders scored permutations and keeps the best ones of a run without
+//! holding every permutation in memory. [`TopProposals`] is a bounded heap: memory
+//! stays proportional to the number of proposals shown, however many are scored,
+//! and the result is the same as sorting everything with [`compare_proposals`].
+//! With [`TopProposals::preferring`], equal scores are first broken by how close each
+//! proposal stays to the original order. [`TopProposals::push_ranked`] ranks by another
+//! key than the score, such as side balance.
+//!
+//! ## Example
+//! ```rust
+//! let mut top = TopProposals::new(opts.count);
+//! if top.would_keep(score) {
+//!     top.push(score, tracklist);
+//! }
+//! let best_first = top.into_sorted_vec();
+//! ```
+
+use crate::scoring::Score;
+use albumseq::Tracklist;
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+
+/// Orders scored permutations by descending score.
+/// Ties are broken by comparing the track titles in order, lexicographically,
+/// so that equal-score proposals always come out in the same order.
+pub fn compare_proposals(a: &(Score, Tracklist), b: &(Score, Tracklist)) -> Ordering {
+    b.0.total_cmp(&a.0).then_with(|| {
+        let a_titles = a.1.0.iter().map(|t| &t.title);
+        let b_titles = b.1.0.iter().map(|t| &t.title);
+        a_titles.cmp(b_titles)
+    })
+}
+
+/// A scored permutation ordered by descending `key` (its score unless ranked by
+/// another key), then by [`compare_proposals`], with equal scores first ordered by
+/// `deviation` (zero unless preferring an order): better proposals are smaller.
+struct Ranked {
+    key: Score,
+    proposal: (Score, Tracklist),
+    deviation: usize,
+}
+
+impl PartialEq for Ranked {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Ranked {}
+
+impl PartialOrd for Ranked {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Ranked {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .key
+            .total_cmp(&self.key)
+            .then(other.proposal.0.total_cmp(&self.proposal.0))
+            .then(self.deviation.cmp(&other.deviation))
+            .then_with(|| compare_proposals(&self.proposal, &other.proposal))
+    }
+}
+
+/// Keeps the best `capacity` scored permutations pushed to it.
+///
+/// The heap's top is the worst proposal kept, so a better one replaces it in
+/// `O(log capacity)`.
+pub struct TopProposals {
+    capacity: usize,
+    heap: BinaryHeap<Ranked>,
+    /// Positions of the titles in the preferred order, if any.
+    preferred: Option<HashMap<String, usize>>,
+}
+
+impl TopProposals {
+    /// Creates an empty selection keeping at most `capacity` proposals.
+    pub fn new(capacity: usize) -> Self {
+        TopProposals {
+            capacity,
+            heap: BinaryHeap::with_capacity(capacity.saturating_add(1).min(1024)),
+            preferred: None,
+        }
+    }
+
+    /// Breaks ties between equal scores in favour of the proposals closest to
+    /// `order`: the smallest sum over tracks of the distance between their position
+    /// and their position in `order`. Remaining ties are broken by title.
+    pub fn preferring(mut self, order: &Tracklist) -> Self {
+        self.preferred = Some(
+            order
+                .0
+                .iter()
+                .enumerate()
+                .map(|(i, t)| (t.title.clone(), i))
+                .collect(),
+        );
+        self
+    }
+
+    /// Returns whether a proposal with this score (or key, see [`TopProposals::push_ranked`])
+    /// could be kept, so callers can skip building its tracklist. Equal scores may still
+    /// be kept by the title tie-break.
+    pub fn would_keep(&self, key: Score) -> bool {
+        if self.heap.len() < self.capacity {
+            return true;
+        }
+        self.heap.peek().is_some_and(|worst| key >= worst.key)
+    }
+
+    /// Adds a proposal, dropping the worst one when over capacity.
+    pub fn push(&mut self, score: Score, tracklist: Tracklist) {
+        self.push_ranked(score, score, tracklist);
+    }
+
+    /// Adds a proposal ranked by `key` (higher is better) instead of its score; the
+    /// score breaks ties. A selection should be ranked by one kind of key throughout.
+    pub fn push_ranked(&mut self, key: Score, score: Score, tracklist: Tracklist) {
+        if self.capacity == 0 {
+            return;
+        }
+        let deviation = self.preferred.as_ref().map_or(0, |positions| {
+            tracklist
+                .0
+                .iter()
+                .enumerate()
+                .map(|(i, t)| positions.get(&t.title).map_or(0, |&pos| pos.abs_diff(i)))
+                .sum()
+        });
+        self.heap.push(Ranked {
+            key,
+            proposal: (score, tracklist),
+            deviation,
+        });
+        if self.heap.len() > self.capacity {
+            self.heap.pop();
+        }
+    }
+
+    /// Returns the kept proposals, best first.
+    pub fn into_sorted_vec(self) -> Vec<(Score, Tracklist)> {
+        self.heap
+            .into_sorted_vec()
+            .into_iter()
+            .map(|ranked| ranked.proposal)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use albumseq::Track;
+
+    /// Every order of the tracks, in lexicographic order of positions.
+    fn permutations(tracks: &[Track]) -> Vec<Vec<Track>> {
+        if tracks.is_empty() {
+            return vec![Vec::new()];
+        }
+        let mut all = Vec::new();
+        for i in 0..tracks.len() {
+            let mut rest = tracks.to_vec();
+            let first = rest.remove(i);
+            for mut perm in permutations(&rest) {
+                perm.insert(0, first.clone());
+                all.push(perm);
+            }
+        }
+        all
+    }
+
+    /// The 720 orders of six tracks, with scores from 0 to 4 so that many tie.
+    fn scored() -> Vec<(Score, Vec<Track>)> {
+        let tracks: Vec<Track> = ["A", "B", "C", "D", "E", "F"]
+            .iter()
+            .enumerate()
+            .map(|(i, title)| Track {
+                title: title.to_string(),
+                duration: 2.0 + i as f64,
+            })
+            .collect();
+        permutations(&tracks)
+            .into_iter()
+            .enumerate()
+            .map(|(i, perm)| (((i * 7) % 5) as Score, perm))
+            .collect()
+    }
+
+    fn titles(proposals: &[(Score, Tracklist)]) -> Vec<(Score, Vec<String>)> {
+        proposals
+            .iter()
+            .map(|(score, tl)| (*score, tl.0.iter().map(|t| t.title.clone()).collect()))
+            .collect()
+    }
+
+    #[test]
+    fn heap_keeps_the_same_top_as_a_full_sort() {
+        for count in [0, 1, 5, 37, 720, 1000] {
+            let mut top = TopProposals::new(count);
+            for (score, perm) in scored() {
+                if top.would_keep(score) {
+                    top.push(score, Tracklist(perm));
+                }
+            }
+
+            let mut all: Vec<(Score, Tracklist)> = scored()
+                .into_iter()
+                .map(|(score, perm)| (score, Tracklist(perm)))
+                .collect();
+            all.sort_by(compare_proposals);
+            all.truncate(count);
+
+            assert_eq!(
+                titles(&top.into_sorted_vec()),
+                titles(&all),
+                "count {}",
+                count
+            );
+        }
+    }
+
+    #[test]
+    fn preferring_breaks_ties_by_distance_to_the_order() {
+        let original = Tracklist(scored()[0].1.clone());
+        let deviation = |perm: &[Track]| -> usize {
+            perm.iter()
+                .enumerate()
+                .map(|(i, t)| {
+                    let pos = original.0.iter().position(|o| o.title == t.title).unwrap();
+                    pos.abs_diff(i)
+                })
+                .sum()
+        };
+
+        let mut top = TopProposals::new(25).preferring(&original);
+        for (score, perm) in scored() {
+            top.push(score, Tracklist(perm));
+        }
+
+        let mut all: Vec<(Score, Vec<Track>)> = scored();
+        all.sort_by(|a, b| {
+            b.0.total_cmp(&a.0)
+                .then(deviation(&a.1).cmp(&deviation(&b.1)))
+                .then_with(|| {
+                    compare_proposals(
+                        &(a.0, Tracklist(a.1.clone())),
+                        &(b.0, Tracklist(b.1.clone())),
+                    )
+                })
+        });
+        let all: Vec<(Score, Tracklist)> = all
+            .into_iter()
+            .take(25)
+            .map(|(score, perm)| (score, Tracklist(perm)))
+            .collect();
+
+        assert_eq!(titles(&top.into_sorted_vec()), titles(&all));
+    }
+
+    #[test]
+    fn push_ranked_orders_by_key_then_score() {
+        let mut top = TopProposals::new(2);
+        for (key, score, title) in [(1.0, 5.0, "low key"), (3.0, 1.0, "best"), (3.0, 2.0, "tie")] {
+            let track = Track {
+                title: title.to_string(),
+                duration: 3.0,
+            };
+            top.push_ranked(key, score, Tracklist(vec![track]));
+        }
+
+        let kept = titles(&top.into_sorted_vec());
+        assert_eq!(kept[0], (2.0, vec!["tie".to_string()]));
+        assert_eq!(kept[1], (1.0, vec!["best".to_string()]));
+    }
+
+    #[test]
+    fn equal_scores_come_out_in_title_order_whatever_the_push_order() {
+        let tracklist = |titles: &[&str]| {
+            Tracklist(
+                titles
+                    .iter()
+                    .map(|title| Track {
+                        title: title.to_string(),
+                        duration: 3.0,
+                    })
+                    .collect(),
+            )
+        };
+        let expected = vec![
+            (2.0, vec!["A".to_string(), "B".to_string()]),
+            (2.0, vec!["B".to_string(), "A".to_string()]),
+        ];
+
+        for pushed in [[["A", "B"], ["B", "A"]], [["B", "A"], ["A", "B"]]] {
+            let mut top = TopProposals::new(2);
+            for titles in pushed {
+                top.push(2.0, tracklist(&titles));
+            }
+            assert_eq!(titles(&top.into_sorted_vec()), expected);
+        }
+    }
+
+    #[test]
+    fn fractional_scores_sort_between_whole_ones() {
+        let mut top = TopProposals::new(4);
+        for (score, title) in [
+            (1.0, "one"),
+            (1.75, "three quarters"),
+            (2.0, "two"),
+            (1.5, "half"),
+        ] {
+            let track = Track {
+                title: title.to_string(),
+                duration: 3.0,
+            };
+            top.push(score, Tracklist(vec![track]));
+        }
+
+        let kept: Vec<Score> = top
+            .into_sorted_vec()
+            .iter()
+            .map(|(score, _)| *score)
+            .collect();
+        assert_eq!(kept, [2.0, 1.75, 1.5, 1.0]);
+
+        let proposal = |score: Score| (score, Tracklist(Vec::new()));
+        assert_eq!(
+            compare_proposals(&proposal(0.33), &proposal(0.3)),
+            Ordering::Less
+        );
+        assert_eq!(
+            compare_proposals(&proposal(0.3), &proposal(0.33)),
+            Ordering::Greater
+        );
+    }
+}
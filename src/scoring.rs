@@ -0,0 +1,1564 @@
+//! # Constraint Scoring
+//!
+//! This module scores tracklist permutations against the constraints stored in the context.
+//! Constraint kinds known to `albumseq` are delegated to [`albumseq::score_tracklist`], while
+//! kinds only defined by albumseq_cli are evaluated here.
+//!
+//! ## Main Types
+//! - [`Scorer`]: Scores tracklists against a fixed constraint set and medium.
+//! - [`Gaps`]: Silence after tracks, counted toward side durations.
+//! - [`TrackIndex`]: Finds the stored track a track of a permutation stands for.
+//!
+//! ## Example
+//! ```rust
+//! let scorer = Scorer::new(&ctx.constraints, &medium, &ser_tl.tracks, &ctx.tracklists);
+//! let score = scorer.score(&tracklist);
+//! ```
+
+use crate::context::{NamedSerTracklist, SerConstraint, SerConstraintKind, SerTrack, SerTracklist};
+use crate::utils::split_duration_qualifier;
+use albumseq::{Constraint as AlbumConstraint, Duration, Medium as AlbumMedium, Track, Tracklist};
+use log::warn;
+use std::collections::HashMap;
+
+/// The score of a tracklist. Graded constraints award fractional points, so
+/// close layouts don't tie as often as with whole points.
+pub type Score = f64;
+
+/// How far (in minutes, i.e. half a second) a side may run over the medium's max
+/// duration and still fit, so that floating-point error in summed durations doesn't
+/// reject a side that fills the limit exactly.
+pub const FIT_TOLERANCE: Duration = 0.5 / 60.0;
+
+/// Number of sides on one disc, for `DiscBalance`.
+pub const SIDES_PER_DISC: usize = 2;
+
+/// Fraction of a `PreferSameSide` weight awarded when the two tracks are on
+/// neighbouring sides instead of the same one.
+pub const NEIGHBOURING_SIDES_FRACTION: Score = 0.5;
+
+/// Distance from its target position at which a `NearPosition` track earns nothing;
+/// each position closer earns an equal share of the weight.
+pub const NEAR_POSITION_REACH: usize = 4;
+
+/// Largest difference between a track's duration and the duration qualifier of a
+/// constraint argument (see [`split_duration_qualifier`]) for the track to match.
+/// Qualifiers are in whole seconds, so this is half a second.
+pub const QUALIFIER_TOLERANCE: Duration = 0.5 / 60.0;
+
+/// Finds the position in the stored tracklist of the track a track of a permutation
+/// stands for, so that tracks sharing a title keep their own gap and metadata.
+///
+/// Permutation tracks are copies of the stored ones, so they are matched by title and
+/// exact duration, the way duration-qualified constraint arguments ("Song One@3:45")
+/// tell same-titled tracks apart. A track matching no stored track that way falls back
+/// to the first stored track with its title.
+#[derive(Default)]
+pub struct TrackIndex<'a> {
+    exact: HashMap<(&'a str, u64), usize>,
+    by_title: HashMap<&'a str, usize>,
+}
+
+impl<'a> TrackIndex<'a> {
+    /// Indexes the tracks of a stored tracklist.
+    pub fn of(tracks: &'a SerTracklist) -> Self {
+        let mut index = TrackIndex::default();
+        for (i, t) in tracks.0.iter().enumerate() {
+            index
+                .exact
+                .entry((t.title.as_str(), t.duration.to_bits()))
+                .or_insert(i);
+            index.by_title.entry(t.title.as_str()).or_insert(i);
+        }
+        index
+    }
+
+    /// Returns the stored position of the track, if it's in the tracklist.
+    pub fn get(&self, track: &Track) -> Option<usize> {
+        self.exact
+            .get(&(track.title.as_str(), track.duration.to_bits()))
+            .or_else(|| self.by_title.get(track.title.as_str()))
+            .copied()
+    }
+}
+
+/// Silence after each track of a stored tracklist, looked up by stored track (see
+/// [`TrackIndex`]).
+///
+/// A gap counts toward a side's duration when another track follows on the same
+/// side; the gap after a side's last track is not recorded on the medium.
+#[derive(Default)]
+pub struct Gaps<'a> {
+    index: TrackIndex<'a>,
+    /// Gap after each stored track, by position.
+    gaps: Vec<Duration>,
+}
+
+impl<'a> Gaps<'a> {
+    /// Collects the gaps of the tracks of a stored tracklist.
+    pub fn of(tracks: &'a SerTracklist) -> Self {
+        Gaps {
+            index: TrackIndex::of(tracks),
+            gaps: tracks
+                .0
+                .iter()
+                .map(|t| t.gap_after.filter(|gap| *gap > 0.0).unwrap_or(0.0))
+                .collect(),
+        }
+    }
+
+    /// Returns the gap after a track, zero if it has none.
+    pub fn after(&self, track: &Track) -> Duration {
+        self.index.get(track).map_or(0.0, |i| self.gaps[i])
+    }
+
+    /// Returns the duration of a side: its tracks and the gaps between them.
+    pub fn side_duration(&self, side: &[&Track]) -> Duration {
+        let tracks: Duration = side.iter().map(|t| t.duration).sum();
+        let gaps: Duration = side.iter().rev().skip(1).map(|t| self.after(t)).sum();
+        tracks + gaps
+    }
+}
+
+/// Splits a tracklist into sides based on medium max duration per side, counting the
+/// gap after each track that is followed by another track on the same side.
+/// Returns a vector of vectors, each representing a side; an empty tracklist has no sides.
+pub fn split_tracklist_by_side<'a>(
+    tracklist: &'a Tracklist,
+    medium: &'a AlbumMedium,
+    gaps: &Gaps,
+) -> Vec<Vec<&'a Track>> {
+    split_tracks_by_side(&tracklist.0, medium, gaps)
+}
+
+/// Splits tracks into sides like [`split_tracklist_by_side`].
+fn split_tracks_by_side<'a>(
+    tracks: &'a [Track],
+    medium: &AlbumMedium,
+    gaps: &Gaps,
+) -> Vec<Vec<&'a Track>> {
+    let mut sides = Vec::new();
+    let mut current_side: Vec<&Track> = Vec::new();
+    let mut current_duration = 0.0;
+
+    for track in tracks {
+        let gap = current_side.last().map_or(0.0, |prev| gaps.after(prev));
+        if current_duration + gap + track.duration <= medium.max_duration_per_side + FIT_TOLERANCE {
+            current_side.push(track);
+            current_duration += gap + track.duration;
+        } else {
+            sides.push(current_side);
+            current_side = vec![track];
+            current_duration = track.duration;
+        }
+
+        if sides.len() == medium.sides {
+            break;
+        }
+    }
+
+    if !current_side.is_empty() {
+        sides.push(current_side);
+    }
+
+    sides
+}
+
+/// Checks whether a tracklist fits the medium once the gaps between tracks are counted:
+/// every track is placed, on at most `medium.sides` sides, none over the side length
+/// by more than [`FIT_TOLERANCE`].
+pub fn fits_medium(tracklist: &Tracklist, medium: &AlbumMedium, gaps: &Gaps) -> bool {
+    let sides = split_tracklist_by_side(tracklist, medium, gaps);
+    let placed: usize = sides.iter().map(|side| side.len()).sum();
+    placed == tracklist.0.len()
+        && sides.len() <= medium.sides
+        && sides
+            .iter()
+            .all(|side| gaps.side_duration(side) <= medium.max_duration_per_side + FIT_TOLERANCE)
+}
+
+/// Scores tracklists against a set of stored constraints on a given medium.
+///
+/// The constraints are split once on construction so that scoring many
+/// permutations doesn't repeatedly convert them. Per-track metadata (tags, gaps, ...)
+/// is looked up in the stored tracklist the permutations come from, by stored track
+/// (see [`TrackIndex`]), so same-titled tracks keep their own.
+///
+/// Side-based constraints evaluated here count the gaps between tracks; the ones
+/// delegated to albumseq (e.g. `OnSameSide`) use albumseq's own side split.
+///
+/// A scorer for a box set (see [`Scorer::with_discs`]) splits each disc into sides
+/// of its own.
+pub struct Scorer<'a> {
+    medium: &'a AlbumMedium,
+    source: &'a SerTracklist,
+    constraints: &'a [SerConstraint],
+    index: TrackIndex<'a>,
+    gaps: Gaps<'a>,
+    album_constraints: Vec<AlbumConstraint>,
+    local_constraints: Vec<&'a SerConstraint>,
+    /// Track positions of the reference tracklists used by `StayCloseTo`, keyed by
+    /// lowercase tracklist name. Only references holding the same tracks as `source`.
+    references: HashMap<String, HashMap<&'a str, usize>>,
+    /// Number of tracks on each disc of a box set, in disc order; empty for a single
+    /// tracklist.
+    discs: Vec<usize>,
+}
+
+impl<'a> Scorer<'a> {
+    /// Creates a scorer for the given constraints and medium.
+    /// `source` is the stored tracklist the scored permutations are built from, and
+    /// `tracklists` are the stored tracklists that constraints may refer to.
+    pub fn new(
+        constraints: &'a [SerConstraint],
+        medium: &'a AlbumMedium,
+        source: &'a SerTracklist,
+        tracklists: &'a [NamedSerTracklist],
+    ) -> Self {
+        let mut album_constraints = Vec::new();
+        let mut local_constraints = Vec::new();
+        let mut references = HashMap::new();
+
+        for c in constraints.iter().filter(|c| c.enabled) {
+            match c.to_album_constraint() {
+                Some(ac) => album_constraints.push(ac),
+                None => local_constraints.push(c),
+            }
+
+            if let SerConstraintKind::StayCloseTo(name) = &c.kind
+                && let Some(positions) = reference_positions(source, tracklists, name)
+            {
+                references.insert(name.to_lowercase(), positions);
+            }
+        }
+
+        Scorer {
+            medium,
+            source,
+            constraints,
+            index: TrackIndex::of(source),
+            gaps: Gaps::of(source),
+            album_constraints,
+            local_constraints,
+            references,
+            discs: Vec::new(),
+        }
+    }
+
+    /// Makes this a scorer for a box set: the scored tracklists are discs played one
+    /// after the other, with `disc_lengths` tracks each, and every disc goes on its own
+    /// copy of the medium.
+    ///
+    /// Each disc is split into sides on its own, numbered on from the previous disc's
+    /// (with 2 sides per disc, side 2 is the first side of the second disc); positions
+    /// count across the whole set. Every constraint is evaluated here, as albumseq
+    /// would split the set as one tracklist.
+    pub fn with_discs(mut self, disc_lengths: Vec<usize>) -> Self {
+        self.album_constraints.clear();
+        self.local_constraints = self.constraints.iter().filter(|c| c.enabled).collect();
+        self.discs = disc_lengths;
+        self
+    }
+
+    /// Splits a tracklist into sides: on the medium, or disc by disc for a box set.
+    pub fn sides<'t>(&self, tracklist: &'t Tracklist) -> Vec<Vec<&'t Track>> {
+        if self.discs.is_empty() {
+            return split_tracks_by_side(&tracklist.0, self.medium, &self.gaps);
+        }
+
+        let mut sides = Vec::new();
+        let mut start = 0;
+        for &length in &self.discs {
+            let end = (start + length).min(tracklist.0.len());
+            sides.extend(split_tracks_by_side(
+                &tracklist.0[start..end],
+                self.medium,
+                &self.gaps,
+            ));
+            start = end;
+        }
+        sides
+    }
+
+    /// Returns the total score of a tracklist: the sum of the weights of all satisfied
+    /// constraints, plus the (possibly fractional) points of graded constraints.
+    pub fn score(&self, tracklist: &Tracklist) -> Score {
+        let album_score =
+            albumseq::score_tracklist(tracklist, &self.album_constraints, self.medium) as Score;
+        let sides = self.sides(tracklist);
+        let local_score: Score = self
+            .local_constraints
+            .iter()
+            .map(|c| self.local_points(c, tracklist, &sides))
+            .sum();
+
+        album_score + local_score
+    }
+
+    /// Returns the points each constraint awards a tracklist, in the order the
+    /// constraints were given; `None` for disabled constraints. The points sum to
+    /// [`Scorer::score`].
+    pub fn breakdown(&self, tracklist: &Tracklist) -> Vec<Option<Score>> {
+        let sides = self.sides(tracklist);
+        self.constraints
+            .iter()
+            .map(|c| {
+                let album_constraint = c.to_album_constraint().filter(|_| self.discs.is_empty());
+                c.enabled.then(|| match album_constraint {
+                    Some(ac) => albumseq::score_tracklist(tracklist, &[ac], self.medium) as Score,
+                    None => self.local_points(c, tracklist, &sides),
+                })
+            })
+            .collect()
+    }
+
+    /// Returns the points awarded by a constraint that albumseq doesn't know about.
+    /// Most kinds award their full weight or nothing; graded kinds return early.
+    /// `sides` is the tracklist split into sides.
+    fn local_points(
+        &self,
+        constraint: &SerConstraint,
+        tracklist: &Tracklist,
+        sides: &[Vec<&Track>],
+    ) -> Score {
+        let weight = constraint.weight as Score;
+        let satisfied = match &constraint.kind {
+            SerConstraintKind::SideSortedByDuration(side, ascending) => {
+                side_sorted_by_duration(sides, *side, *ascending)
+            }
+            SerConstraintKind::MaxSideBreaksBetween(t1, t2) => no_side_break_between(sides, t1, t2),
+            SerConstraintKind::SpreadTag(tag, min_gap) => self.tag_spread(tracklist, tag, *min_gap),
+            SerConstraintKind::LongestNotSideCloser => longest_not_side_closer(sides),
+            SerConstraintKind::StartsBefore(title, minutes) => {
+                self.starts_before(tracklist, title, *minutes)
+            }
+            SerConstraintKind::StrongSideOpeners(min_energy) => {
+                self.strong_side_openers(sides, *min_energy)
+            }
+            SerConstraintKind::MaxHardCuts(max_cuts) => self.hard_cuts(sides) <= *max_cuts,
+            SerConstraintKind::AtAnyPosition(title, positions) => {
+                at_any_position(tracklist, title, positions)
+            }
+            SerConstraintKind::Bookend(opener, closer) => bookend(tracklist, opener, closer),
+            // Only reached for a box set, or with a duration-qualified argument, which
+            // albumseq can't match
+            SerConstraintKind::AtPosition(title, pos) => {
+                tracklist.0.get(*pos).is_some_and(|t| refers_to(title, t))
+            }
+            SerConstraintKind::Adjacent(t1, t2) => {
+                match (position_of(tracklist, t1), position_of(tracklist, t2)) {
+                    (Some(p1), Some(p2)) => p1.abs_diff(p2) == 1,
+                    _ => false,
+                }
+            }
+            SerConstraintKind::OnSameSide(t1, t2) => {
+                side_of(sides, t1).is_some_and(|side| side_of(sides, t2) == Some(side))
+            }
+            SerConstraintKind::SideContains(side, titles, exact) => {
+                side_contains(sides, *side, titles, *exact)
+            }
+            SerConstraintKind::SideNearFull(target, tolerance) => {
+                self.sides_near_full(sides, *target, *tolerance)
+            }
+            SerConstraintKind::SideBalance(tolerance) => self.sides_balanced(sides, *tolerance),
+            SerConstraintKind::DiscBalance(tolerance) => self.discs_balanced(sides, *tolerance),
+            SerConstraintKind::MaxArtistPerSide(max) => self.max_artist_per_side(sides, *max),
+            SerConstraintKind::SmoothLoudness(max_jump) => {
+                self.smooth_loudness(tracklist, *max_jump)
+            }
+            SerConstraintKind::FirstBreakAfter(minutes) => {
+                sides
+                    .first()
+                    .map_or(0.0, |side| self.gaps.side_duration(side))
+                    >= *minutes
+            }
+            SerConstraintKind::AlternatePacing(threshold) => {
+                let is_short = |track: &Track| track.duration < *threshold;
+                tracklist
+                    .0
+                    .windows(2)
+                    .all(|pair| is_short(&pair[0]) != is_short(&pair[1]))
+            }
+            SerConstraintKind::StayCloseTo(name) => {
+                return self.closeness_points(tracklist, name, weight);
+            }
+            SerConstraintKind::FillSides(target) => {
+                return self.fill_sides_points(sides, *target, weight);
+            }
+            SerConstraintKind::ShortToInner(scale) => {
+                return short_to_inner_points(sides, weight * *scale as Score);
+            }
+            SerConstraintKind::PreferSameSide(t1, t2) => {
+                return prefer_same_side_points(sides, t1, t2, weight);
+            }
+            SerConstraintKind::NearPosition(title, pos) => {
+                return near_position_points(tracklist, title, *pos, weight);
+            }
+        };
+
+        if satisfied { weight } else { 0.0 }
+    }
+
+    /// Scores how close the permutation is to the order of a reference tracklist.
+    ///
+    /// The displacement is the sum over tracks of the absolute difference between the
+    /// track's position and its position in the reference. The result is
+    /// `max_points * (max - displacement) / max`, where `max` is the largest possible
+    /// displacement for this many tracks (a full reversal).
+    /// An unusable reference (missing, or different tracks) awards nothing.
+    fn closeness_points(&self, tracklist: &Tracklist, reference: &str, max_points: Score) -> Score {
+        let Some(positions) = self.references.get(&reference.to_lowercase()) else {
+            return 0.0;
+        };
+
+        let n = tracklist.0.len();
+        let max_displacement = n * n / 2;
+        if max_displacement == 0 {
+            return max_points;
+        }
+
+        let displacement: usize = tracklist
+            .0
+            .iter()
+            .enumerate()
+            .map(|(i, t)| {
+                positions
+                    .get(t.title.as_str())
+                    .map_or(n, |&pos| pos.abs_diff(i))
+            })
+            .sum();
+
+        max_points * max_displacement.saturating_sub(displacement) as Score
+            / max_displacement as Score
+    }
+
+    /// Returns the stored metadata for a track of a permutation.
+    fn track_info(&self, track: &Track) -> Option<&'a SerTrack> {
+        self.index.get(track).map(|i| &self.source.0[i])
+    }
+
+    /// Checks that the first track of every side has at least `min_energy`.
+    /// Tracks without an energy rating fail the check.
+    fn strong_side_openers(&self, sides: &[Vec<&Track>], min_energy: u8) -> bool {
+        sides.iter().filter_map(|side| side.first()).all(|opener| {
+            self.track_info(opener)
+                .and_then(|info| info.energy)
+                .is_some_and(|energy| energy >= min_energy)
+        })
+    }
+
+    /// Checks that no side has more than `max` tracks by the same artist (compared
+    /// case-insensitively). Tracks without an artist don't count.
+    fn max_artist_per_side(&self, sides: &[Vec<&Track>], max: usize) -> bool {
+        sides.iter().all(|side| {
+            let mut counts: HashMap<String, usize> = HashMap::new();
+            for track in side {
+                if let Some(artist) = self.track_info(track).and_then(|info| info.artist.as_ref()) {
+                    *counts.entry(artist.to_lowercase()).or_default() += 1;
+                }
+            }
+            counts.values().all(|&count| count <= max)
+        })
+    }
+
+    /// Checks that every two consecutive tracks differ in loudness by at most
+    /// `max_jump`. Pairs where a track has no loudness are skipped.
+    fn smooth_loudness(&self, tracklist: &Tracklist, max_jump: f64) -> bool {
+        let loudness = |track: &Track| self.track_info(track).and_then(|info| info.loudness);
+
+        tracklist
+            .0
+            .windows(2)
+            .all(|pair| match (loudness(&pair[0]), loudness(&pair[1])) {
+                (Some(a), Some(b)) => (a - b).abs() <= max_jump,
+                _ => true,
+            })
+    }
+
+    /// Counts the side breaks where both the side's closer and the next side's
+    /// opener are marked continuous.
+    fn hard_cuts(&self, sides: &[Vec<&Track>]) -> usize {
+        let is_continuous =
+            |track: &Track| self.track_info(track).is_some_and(|info| info.continuous);
+
+        sides
+            .windows(2)
+            .filter(|pair| match (pair[0].last(), pair[1].first()) {
+                (Some(closer), Some(opener)) => is_continuous(closer) && is_continuous(opener),
+                _ => false,
+            })
+            .count()
+    }
+
+    /// Checks that the cumulative duration of the tracks (and gaps) before `title`
+    /// is under `minutes`. A missing track never satisfies the constraint.
+    fn starts_before(&self, tracklist: &Tracklist, title: &str, minutes: Duration) -> bool {
+        let Some(index) = position_of(tracklist, title) else {
+            return false;
+        };
+
+        let start: Duration = tracklist.0[..index]
+            .iter()
+            .map(|t| t.duration + self.gaps.after(t))
+            .sum();
+        start < minutes
+    }
+
+    /// Scores how full the sides of the medium are.
+    ///
+    /// Each side earns a fill ratio of its duration (gaps included) over `target` times
+    /// the side's max duration, capped at 1. The result is `max_points` times the mean
+    /// ratio over all sides of the medium (unused sides count as empty).
+    fn fill_sides_points(&self, sides: &[Vec<&Track>], target: f64, max_points: Score) -> Score {
+        let wanted = target * self.medium.max_duration_per_side;
+        if self.medium.sides == 0 || wanted <= 0.0 {
+            return max_points;
+        }
+
+        let fill: f64 = sides
+            .iter()
+            .map(|side| (self.gaps.side_duration(side) / wanted).min(1.0))
+            .sum();
+
+        max_points * fill / self.medium.sides as f64
+    }
+
+    /// Checks that every side of the medium is filled (gaps included) to within
+    /// `tolerance` of `target`, both as fractions of the side's max duration.
+    /// Unused sides count as empty.
+    fn sides_near_full(&self, sides: &[Vec<&Track>], target: f64, tolerance: f64) -> bool {
+        let max = self.medium.max_duration_per_side;
+        if max <= 0.0 {
+            return false;
+        }
+
+        (0..self.medium.sides).all(|i| {
+            let duration = sides
+                .get(i)
+                .map_or(0.0, |side| self.gaps.side_duration(side));
+            (duration / max - target).abs() <= tolerance
+        })
+    }
+
+    /// Checks that the durations (gaps included) of the medium's sides differ by at
+    /// most `tolerance`. Unused sides count as empty.
+    fn sides_balanced(&self, sides: &[Vec<&Track>], tolerance: Duration) -> bool {
+        let durations: Vec<Duration> = (0..self.medium.sides)
+            .map(|i| {
+                sides
+                    .get(i)
+                    .map_or(0.0, |side| self.gaps.side_duration(side))
+            })
+            .collect();
+
+        let longest = durations.iter().copied().fold(0.0, Duration::max);
+        let shortest = durations.iter().copied().fold(longest, Duration::min);
+        longest - shortest <= tolerance
+    }
+
+    /// Checks that the total runtimes (gaps included) of the medium's discs differ by
+    /// at most `tolerance`. A disc is a pair of consecutive sides (1-2, 3-4, ...); with
+    /// an odd number of sides the last disc has one. Unused sides count as empty.
+    fn discs_balanced(&self, sides: &[Vec<&Track>], tolerance: Duration) -> bool {
+        let discs: Vec<Duration> = (0..self.medium.sides)
+            .step_by(SIDES_PER_DISC)
+            .map(|first| {
+                (first..(first + SIDES_PER_DISC).min(self.medium.sides))
+                    .filter_map(|i| sides.get(i))
+                    .map(|side| self.gaps.side_duration(side))
+                    .sum()
+            })
+            .collect();
+
+        let longest = discs.iter().copied().fold(0.0, Duration::max);
+        let shortest = discs.iter().copied().fold(longest, Duration::min);
+        longest - shortest <= tolerance
+    }
+
+    /// Checks that every two consecutive tracks carrying the tag have at least
+    /// `min_gap` other tracks between them. Fewer than two tagged tracks always pass.
+    fn tag_spread(&self, tracklist: &Tracklist, tag: &str, min_gap: usize) -> bool {
+        let positions: Vec<usize> = tracklist
+            .0
+            .iter()
+            .enumerate()
+            .filter(|(_, t)| self.track_info(t).is_some_and(|info| info.has_tag(tag)))
+            .map(|(i, _)| i)
+            .collect();
+
+        positions.windows(2).all(|pair| pair[1] - pair[0] > min_gap)
+    }
+}
+
+/// Checks whether the durations on the given side (0-based) are monotonically ordered.
+/// Sides with fewer than two tracks are trivially sorted.
+fn side_sorted_by_duration(sides: &[Vec<&Track>], side: usize, ascending: bool) -> bool {
+    let Some(tracks) = sides.get(side) else {
+        return true;
+    };
+
+    tracks.windows(2).all(|pair| {
+        if ascending {
+            pair[0].duration <= pair[1].duration
+        } else {
+            pair[0].duration >= pair[1].duration
+        }
+    })
+}
+
+/// Checks that the given side (0-based) holds all the titles, in any order, and with
+/// `exact` no other track. A missing side never satisfies the constraint.
+fn side_contains(sides: &[Vec<&Track>], side: usize, titles: &[String], exact: bool) -> bool {
+    let Some(tracks) = sides.get(side) else {
+        return false;
+    };
+
+    titles
+        .iter()
+        .all(|title| tracks.iter().any(|t| refers_to(title, t)))
+        && (!exact
+            || tracks
+                .iter()
+                .all(|t| titles.iter().any(|title| refers_to(title, t))))
+}
+
+/// Checks whether a track is the one a constraint argument refers to: the same title
+/// and, if the argument has a duration qualifier ("Song One@3:45"), a duration within
+/// [`QUALIFIER_TOLERANCE`] of it.
+pub fn arg_matches(arg: &str, title: &str, duration: Duration) -> bool {
+    match split_duration_qualifier(arg) {
+        (wanted, Some(qualifier)) => {
+            title == wanted && (duration - qualifier).abs() <= QUALIFIER_TOLERANCE
+        }
+        (wanted, None) => title == wanted,
+    }
+}
+
+/// Checks whether a track of a permutation is the one a constraint argument refers to.
+fn refers_to(arg: &str, track: &Track) -> bool {
+    arg_matches(arg, &track.title, track.duration)
+}
+
+/// Returns the position of the first track a constraint argument refers to, if any.
+fn position_of(tracklist: &Tracklist, arg: &str) -> Option<usize> {
+    tracklist.0.iter().position(|t| refers_to(arg, t))
+}
+
+/// Returns the index of the side holding the track with the given title, if any.
+fn side_of(sides: &[Vec<&Track>], title: &str) -> Option<usize> {
+    sides
+        .iter()
+        .position(|side| side.iter().any(|t| refers_to(title, t)))
+}
+
+/// Checks that no side break falls between the two tracks, i.e. they share a side.
+/// Missing tracks never satisfy the constraint.
+fn no_side_break_between(sides: &[Vec<&Track>], title1: &str, title2: &str) -> bool {
+    match (side_of(sides, title1), side_of(sides, title2)) {
+        (Some(s1), Some(s2)) => s1 == s2,
+        _ => false,
+    }
+}
+
+/// Checks that on every side the closing track is strictly shorter than the side's
+/// longest track. Single-track sides are trivially satisfied.
+fn longest_not_side_closer(sides: &[Vec<&Track>]) -> bool {
+    sides.iter().filter(|side| side.len() >= 2).all(|side| {
+        let longest = side.iter().map(|t| t.duration).fold(0.0, f64::max);
+        side.last().is_some_and(|closer| closer.duration < longest)
+    })
+}
+
+/// Scores how well durations decrease toward the end of each side.
+///
+/// Every pair of neighbouring tracks on the same side is checked; a pair is a
+/// violation when the later track is longer than the earlier one. The result is
+/// `max_points * (pairs - violations) / pairs`. Layouts without any pair (only
+/// single-track sides) get the full points.
+fn short_to_inner_points(sides: &[Vec<&Track>], max_points: Score) -> Score {
+    let (pairs, violations) = sides.iter().flat_map(|side| side.windows(2)).fold(
+        (0usize, 0usize),
+        |(pairs, violations), pair| {
+            let violation = pair[1].duration > pair[0].duration;
+            (pairs + 1, violations + violation as usize)
+        },
+    );
+
+    if pairs == 0 {
+        return max_points;
+    }
+
+    max_points * (pairs - violations) as Score / pairs as Score
+}
+
+/// Scores how close two tracks are in terms of sides: `max_points` on the same
+/// side, [`NEIGHBOURING_SIDES_FRACTION`] of it on neighbouring sides, nothing
+/// further apart or when a track is missing.
+fn prefer_same_side_points(
+    sides: &[Vec<&Track>],
+    title1: &str,
+    title2: &str,
+    max_points: Score,
+) -> Score {
+    match (side_of(sides, title1), side_of(sides, title2)) {
+        (Some(s1), Some(s2)) if s1 == s2 => max_points,
+        (Some(s1), Some(s2)) if s1.abs_diff(s2) == 1 => max_points * NEIGHBOURING_SIDES_FRACTION,
+        _ => 0.0,
+    }
+}
+
+/// Scores how close a track is to its target position: `max_points` at the position,
+/// a linearly decreasing share of it up to [`NEAR_POSITION_REACH`] positions away,
+/// and nothing further or when the track is missing.
+fn near_position_points(
+    tracklist: &Tracklist,
+    title: &str,
+    target: usize,
+    max_points: Score,
+) -> Score {
+    let Some(distance) = position_of(tracklist, title).map(|pos| pos.abs_diff(target)) else {
+        return 0.0;
+    };
+
+    max_points * NEAR_POSITION_REACH.saturating_sub(distance) as Score
+        / NEAR_POSITION_REACH as Score
+}
+
+/// Checks that the track is at one of the given positions.
+/// A missing track never satisfies the constraint.
+fn at_any_position(tracklist: &Tracklist, title: &str, positions: &[usize]) -> bool {
+    position_of(tracklist, title).is_some_and(|pos| positions.contains(&pos))
+}
+
+/// Checks that `opener` is the first track and `closer` the last one.
+fn bookend(tracklist: &Tracklist, opener: &str, closer: &str) -> bool {
+    tracklist.0.first().is_some_and(|t| refers_to(opener, t))
+        && tracklist.0.last().is_some_and(|t| refers_to(closer, t))
+}
+
+/// Returns the position of each title in the named reference tracklist, or `None`
+/// (with a warning) if the reference is missing or doesn't hold the same tracks as `source`.
+fn reference_positions<'a>(
+    source: &SerTracklist,
+    tracklists: &'a [NamedSerTracklist],
+    name: &str,
+) -> Option<HashMap<&'a str, usize>> {
+    let Some(reference) = tracklists
+        .iter()
+        .find(|tl| tl.name.eq_ignore_ascii_case(name))
+    else {
+        warn!(
+            "Reference tracklist '{}' not found, StayCloseTo ignored",
+            name
+        );
+        return None;
+    };
+
+    let mut source_titles: Vec<&str> = source.0.iter().map(|t| t.title.as_str()).collect();
+    let mut reference_titles: Vec<&str> = reference
+        .tracks
+        .0
+        .iter()
+        .map(|t| t.title.as_str())
+        .collect();
+    source_titles.sort_unstable();
+    reference_titles.sort_unstable();
+    if source_titles != reference_titles {
+        warn!(
+            "Reference tracklist '{}' doesn't hold the same tracks, StayCloseTo ignored",
+            reference.name
+        );
+        return None;
+    }
+
+    Some(
+        reference
+            .tracks
+            .0
+            .iter()
+            .enumerate()
+            .map(|(i, t)| (t.title.as_str(), i))
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Two versions of "Song One": 3:00 with a 0:30 gap after it and energy 9, and
+    /// 4:00 with no gap and energy 2; plus "Other" (2:00, energy 9).
+    fn source() -> SerTracklist {
+        SerTracklist(vec![
+            SerTrack {
+                title: "Song One".to_string(),
+                duration: 3.0,
+                gap_after: Some(0.5),
+                energy: Some(9),
+                ..Default::default()
+            },
+            SerTrack {
+                title: "Song One".to_string(),
+                duration: 4.0,
+                energy: Some(2),
+                ..Default::default()
+            },
+            SerTrack {
+                title: "Other".to_string(),
+                duration: 2.0,
+                energy: Some(9),
+                ..Default::default()
+            },
+        ])
+    }
+
+    fn track(title: &str, duration: Duration) -> Track {
+        Track {
+            title: title.to_string(),
+            duration,
+        }
+    }
+
+    /// The stored tracks of `source` in the given order, by position.
+    fn order(source: &SerTracklist, positions: &[usize]) -> Tracklist {
+        Tracklist(positions.iter().map(|&i| (&source.0[i]).into()).collect())
+    }
+
+    fn medium(sides: usize, max_duration_per_side: Duration) -> AlbumMedium {
+        AlbumMedium {
+            sides,
+            max_duration_per_side,
+            name: "Test".to_string(),
+        }
+    }
+
+    fn constraint(kind: SerConstraintKind) -> SerConstraint {
+        SerConstraint {
+            kind,
+            weight: 1,
+            enabled: true,
+        }
+    }
+
+    #[test]
+    fn track_index_tells_same_titled_tracks_apart() {
+        let source = source();
+        let index = TrackIndex::of(&source);
+
+        assert_eq!(index.get(&track("Song One", 3.0)), Some(0));
+        assert_eq!(index.get(&track("Song One", 4.0)), Some(1));
+        assert_eq!(index.get(&track("Other", 2.0)), Some(2));
+        // No version with that duration: the first one with the title
+        assert_eq!(index.get(&track("Song One", 5.0)), Some(0));
+        assert_eq!(index.get(&track("Missing", 3.0)), None);
+    }
+
+    #[test]
+    fn gaps_follow_each_same_titled_track() {
+        let source = source();
+        let gaps = Gaps::of(&source);
+        let tracklist = order(&source, &[0, 1]);
+        let reversed = order(&source, &[1, 0]);
+
+        assert_eq!(gaps.after(&tracklist.0[0]), 0.5);
+        assert_eq!(gaps.after(&tracklist.0[1]), 0.0);
+        assert_eq!(
+            gaps.side_duration(&tracklist.0.iter().collect::<Vec<_>>()),
+            7.5
+        );
+        assert_eq!(
+            gaps.side_duration(&reversed.0.iter().collect::<Vec<_>>()),
+            7.0
+        );
+
+        // Only the 3:00 version's gap pushes the side over its length
+        let one_side = medium(1, 7.0);
+        assert!(!fits_medium(&tracklist, &one_side, &gaps));
+        assert!(fits_medium(&reversed, &one_side, &gaps));
+    }
+
+    #[test]
+    fn side_openers_use_their_own_energy() {
+        let source = source();
+        let constraints = [constraint(SerConstraintKind::StrongSideOpeners(5))];
+        let medium = medium(2, 6.0);
+        let scorer = Scorer::new(&constraints, &medium, &source, &[]);
+
+        // Other + Song One (4:00) | Song One (3:00): both openers have energy 9
+        assert_eq!(scorer.score(&order(&source, &[2, 1, 0])), 1.0);
+        // Other + Song One (3:00) | Song One (4:00): the second side opens with energy 2
+        assert_eq!(scorer.score(&order(&source, &[2, 0, 1])), 0.0);
+    }
+
+    #[test]
+    fn qualified_constraint_matches_only_that_version() {
+        let source = source();
+        let constraints = [constraint(SerConstraintKind::AtPosition(
+            "Song One@4:00".to_string(),
+            0,
+        ))];
+        let medium = medium(2, 10.0);
+        let scorer = Scorer::new(&constraints, &medium, &source, &[]);
+
+        assert_eq!(scorer.score(&order(&source, &[1, 0, 2])), 1.0);
+        assert_eq!(scorer.score(&order(&source, &[0, 1, 2])), 0.0);
+    }
+
+    #[test]
+    fn side_balance_compares_side_durations() {
+        let source = source();
+        let medium = medium(2, 6.0);
+        let score = |positions: &[usize], tolerance: Duration| {
+            let constraints = [constraint(SerConstraintKind::SideBalance(tolerance))];
+            Scorer::new(&constraints, &medium, &source, &[]).score(&order(&source, positions))
+        };
+
+        // Other + Song One (4:00) | Song One (3:00): 6:00 and 3:00
+        assert_eq!(score(&[2, 1, 0], 3.0), 1.0);
+        assert_eq!(score(&[2, 1, 0], 2.5), 0.0);
+        // Song One (3:00) + its gap + Other | Song One (4:00): 5:30 and 4:00
+        assert_eq!(score(&[0, 2, 1], 1.5), 1.0);
+        assert_eq!(score(&[0, 2, 1], 1.25), 0.0);
+    }
+
+    #[test]
+    fn arg_matches_checks_the_duration_qualifier() {
+        assert!(arg_matches("Song One", "Song One", 3.0));
+        assert!(arg_matches("Song One@3:00", "Song One", 3.0));
+        assert!(arg_matches("Song One@3:00", "Song One", 3.0 + 0.4 / 60.0));
+        assert!(!arg_matches("Song One@3:00", "Song One", 4.0));
+        assert!(!arg_matches("Song One@3:00", "Song Two", 3.0));
+    }
+
+    #[test]
+    fn side_sorted_by_duration_checks_one_side() {
+        let tracks = [
+            track("A", 5.0),
+            track("B", 4.0),
+            track("C", 4.0),
+            track("D", 2.0),
+            track("E", 6.0),
+        ];
+        let sides = vec![
+            vec![&tracks[0], &tracks[1], &tracks[2], &tracks[3]],
+            vec![&tracks[4]],
+        ];
+
+        // Longest to shortest, with a tie
+        assert!(side_sorted_by_duration(&sides, 0, false));
+        assert!(!side_sorted_by_duration(&sides, 0, true));
+
+        let unsorted = vec![vec![&tracks[1], &tracks[0], &tracks[3]]];
+        assert!(!side_sorted_by_duration(&unsorted, 0, false));
+        assert!(!side_sorted_by_duration(&unsorted, 0, true));
+
+        // A single-track side, and a side the tracklist doesn't reach, are sorted
+        for ascending in [true, false] {
+            assert!(side_sorted_by_duration(&sides, 1, ascending));
+            assert!(side_sorted_by_duration(&sides, 2, ascending));
+        }
+    }
+
+    #[test]
+    fn no_side_break_between_two_tracks() {
+        let tracks = [track("A", 4.0), track("B", 3.0), track("C", 5.0)];
+        let sides = vec![vec![&tracks[0], &tracks[1]], vec![&tracks[2]]];
+
+        assert!(no_side_break_between(&sides, "A", "B"));
+        assert!(no_side_break_between(&sides, "B", "A"));
+        assert!(!no_side_break_between(&sides, "A", "C"));
+        assert!(!no_side_break_between(&sides, "A", "Missing"));
+    }
+
+    fn ser_track(title: &str, duration: Duration) -> SerTrack {
+        SerTrack {
+            title: title.to_string(),
+            duration,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn spread_tag_tells_clustered_from_spread_tracks() {
+        let mut source = SerTracklist(vec![
+            ser_track("A", 3.0),
+            ser_track("B", 3.0),
+            ser_track("C", 3.0),
+            ser_track("D", 3.0),
+        ]);
+        source.0[0].tags = vec!["slow".to_string()];
+        source.0[1].tags = vec!["Slow".to_string()];
+        let medium = medium(1, 20.0);
+        let score = |min_gap: usize, positions: &[usize]| {
+            let constraints = [constraint(SerConstraintKind::SpreadTag(
+                "slow".to_string(),
+                min_gap,
+            ))];
+            Scorer::new(&constraints, &medium, &source, &[]).score(&order(&source, positions))
+        };
+
+        // Clustered
+        assert_eq!(score(1, &[0, 1, 2, 3]), 0.0);
+        // One track apart, then two
+        assert_eq!(score(1, &[0, 2, 1, 3]), 1.0);
+        assert_eq!(score(2, &[0, 2, 1, 3]), 0.0);
+        assert_eq!(score(2, &[0, 2, 3, 1]), 1.0);
+    }
+
+    #[test]
+    fn longest_not_side_closer_checks_every_side() {
+        let tracks = [
+            track("A", 5.0),
+            track("B", 3.0),
+            track("C", 4.0),
+            track("D", 6.0),
+            track("E", 6.0),
+        ];
+
+        // Each side closes with a shorter track; a single-track side passes
+        let satisfying = vec![vec![&tracks[0], &tracks[1]], vec![&tracks[3], &tracks[2]]];
+        assert!(longest_not_side_closer(&satisfying));
+        assert!(longest_not_side_closer(&[vec![&tracks[3]]]));
+
+        // The second side closes with its longest track
+        let violating = vec![vec![&tracks[0], &tracks[1]], vec![&tracks[2], &tracks[3]]];
+        assert!(!longest_not_side_closer(&violating));
+        // A closer tied for longest is still the longest
+        assert!(!longest_not_side_closer(&[vec![&tracks[3], &tracks[4]]]));
+    }
+
+    #[test]
+    fn short_to_inner_prefers_long_tracks_first() {
+        let tracks = [track("A", 6.0), track("B", 4.0), track("C", 3.0)];
+
+        // Long tracks on the outside of the side earn every point
+        let front_loaded = vec![vec![&tracks[0], &tracks[1], &tracks[2]]];
+        assert_eq!(short_to_inner_points(&front_loaded, 2.0), 2.0);
+
+        // Long tracks toward the inner groove earn none
+        let back_loaded = vec![vec![&tracks[2], &tracks[1], &tracks[0]]];
+        assert_eq!(short_to_inner_points(&back_loaded, 2.0), 0.0);
+
+        // One of the two pairs gets shorter
+        let mixed = vec![vec![&tracks[1], &tracks[0], &tracks[2]]];
+        assert_eq!(short_to_inner_points(&mixed, 2.0), 1.0);
+
+        // No pair of neighbours on any side
+        assert_eq!(short_to_inner_points(&[vec![&tracks[0]]], 2.0), 2.0);
+    }
+
+    #[test]
+    fn starts_before_counts_the_tracks_and_gaps_ahead() {
+        let source = source();
+        let medium = medium(1, 20.0);
+        let score = |title: &str, minutes: Duration, positions: &[usize]| {
+            let constraints = [constraint(SerConstraintKind::StartsBefore(
+                title.to_string(),
+                minutes,
+            ))];
+            Scorer::new(&constraints, &medium, &source, &[]).score(&order(&source, positions))
+        };
+
+        // Other starts at 4:00, then after 3:00 and a 0:30 gap, then at 7:30
+        assert_eq!(score("Other", 5.0, &[1, 2, 0]), 1.0);
+        assert_eq!(score("Other", 4.0, &[0, 2, 1]), 1.0);
+        assert_eq!(score("Other", 3.5, &[0, 2, 1]), 0.0);
+        assert_eq!(score("Other", 5.0, &[0, 1, 2]), 0.0);
+        assert_eq!(score("Missing", 5.0, &[0, 1, 2]), 0.0);
+    }
+
+    #[test]
+    fn stay_close_to_rewards_the_reference_order() {
+        let source = SerTracklist(vec![
+            ser_track("A", 3.0),
+            ser_track("B", 3.0),
+            ser_track("C", 3.0),
+            ser_track("D", 3.0),
+        ]);
+        let references = [NamedSerTracklist {
+            name: "Reference".to_string(),
+            tracks: source.clone(),
+            ..Default::default()
+        }];
+        let constraints = [SerConstraint {
+            kind: SerConstraintKind::StayCloseTo("reference".to_string()),
+            weight: 8,
+            enabled: true,
+        }];
+        let medium = medium(1, 20.0);
+        let scorer = Scorer::new(&constraints, &medium, &source, &references);
+        let score = |positions: &[usize]| scorer.score(&order(&source, positions));
+
+        assert_eq!(score(&[0, 1, 2, 3]), 8.0);
+        // Displacement 2 of at most 8
+        assert_eq!(score(&[1, 0, 2, 3]), 6.0);
+        // Reversed
+        assert_eq!(score(&[3, 2, 1, 0]), 0.0);
+
+        // A missing reference awards nothing
+        let scorer = Scorer::new(&constraints, &medium, &source, &[]);
+        assert_eq!(scorer.score(&order(&source, &[0, 1, 2, 3])), 0.0);
+    }
+
+    #[test]
+    fn strong_side_openers_need_enough_energy_on_every_side() {
+        let mut source = SerTracklist(vec![
+            ser_track("Loud", 4.0),
+            ser_track("Calm", 4.0),
+            ser_track("Unrated", 4.0),
+        ]);
+        source.0[0].energy = Some(8);
+        source.0[1].energy = Some(3);
+        let medium = medium(3, 4.0);
+        let score = |min_energy: u8, positions: &[usize]| {
+            let constraints = [constraint(SerConstraintKind::StrongSideOpeners(min_energy))];
+            Scorer::new(&constraints, &medium, &source, &[]).score(&order(&source, positions))
+        };
+
+        // One track per side, so every track opens a side
+        assert_eq!(score(1, &[0, 1, 2]), 0.0);
+
+        source.0[2].energy = Some(5);
+        let score = |min_energy: u8| {
+            let constraints = [constraint(SerConstraintKind::StrongSideOpeners(min_energy))];
+            Scorer::new(&constraints, &medium, &source, &[]).score(&order(&source, &[0, 1, 2]))
+        };
+        assert_eq!(score(3), 1.0);
+        assert_eq!(score(4), 0.0);
+    }
+
+    #[test]
+    fn fill_sides_rewards_well_filled_sides() {
+        let source = SerTracklist(vec![
+            ser_track("A", 5.0),
+            ser_track("B", 5.0),
+            ser_track("C", 3.0),
+            ser_track("D", 2.0),
+        ]);
+        let tracklist = order(&source, &[0, 1, 2, 3]);
+        let score = |sides: usize, target: f64| {
+            let constraints = [constraint(SerConstraintKind::FillSides(target))];
+            let medium = medium(sides, 10.0);
+            Scorer::new(&constraints, &medium, &source, &[]).score(&tracklist)
+        };
+
+        // A B (10:00) | C D (5:00)
+        assert_eq!(score(2, 1.0), 0.75);
+        // The same sides with two more left empty
+        assert_eq!(score(4, 1.0), 0.375);
+        // Both used sides reach half their length
+        assert_eq!(score(2, 0.5), 1.0);
+    }
+
+    #[test]
+    fn max_hard_cuts_counts_breaks_inside_continuous_pairs() {
+        let mut source = SerTracklist(vec![
+            ser_track("A", 4.0),
+            ser_track("B", 4.0),
+            ser_track("C", 4.0),
+            ser_track("D", 4.0),
+        ]);
+        source.0[0].continuous = true;
+        source.0[1].continuous = true;
+        source.0[2].continuous = true;
+        // One track per side, so every pair of neighbours is split by a side break
+        let medium = medium(4, 4.0);
+        let score = |max_cuts: usize, positions: &[usize]| {
+            let constraints = [constraint(SerConstraintKind::MaxHardCuts(max_cuts))];
+            Scorer::new(&constraints, &medium, &source, &[]).score(&order(&source, positions))
+        };
+
+        // A|B and B|C break a continuous pair
+        assert_eq!(score(1, &[0, 1, 2, 3]), 0.0);
+        assert_eq!(score(2, &[0, 1, 2, 3]), 1.0);
+        // Only C|B does
+        assert_eq!(score(0, &[0, 3, 2, 1]), 0.0);
+        assert_eq!(score(1, &[0, 3, 2, 1]), 1.0);
+    }
+
+    #[test]
+    fn a_gap_can_push_a_side_over_its_length() {
+        let mut source = SerTracklist(vec![ser_track("A", 4.0), ser_track("B", 4.0)]);
+        let medium = medium(1, 8.0);
+        let fits = |source: &SerTracklist| {
+            fits_medium(&order(source, &[0, 1]), &medium, &Gaps::of(source))
+        };
+
+        assert!(fits(&source));
+        // The gap after a side's last track doesn't take up room
+        source.0[1].gap_after = Some(0.5);
+        assert!(fits(&source));
+        source.0[0].gap_after = Some(0.5);
+        assert!(!fits(&source));
+    }
+
+    #[test]
+    fn at_any_position_matches_any_listed_position() {
+        let source = SerTracklist(vec![
+            ser_track("A", 4.0),
+            ser_track("B", 3.0),
+            ser_track("C", 5.0),
+            ser_track("D", 2.0),
+        ]);
+        let constraints = [constraint(SerConstraintKind::AtAnyPosition(
+            "C".to_string(),
+            vec![0, 3],
+        ))];
+        let medium = medium(2, 8.0);
+        let scorer = Scorer::new(&constraints, &medium, &source, &[]);
+        let score = |positions: &[usize]| scorer.score(&order(&source, positions));
+
+        assert_eq!(score(&[2, 1, 0, 3]), 1.0);
+        assert_eq!(score(&[0, 1, 3, 2]), 1.0);
+        assert_eq!(score(&[0, 2, 1, 3]), 0.0);
+        assert_eq!(score(&[0, 1, 2, 3]), 0.0);
+    }
+
+    #[test]
+    fn prefer_same_side_gives_part_credit_to_neighbouring_sides() {
+        let source = SerTracklist(vec![
+            ser_track("A", 4.0),
+            ser_track("B", 4.0),
+            ser_track("C", 4.0),
+            ser_track("D", 4.0),
+        ]);
+        let constraints = [constraint(SerConstraintKind::PreferSameSide(
+            "A".to_string(),
+            "B".to_string(),
+        ))];
+        // Two tracks per side
+        let lp = medium(2, 8.0);
+        let scorer = Scorer::new(&constraints, &lp, &source, &[]);
+        let score = |positions: &[usize]| scorer.score(&order(&source, positions));
+
+        assert_eq!(score(&[0, 1, 2, 3]), 1.0);
+        assert_eq!(score(&[0, 2, 1, 3]), NEIGHBOURING_SIDES_FRACTION);
+
+        // One track per side
+        let singles = medium(4, 4.0);
+        let scorer = Scorer::new(&constraints, &singles, &source, &[]);
+        let score = |positions: &[usize]| scorer.score(&order(&source, positions));
+        assert_eq!(score(&[2, 0, 1, 3]), NEIGHBOURING_SIDES_FRACTION);
+        assert_eq!(score(&[0, 2, 1, 3]), 0.0);
+        assert_eq!(score(&[0, 2, 3, 1]), 0.0);
+    }
+
+    #[test]
+    fn breakdown_sums_to_the_score() {
+        let source = SerTracklist(vec![
+            ser_track("A", 4.0),
+            ser_track("B", 3.0),
+            ser_track("C", 5.0),
+            ser_track("D", 2.0),
+        ]);
+        let mut disabled = constraint(SerConstraintKind::AtPosition("B".to_string(), 0));
+        disabled.enabled = false;
+        let constraints = [
+            constraint(SerConstraintKind::AtPosition("A".to_string(), 0)),
+            SerConstraint {
+                weight: 2,
+                ..constraint(SerConstraintKind::OnSameSide(
+                    "C".to_string(),
+                    "D".to_string(),
+                ))
+            },
+            constraint(SerConstraintKind::Adjacent(
+                "A".to_string(),
+                "B".to_string(),
+            )),
+            SerConstraint {
+                weight: 3,
+                ..constraint(SerConstraintKind::FillSides(1.0))
+            },
+            disabled,
+        ];
+        let medium = medium(2, 8.0);
+        let scorer = Scorer::new(&constraints, &medium, &source, &[]);
+
+        for positions in [
+            [0, 1, 2, 3],
+            [2, 1, 0, 3],
+            [2, 0, 1, 3],
+            [1, 0, 3, 2],
+            [3, 2, 1, 0],
+        ] {
+            let tracklist = order(&source, &positions);
+            let breakdown = scorer.breakdown(&tracklist);
+            assert_eq!(breakdown.len(), constraints.len());
+            assert_eq!(breakdown[4], None);
+
+            let sum: Score = breakdown.iter().flatten().sum();
+            assert!(
+                (sum - scorer.score(&tracklist)).abs() < 1e-9,
+                "{:?}",
+                positions
+            );
+        }
+    }
+
+    #[test]
+    fn a_side_exactly_at_its_limit_fits() {
+        let medium = medium(1, 8.0);
+        let fits = |durations: &[Duration]| {
+            let source = SerTracklist(
+                durations
+                    .iter()
+                    .enumerate()
+                    .map(|(i, &d)| ser_track(&i.to_string(), d))
+                    .collect(),
+            );
+            let positions: Vec<usize> = (0..durations.len()).collect();
+            fits_medium(&order(&source, &positions), &medium, &Gaps::of(&source))
+        };
+
+        assert!(fits(&[4.0, 4.0]));
+        // Sums of inexact durations may land a hair over 8.0
+        assert!(fits(&[10.0 / 3.0, 14.0 / 3.0]));
+        assert!(fits(&[0.1, 0.2, 7.7]));
+        // A second over
+        assert!(!fits(&[4.0, 4.0 + 1.0 / 60.0]));
+    }
+
+    #[test]
+    fn side_contains_exactly_or_at_least() {
+        let source = SerTracklist(vec![
+            ser_track("A", 4.0),
+            ser_track("B", 3.0),
+            ser_track("C", 5.0),
+            ser_track("D", 2.0),
+        ]);
+        let medium = medium(2, 8.0);
+        let score = |exact: bool, positions: &[usize]| {
+            let constraints = [constraint(SerConstraintKind::SideContains(
+                1,
+                vec!["D".to_string()],
+                exact,
+            ))];
+            Scorer::new(&constraints, &medium, &source, &[]).score(&order(&source, positions))
+        };
+
+        // A B | C D: the second side holds D and C
+        assert_eq!(score(false, &[0, 1, 2, 3]), 1.0);
+        assert_eq!(score(true, &[0, 1, 2, 3]), 0.0);
+        // C B | A D
+        assert_eq!(score(false, &[2, 1, 0, 3]), 1.0);
+        // A D | C B: D is on the first side
+        assert_eq!(score(false, &[0, 3, 2, 1]), 0.0);
+
+        // A B C D on one side of a longer medium: side 1 doesn't exist
+        let long = self::medium(2, 20.0);
+        let constraints = [constraint(SerConstraintKind::SideContains(
+            1,
+            vec!["D".to_string()],
+            false,
+        ))];
+        let scorer = Scorer::new(&constraints, &long, &source, &[]);
+        assert_eq!(scorer.score(&order(&source, &[0, 1, 2, 3])), 0.0);
+
+        let constraints = [constraint(SerConstraintKind::SideContains(
+            0,
+            vec!["C".to_string(), "B".to_string()],
+            true,
+        ))];
+        let scorer = Scorer::new(&constraints, &medium, &source, &[]);
+        assert_eq!(scorer.score(&order(&source, &[2, 1, 0, 3])), 1.0);
+        assert_eq!(scorer.score(&order(&source, &[1, 2, 3, 0])), 1.0);
+        assert_eq!(scorer.score(&order(&source, &[0, 1, 2, 3])), 0.0);
+    }
+
+    #[test]
+    fn side_near_full_needs_every_side_within_tolerance() {
+        let source = SerTracklist(vec![
+            ser_track("A", 4.0),
+            ser_track("B", 4.0),
+            ser_track("C", 5.0),
+            ser_track("D", 4.0),
+        ]);
+        // A B (80%) | C D (90%)
+        let tracklist = order(&source, &[0, 1, 2, 3]);
+        let score = |sides: usize, target: f64, tolerance: f64| {
+            let constraints = [constraint(SerConstraintKind::SideNearFull(
+                target, tolerance,
+            ))];
+            let medium = medium(sides, 10.0);
+            Scorer::new(&constraints, &medium, &source, &[]).score(&tracklist)
+        };
+
+        assert_eq!(score(2, 0.85, 0.1), 1.0);
+        assert_eq!(score(2, 0.85, 0.02), 0.0);
+        assert_eq!(score(2, 0.9, 0.02), 0.0);
+        assert_eq!(score(2, 0.7, 0.25), 1.0);
+        // An unused third side is empty
+        assert_eq!(score(3, 0.85, 0.1), 0.0);
+    }
+
+    #[test]
+    fn disc_balance_compares_pairs_of_sides() {
+        let source = SerTracklist(vec![
+            ser_track("A", 4.0),
+            ser_track("B", 3.0),
+            ser_track("C", 5.0),
+            ser_track("D", 2.0),
+        ]);
+        let medium = medium(4, 5.0);
+        let score = |tolerance: Duration, positions: &[usize]| {
+            let constraints = [constraint(SerConstraintKind::DiscBalance(tolerance))];
+            Scorer::new(&constraints, &medium, &source, &[]).score(&order(&source, positions))
+        };
+
+        // A | B || C | D: 7:00 on each disc
+        assert_eq!(score(1.0, &[0, 1, 2, 3]), 1.0);
+        // A | B D || C: 9:00 against 5:00
+        assert_eq!(score(1.0, &[0, 1, 3, 2]), 0.0);
+        assert_eq!(score(4.0, &[0, 1, 3, 2]), 1.0);
+    }
+
+    #[test]
+    fn max_artist_per_side_counts_artists_case_insensitively() {
+        let mut source = SerTracklist(vec![
+            ser_track("A", 4.0),
+            ser_track("B", 4.0),
+            ser_track("C", 4.0),
+            ser_track("D", 4.0),
+        ]);
+        source.0[0].artist = Some("Nina".to_string());
+        source.0[1].artist = Some("NINA".to_string());
+        source.0[2].artist = Some("Otis".to_string());
+        let medium = medium(2, 8.0);
+        let score = |max: usize, positions: &[usize]| {
+            let constraints = [constraint(SerConstraintKind::MaxArtistPerSide(max))];
+            Scorer::new(&constraints, &medium, &source, &[]).score(&order(&source, positions))
+        };
+
+        // A B | C D: both of Nina's tracks on the first side
+        assert_eq!(score(1, &[0, 1, 2, 3]), 0.0);
+        assert_eq!(score(2, &[0, 1, 2, 3]), 1.0);
+        // A C | B D
+        assert_eq!(score(1, &[0, 2, 1, 3]), 1.0);
+        // C D | A B: D has no artist
+        assert_eq!(score(1, &[2, 3, 0, 1]), 0.0);
+    }
+
+    #[test]
+    fn smooth_loudness_limits_the_jump_between_neighbours() {
+        let mut source = SerTracklist(vec![
+            ser_track("Quiet", 4.0),
+            ser_track("Medium", 4.0),
+            ser_track("Loud", 4.0),
+            ser_track("Unmeasured", 4.0),
+        ]);
+        source.0[0].loudness = Some(-14.0);
+        source.0[1].loudness = Some(-10.0);
+        source.0[2].loudness = Some(-6.0);
+        let medium = medium(2, 8.0);
+        let score = |max_jump: f64, positions: &[usize]| {
+            let constraints = [constraint(SerConstraintKind::SmoothLoudness(max_jump))];
+            Scorer::new(&constraints, &medium, &source, &[]).score(&order(&source, positions))
+        };
+
+        assert_eq!(score(4.0, &[0, 1, 2, 3]), 1.0);
+        // Quiet straight to Loud
+        assert_eq!(score(4.0, &[0, 2, 1, 3]), 0.0);
+        assert_eq!(score(8.0, &[0, 2, 1, 3]), 1.0);
+        // Pairs with the unmeasured track are skipped
+        assert_eq!(score(4.0, &[0, 3, 2, 1]), 1.0);
+    }
+
+    #[test]
+    fn first_break_after_measures_the_first_side() {
+        let mut source = SerTracklist(vec![
+            ser_track("A", 4.0),
+            ser_track("B", 3.0),
+            ser_track("C", 5.0),
+            ser_track("D", 2.0),
+        ]);
+        let medium = medium(2, 8.0);
+        let score = |source: &SerTracklist, minutes: Duration, positions: &[usize]| {
+            let constraints = [constraint(SerConstraintKind::FirstBreakAfter(minutes))];
+            Scorer::new(&constraints, &medium, source, &[]).score(&order(source, positions))
+        };
+
+        // C B | A D: the first side runs 8:00
+        assert_eq!(score(&source, 8.0, &[2, 1, 0, 3]), 1.0);
+        // A D | B C: 6:00
+        assert_eq!(score(&source, 7.0, &[0, 3, 1, 2]), 0.0);
+        assert_eq!(score(&source, 6.0, &[0, 3, 1, 2]), 1.0);
+
+        // The gap between A and D counts
+        source.0[0].gap_after = Some(1.0);
+        assert_eq!(score(&source, 7.0, &[0, 3, 1, 2]), 1.0);
+    }
+
+    #[test]
+    fn bookend_needs_both_opener_and_closer() {
+        let source = SerTracklist(vec![
+            ser_track("Intro", 1.0),
+            ser_track("A", 3.0),
+            ser_track("B", 3.0),
+            ser_track("Outro", 1.0),
+        ]);
+        let medium = medium(1, 20.0);
+        let constraints = [constraint(SerConstraintKind::Bookend(
+            "Intro".to_string(),
+            "Outro".to_string(),
+        ))];
+        let scorer = Scorer::new(&constraints, &medium, &source, &[]);
+
+        assert_eq!(scorer.score(&order(&source, &[0, 1, 2, 3])), 1.0);
+        // Only the opener or only the closer in place
+        assert_eq!(scorer.score(&order(&source, &[0, 3, 1, 2])), 0.0);
+        assert_eq!(scorer.score(&order(&source, &[1, 0, 2, 3])), 0.0);
+        // Swapped
+        assert_eq!(scorer.score(&order(&source, &[3, 1, 2, 0])), 0.0);
+    }
+
+    #[test]
+    fn near_position_decreases_with_distance() {
+        let source = SerTracklist(
+            ["X", "A", "B", "C", "D", "E"]
+                .iter()
+                .map(|title| ser_track(title, 2.0))
+                .collect(),
+        );
+        let medium = medium(1, 20.0);
+        let constraints = [constraint(SerConstraintKind::NearPosition(
+            "X".to_string(),
+            0,
+        ))];
+        let scorer = Scorer::new(&constraints, &medium, &source, &[]);
+        // X moved to the given position, the others in order
+        let score = |pos: usize| {
+            let mut positions: Vec<usize> = (1..6).collect();
+            positions.insert(pos, 0);
+            scorer.score(&order(&source, &positions))
+        };
+
+        assert_eq!(score(0), 1.0);
+        assert_eq!(score(1), 0.75);
+        assert_eq!(score(2), 0.5);
+        assert_eq!(score(3), 0.25);
+        // Floored at zero from NEAR_POSITION_REACH on
+        assert_eq!(score(4), 0.0);
+        assert_eq!(score(5), 0.0);
+    }
+
+    #[test]
+    fn alternate_pacing_needs_short_and_long_tracks_to_alternate() {
+        let source = SerTracklist(vec![
+            ser_track("Short 1", 2.0),
+            ser_track("Short 2", 3.0),
+            ser_track("Long 1", 6.0),
+            ser_track("Long 2", 4.0),
+        ]);
+        let medium = medium(1, 20.0);
+        let constraints = [constraint(SerConstraintKind::AlternatePacing(4.0))];
+        let scorer = Scorer::new(&constraints, &medium, &source, &[]);
+
+        assert_eq!(scorer.score(&order(&source, &[0, 2, 1, 3])), 1.0);
+        assert_eq!(scorer.score(&order(&source, &[2, 0, 3, 1])), 1.0);
+        // Clustered by length
+        assert_eq!(scorer.score(&order(&source, &[0, 1, 2, 3])), 0.0);
+        // One pair of long tracks in a row
+        assert_eq!(scorer.score(&order(&source, &[0, 2, 3, 1])), 0.0);
+    }
+}
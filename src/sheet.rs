@@ -0,0 +1,155 @@
+//! # Tracklist Sheet
+//!
+//! This module renders a tracklist split into sides as a plain-text sheet for
+//! printing or liner notes: the album title, the sides laid out two by two with
+//! per-track and per-side durations, and the total runtime.
+//!
+//! ## Example
+//! ```rust
+//! let sides = split_tracklist_by_side(&tracklist, &medium, &gaps);
+//! let sheet = render_sheet(&ser_tl, &sides, &gaps, SideLabels::Letters);
+//! print!("{}", sheet);
+//! ```
+
+use crate::context::NamedSerTracklist;
+use crate::scoring::Gaps;
+use crate::utils::{SideLabels, format_duration};
+use albumseq::{Duration, Track};
+
+/// Number of sides laid out next to each other.
+const SIDES_PER_ROW: usize = 2;
+
+/// Spaces between two sides laid out next to each other.
+const COLUMN_SPACING: usize = 6;
+
+/// Renders the sheet of a tracklist split into sides.
+/// Tracks are numbered across sides; side durations include the gaps between tracks.
+pub fn render_sheet(
+    tracklist: &NamedSerTracklist,
+    sides: &[Vec<&Track>],
+    gaps: &Gaps,
+    side_labels: SideLabels,
+) -> String {
+    let mut lines = vec![tracklist.name.to_uppercase()];
+    if let Some(catalog) = &tracklist.catalog {
+        lines.push(format!("Catalog: {}", catalog));
+    }
+    let rule_width = lines.iter().map(|l| l.chars().count()).max().unwrap_or(0);
+    lines.push("=".repeat(rule_width));
+    lines.push(String::new());
+
+    let mut number = 1;
+    let blocks: Vec<Vec<String>> = sides
+        .iter()
+        .enumerate()
+        .map(|(idx, side)| {
+            let block = side_block(side, &side_labels.label(idx), number, gaps);
+            number += side.len();
+            block
+        })
+        .collect();
+    let width = blocks
+        .iter()
+        .flatten()
+        .map(|l| l.chars().count())
+        .max()
+        .unwrap_or(0);
+
+    for row in blocks.chunks(SIDES_PER_ROW) {
+        let height = row.iter().map(|b| b.len()).max().unwrap_or(0);
+        for i in 0..height {
+            let cells: Vec<String> = row
+                .iter()
+                .map(|b| {
+                    let cell = b.get(i).map(String::as_str).unwrap_or_default();
+                    format!("{:<w$}", cell, w = width)
+                })
+                .collect();
+            lines.push(
+                cells
+                    .join(&" ".repeat(COLUMN_SPACING))
+                    .trim_end()
+                    .to_string(),
+            );
+        }
+        lines.push(String::new());
+    }
+
+    let total: Duration = sides.iter().map(|side| gaps.side_duration(side)).sum();
+    let tracks: usize = sides.iter().map(|side| side.len()).sum();
+    lines.push(format!(
+        "Total runtime: {} ({} tracks)",
+        format_duration(total),
+        tracks
+    ));
+
+    lines.join("\n") + "\n"
+}
+
+/// Returns the lines of one side: a heading with its duration, then one line per
+/// track, numbered from `first_number`.
+fn side_block(side: &[&Track], label: &str, first_number: usize, gaps: &Gaps) -> Vec<String> {
+    let title_width = side
+        .iter()
+        .map(|t| t.title.chars().count())
+        .max()
+        .unwrap_or(0);
+
+    let mut block = vec![format!(
+        "Side {} ({})",
+        label,
+        format_duration(gaps.side_duration(side))
+    )];
+    for (i, track) in side.iter().enumerate() {
+        block.push(format!(
+            "{:>2}. {:<w$}  {}",
+            first_number + i,
+            track.title,
+            format_duration(track.duration),
+            w = title_width
+        ));
+    }
+    block
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::{SerTrack, SerTracklist};
+
+    #[test]
+    fn sheet_shows_side_and_total_durations() {
+        let mut tracks = SerTracklist(
+            [("A", 4.0), ("B", 3.0), ("C", 5.0), ("D", 2.0)]
+                .iter()
+                .map(|&(title, duration)| SerTrack {
+                    title: title.to_string(),
+                    duration,
+                    ..Default::default()
+                })
+                .collect(),
+        );
+        tracks.0[0].gap_after = Some(0.5);
+        let tracklist = NamedSerTracklist {
+            name: "Album".to_string(),
+            tracks,
+            ..Default::default()
+        };
+        let all: Vec<Track> = tracklist.tracks.0.iter().map(Track::from).collect();
+        let sides = vec![vec![&all[0], &all[1]], vec![&all[2], &all[3]]];
+        let gaps = Gaps::of(&tracklist.tracks);
+
+        let sheet = render_sheet(&tracklist, &sides, &gaps, SideLabels::Letters);
+        let expected = [
+            "ALBUM",
+            "=====",
+            "",
+            "Side A (07:30)      Side B (07:00)",
+            " 1. A  04:00         3. C  05:00",
+            " 2. B  03:00         4. D  02:00",
+            "",
+            "Total runtime: 14:30 (4 tracks)",
+        ];
+        assert_eq!(sheet, expected.join("\n") + "\n");
+    }
+}
@@ -9,9 +9,63 @@
 //! let s = format_duration(dur);
 //! ```
 
-use albumseq::Duration;
+use albumseq::{Duration, Track};
+use clap::ValueEnum;
+use std::sync::OnceLock;
+
+/// How [`format_duration`] turns fractional seconds into whole seconds.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Rounding {
+    /// Round to the nearest second.
+    #[default]
+    Nearest,
+    /// Round down, so a displayed total never exceeds the real one.
+    Floor,
+}
+
+/// The rounding used by [`format_duration`], set once at startup.
+static DURATION_ROUNDING: OnceLock<Rounding> = OnceLock::new();
+
+/// Tolerance (in seconds) for floating-point error when flooring, so that e.g.
+/// 4:10 parsed as decimal minutes isn't shown as 4:09.
+const FLOOR_TOLERANCE_SECONDS: f64 = 1e-6;
+
+/// Sets the rounding used by [`format_duration`] for the rest of the run.
+/// Only the first call has an effect.
+pub fn set_duration_rounding(rounding: Rounding) {
+    let _ = DURATION_ROUNDING.set(rounding);
+}
+
+/// Whether informational messages are suppressed, set once at startup.
+static QUIET: OnceLock<bool> = OnceLock::new();
+
+/// Suppresses informational messages (see [`status!`]) for the rest of the run.
+/// Only the first call has an effect.
+pub fn set_quiet(quiet: bool) {
+    let _ = QUIET.set(quiet);
+}
+
+/// Returns true if informational messages are suppressed.
+pub fn is_quiet() -> bool {
+    QUIET.get().copied().unwrap_or_default()
+}
+
+/// Prints an informational message, such as "Added tracklist", to stdout unless
+/// `--quiet` is set. Command output (tables, proposals) and errors use `println!`
+/// and `eprintln!` directly.
+macro_rules! status {
+    ($($arg:tt)*) => {
+        if !$crate::utils::is_quiet() {
+            println!($($arg)*);
+        }
+    };
+}
+pub(crate) use status;
 
 /// Formats a duration in minutes (f64) as "MM:SS".
+/// Seconds are rounded as set by [`set_duration_rounding`] (to the nearest by default).
+/// To display a sum, add the raw durations and format the total once, so the
+/// rounding of each part doesn't accumulate.
 ///
 /// # Arguments
 /// * `duration` - The duration in minutes.
@@ -19,24 +73,327 @@ use albumseq::Duration;
 /// # Returns
 /// A string in "MM:SS" format.
 pub fn format_duration(duration: Duration) -> String {
-    let total_seconds = (duration * 60.0).round() as u64;
+    let rounding = DURATION_ROUNDING.get().copied().unwrap_or_default();
+    format_duration_with(duration, rounding)
+}
+
+/// Formats a duration in minutes (f64) as "MM:SS" with the given rounding.
+///
+/// # Arguments
+/// * `duration` - The duration in minutes.
+/// * `rounding` - How fractional seconds are rounded.
+///
+/// # Returns
+/// A string in "MM:SS" format.
+pub fn format_duration_with(duration: Duration, rounding: Rounding) -> String {
+    let seconds = duration * 60.0;
+    let total_seconds = match rounding {
+        Rounding::Nearest => seconds.round(),
+        Rounding::Floor => (seconds + FLOOR_TOLERANCE_SECONDS).floor(),
+    } as u64;
     let minutes = total_seconds / 60;
     let seconds = total_seconds % 60;
     format!("{:02}:{:02}", minutes, seconds)
 }
 
-/// Parses a duration from "MM:SS" or decimal minutes.
+/// Formats a score for display: whole scores without decimals, others rounded to
+/// two decimals, e.g. "12" or "7.33".
+pub fn format_score(score: f64) -> String {
+    let rounded = format!("{:.2}", score);
+    rounded
+        .trim_end_matches('0')
+        .trim_end_matches('.')
+        .to_string()
+}
+
+/// Parses a duration from "MM:SS", seconds with an `s` suffix ("225s"), or decimal
+/// minutes with an optional `m` suffix ("3.75m"). A bare number is minutes.
 ///
 /// # Arguments
 /// * `s` - The input string.
 ///
 /// # Returns
-/// `Some(f64)` if parsing succeeds, or `None` if the input is invalid.
+/// `Some(f64)` minutes if parsing succeeds, or `None` if the input is invalid.
 pub fn parse_duration(s: &str) -> Option<f64> {
     if let Some((min_str, sec_str)) = s.split_once(':') {
         if let (Ok(min), Ok(sec)) = (min_str.parse::<u32>(), sec_str.parse::<u32>()) {
             return Some(min as f64 + sec as f64 / 60.0);
         }
     }
-    s.parse::<f64>().ok()
+    if let Some(seconds) = s.strip_suffix('s') {
+        return seconds.parse::<f64>().ok().map(|sec| sec / 60.0);
+    }
+    s.strip_suffix('m').unwrap_or(s).parse::<f64>().ok()
+}
+
+/// Splits a constraint's title argument into the title and the optional duration
+/// qualifier after its last `@`, e.g. "Song One@3:45" for the 3:45 version of a title
+/// shared by several tracks. Only an `MM:SS` suffix is a qualifier, so titles such as
+/// "Me@Home" are kept whole.
+pub fn split_duration_qualifier(arg: &str) -> (&str, Option<f64>) {
+    if let Some((title, qualifier)) = arg.rsplit_once('@')
+        && qualifier.contains(':')
+        && let Some(duration) = parse_duration(qualifier)
+    {
+        return (title, Some(duration));
+    }
+    (arg, None)
+}
+
+/// Parses a signed duration: "MM:SS" or decimal minutes with an optional leading `+` or `-`.
+///
+/// # Arguments
+/// * `s` - The input string, e.g. "-0:05" or "+0.5".
+///
+/// # Returns
+/// `Some(f64)` (negative for a leading `-`) if parsing succeeds, or `None` if the input is invalid.
+pub fn parse_signed_duration(s: &str) -> Option<f64> {
+    if let Some(rest) = s.strip_prefix('-') {
+        parse_duration(rest).map(|d| -d)
+    } else {
+        parse_duration(s.strip_prefix('+').unwrap_or(s))
+    }
+}
+
+/// Parses a track entry of the form "Title:Duration", where the duration is
+/// "MM:SS" or decimal minutes (e.g. "Song1:3:45" or "Song1:3.75").
+///
+/// # Returns
+/// The track, or the reason the entry was rejected.
+pub fn parse_track(s: &str) -> Result<Track, &'static str> {
+    let Some((title, duration)) = s.split_once(':') else {
+        return Err("missing colon");
+    };
+    if title.trim().is_empty() {
+        return Err("missing title");
+    }
+    let duration = parse_duration(duration).ok_or("bad duration")?;
+
+    Ok(Track {
+        title: title.to_string(),
+        duration,
+    })
+}
+
+/// Returns the Levenshtein edit distance between two strings, compared case-insensitively.
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.to_lowercase().chars().collect();
+    let b: Vec<char> = b.to_lowercase().chars().collect();
+
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.iter().enumerate() {
+        let mut row = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = prev[j] + usize::from(ca != cb);
+            row[j + 1] = substitution.min(prev[j + 1] + 1).min(row[j] + 1);
+        }
+        prev = row;
+    }
+
+    prev[b.len()]
+}
+
+/// Returns the candidate closest to `name`, if any is within a third of its length in edits.
+pub fn closest_name<'a>(name: &str, candidates: impl Iterator<Item = &'a str>) -> Option<&'a str> {
+    let max_distance = (name.chars().count() / 3).max(1);
+    candidates
+        .map(|c| (edit_distance(name, c), c))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, c)| c)
+}
+
+/// How proposals are rendered.
+#[derive(Clone, Copy, Debug, Default, ValueEnum)]
+pub enum OutputFormat {
+    /// Colored tables for the terminal.
+    #[default]
+    Table,
+    /// GitHub-flavored Markdown, for pasting into blogs and wikis.
+    Markdown,
+    /// One compact JSON object per line, for piping to other tools.
+    Ndjson,
+}
+
+/// What proposals are ordered by.
+#[derive(Clone, Copy, Debug, Default, ValueEnum)]
+pub enum SortBy {
+    /// Highest constraint score first.
+    #[default]
+    Score,
+    /// Smallest difference between the longest and shortest side first.
+    Balance,
+}
+
+/// How proposals are grouped in the output.
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum GroupBy {
+    /// By the set of tracks on the first side, the album's opening.
+    Side1,
+}
+
+/// How sides are labeled in proposal output.
+#[derive(Clone, Copy, Debug, Default, ValueEnum)]
+pub enum SideLabels {
+    /// "1", "2", "3", ...
+    #[default]
+    Numbers,
+    /// "A", "B", ..., "Z", "AA", "AB", ... (vinyl convention).
+    Letters,
+}
+
+impl SideLabels {
+    /// Returns the label of a side given its 0-based index.
+    pub fn label(self, side: usize) -> String {
+        match self {
+            SideLabels::Numbers => (side + 1).to_string(),
+            SideLabels::Letters => side_letters(side),
+        }
+    }
+}
+
+/// How track titles are normalized by `normalize-titles`. Every mode trims the
+/// title and collapses runs of whitespace into one space.
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum TitleNormalization {
+    /// Only fix the whitespace: "  Song   One " becomes "Song One".
+    Trim,
+    /// Capitalize the first letter of each word and lowercase the rest.
+    Titlecase,
+    /// Lowercase the whole title.
+    Lowercase,
+}
+
+impl TitleNormalization {
+    /// Returns the normalized form of a title.
+    pub fn apply(self, title: &str) -> String {
+        let words = title.split_whitespace();
+        match self {
+            TitleNormalization::Trim => words.collect::<Vec<_>>().join(" "),
+            TitleNormalization::Titlecase => words
+                .map(|word| {
+                    let mut chars = word.chars();
+                    chars.next().map_or_else(String::new, |first| {
+                        first
+                            .to_uppercase()
+                            .chain(chars.flat_map(char::to_lowercase))
+                            .collect()
+                    })
+                })
+                .collect::<Vec<_>>()
+                .join(" "),
+            TitleNormalization::Lowercase => {
+                words.map(str::to_lowercase).collect::<Vec<_>>().join(" ")
+            }
+        }
+    }
+}
+
+/// How `sort` orders the tracks of a tracklist.
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum TrackOrder {
+    /// Alphabetically by title (case-insensitive).
+    Title,
+    /// Shortest track first.
+    DurationAsc,
+    /// Longest track first.
+    DurationDesc,
+}
+
+/// Converts a 0-based side index to letters: 0 is "A", 25 is "Z", 26 is "AA", 27 is "AB".
+pub fn side_letters(side: usize) -> String {
+    let mut letters = Vec::new();
+    let mut n = side + 1;
+    while n > 0 {
+        n -= 1;
+        letters.push(b'A' + (n % 26) as u8);
+        n /= 26;
+    }
+    letters.reverse();
+    String::from_utf8(letters).expect("side letters are ASCII")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn side_labels_count_in_numbers_or_letters() {
+        assert_eq!(SideLabels::Numbers.label(0), "1");
+        assert_eq!(SideLabels::Numbers.label(27), "28");
+
+        assert_eq!(SideLabels::Letters.label(0), "A");
+        assert_eq!(SideLabels::Letters.label(1), "B");
+        assert_eq!(SideLabels::Letters.label(25), "Z");
+        assert_eq!(SideLabels::Letters.label(26), "AA");
+        assert_eq!(SideLabels::Letters.label(27), "AB");
+        assert_eq!(SideLabels::Letters.label(51), "AZ");
+        assert_eq!(SideLabels::Letters.label(52), "BA");
+        assert_eq!(SideLabels::Letters.label(701), "ZZ");
+        assert_eq!(SideLabels::Letters.label(702), "AAA");
+    }
+
+    #[test]
+    fn rounding_applies_to_the_total_not_each_part() {
+        // Three parts of 20.6 seconds, 61.8 seconds in all
+        let part = 20.6 / 60.0;
+        let total = part * 3.0;
+
+        // Rounding each part first adds a second per part
+        let rounded_parts: u64 = (0..3)
+            .map(|_| {
+                let (minutes, seconds) = format_duration_with(part, Rounding::Nearest)
+                    .split_once(':')
+                    .map(|(m, s)| (m.parse::<u64>().unwrap(), s.parse::<u64>().unwrap()))
+                    .unwrap();
+                minutes * 60 + seconds
+            })
+            .sum();
+        assert_eq!(rounded_parts, 63);
+        assert_eq!(format_duration_with(total, Rounding::Nearest), "01:02");
+
+        assert_eq!(format_duration_with(part, Rounding::Floor), "00:20");
+        assert_eq!(format_duration_with(total, Rounding::Floor), "01:01");
+        // Whole seconds stored inexactly aren't floored a second short
+        assert_eq!(format_duration_with(0.3 - 0.1, Rounding::Floor), "00:12");
+    }
+
+    #[test]
+    fn scores_are_shown_with_at_most_two_decimals() {
+        assert_eq!(format_score(12.0), "12");
+        assert_eq!(format_score(7.0 + 1.0 / 3.0), "7.33");
+        assert_eq!(format_score(1.5), "1.5");
+        assert_eq!(format_score(0.0), "0");
+        assert_eq!(format_score(2.999), "3");
+    }
+
+    #[test]
+    fn durations_parse_as_seconds_minutes_or_mm_ss() {
+        assert_eq!(parse_duration("225s"), Some(3.75));
+        assert_eq!(parse_duration("3.75m"), Some(3.75));
+        assert_eq!(parse_duration("3.75"), Some(3.75));
+        assert_eq!(parse_duration("3:45"), Some(3.75));
+        assert_eq!(parse_duration("3"), Some(3.0));
+
+        assert_eq!(parse_duration("3:4x"), None);
+        assert_eq!(parse_duration("m"), None);
+        assert_eq!(parse_duration("abc"), None);
+
+        let track = parse_track("Song:225s").unwrap();
+        assert_eq!((track.title.as_str(), track.duration), ("Song", 3.75));
+    }
+
+    #[test]
+    fn title_normalization_modes() {
+        let title = "  the   LONG way\tHOME ";
+        assert_eq!(TitleNormalization::Trim.apply(title), "the LONG way HOME");
+        assert_eq!(
+            TitleNormalization::Titlecase.apply(title),
+            "The Long Way Home"
+        );
+        assert_eq!(
+            TitleNormalization::Lowercase.apply(title),
+            "the long way home"
+        );
+    }
 }
@@ -0,0 +1,373 @@
+//! # Constraint Wizard
+//!
+//! This module implements the interactive prompts of `add-constraint-wizard`. It asks
+//! for a constraint kind, each of its arguments (offering the titles of a tracklist
+//! where a track title is expected), and a weight, and returns them in the same form
+//! as the `add-constraint` arguments so they go through the same parsing.
+//!
+//! The prompts read from any [`BufRead`] and write to any [`Write`], so they can be
+//! driven by a script as well as by a terminal.
+//!
+//! ## Example
+//! ```rust
+//! let stdin = io::stdin();
+//! let answers = prompt_constraint(&titles, &mut stdin.lock(), &mut io::stdout())?;
+//! handle_add_constraint(&mut ctx, &answers.kind, &answers.args, answers.weight)?;
+//! ```
+
+use crate::error::{CliError, CliResult};
+use std::io::{BufRead, Write};
+
+/// What a constraint argument holds, which decides how it is prompted for.
+#[derive(Clone, Copy)]
+enum ArgKind {
+    /// A track title, chosen from the tracklist by number or typed in.
+    Title,
+    /// One or more track titles, chosen like [`ArgKind::Title`] until an empty answer.
+    Titles,
+    /// Any other value, described by the prompt text.
+    Value(&'static str),
+}
+
+/// The constraint kinds offered by the wizard, with a description and their arguments.
+const KINDS: &[(&str, &str, &[ArgKind])] = &[
+    (
+        "atpos",
+        "track at a given position",
+        &[ArgKind::Title, ArgKind::Value("position (0-based)")],
+    ),
+    (
+        "nearpos",
+        "track near a given position",
+        &[ArgKind::Title, ArgKind::Value("position (0-based)")],
+    ),
+    (
+        "atany",
+        "track at any of several positions",
+        &[
+            ArgKind::Title,
+            ArgKind::Value("positions (0-based, comma-separated)"),
+        ],
+    ),
+    (
+        "adjacent",
+        "two tracks next to each other",
+        &[ArgKind::Title, ArgKind::Title],
+    ),
+    (
+        "bookend",
+        "an opener first and a closer last",
+        &[ArgKind::Title, ArgKind::Title],
+    ),
+    (
+        "onsameside",
+        "two tracks on the same side",
+        &[ArgKind::Title, ArgKind::Title],
+    ),
+    (
+        "prefersameside",
+        "two tracks on the same or a neighbouring side",
+        &[ArgKind::Title, ArgKind::Title],
+    ),
+    (
+        "sidecontains",
+        "a side holding a set of tracks",
+        &[
+            ArgKind::Value("side (0-based)"),
+            ArgKind::Value("mode (exact or atleast)"),
+            ArgKind::Titles,
+        ],
+    ),
+    (
+        "sidesorted",
+        "a side sorted by duration",
+        &[
+            ArgKind::Value("side (0-based)"),
+            ArgKind::Value("order (asc or desc)"),
+        ],
+    ),
+    (
+        "nosidebreak",
+        "no side break between two tracks",
+        &[ArgKind::Title, ArgKind::Title],
+    ),
+    (
+        "spreadtag",
+        "tracks with a tag kept apart",
+        &[ArgKind::Value("tag"), ArgKind::Value("minimum gap")],
+    ),
+    (
+        "longestnotcloser",
+        "no side ends with its longest track",
+        &[],
+    ),
+    (
+        "shorttoinner",
+        "shorter tracks toward the inner groove",
+        &[ArgKind::Value("weight scale")],
+    ),
+    (
+        "startsbefore",
+        "track starts before a playback time",
+        &[ArgKind::Title, ArgKind::Value("time (MM:SS)")],
+    ),
+    (
+        "stayclose",
+        "order close to a reference tracklist",
+        &[ArgKind::Value("reference tracklist")],
+    ),
+    (
+        "strongopeners",
+        "sides open with high-energy tracks",
+        &[ArgKind::Value("minimum energy")],
+    ),
+    (
+        "fillsides",
+        "sides filled to a fraction of their length",
+        &[ArgKind::Value("target fraction (e.g. 0.8)")],
+    ),
+    (
+        "sidenearfull",
+        "sides filled close to a fraction of their length",
+        &[
+            ArgKind::Value("target fraction (e.g. 0.9)"),
+            ArgKind::Value("tolerance (e.g. 0.05)"),
+        ],
+    ),
+    (
+        "sidebalance",
+        "sides of similar durations",
+        &[ArgKind::Value("tolerance (MM:SS)")],
+    ),
+    (
+        "discbalance",
+        "discs of similar runtimes",
+        &[ArgKind::Value("tolerance (MM:SS)")],
+    ),
+    (
+        "maxartistperside",
+        "few tracks by one artist per side",
+        &[ArgKind::Value("maximum tracks per artist and side")],
+    ),
+    (
+        "smoothloudness",
+        "no loudness jumps between neighbouring tracks",
+        &[ArgKind::Value("maximum jump (LU)")],
+    ),
+    (
+        "firstbreakafter",
+        "a first side that doesn't break too early",
+        &[ArgKind::Value("minimum first side duration (MM:SS)")],
+    ),
+    (
+        "alternatepacing",
+        "short and long tracks in turn",
+        &[ArgKind::Value("threshold between short and long (MM:SS)")],
+    ),
+    (
+        "maxhardcuts",
+        "few side breaks inside continuous passages",
+        &[ArgKind::Value("maximum hard cuts")],
+    ),
+];
+
+/// The answers collected by the wizard, in `add-constraint` form.
+#[derive(Debug)]
+pub struct WizardAnswers {
+    pub kind: String,
+    pub args: Vec<String>,
+    pub weight: usize,
+}
+
+/// Prompts for a constraint kind, its arguments and a weight.
+///
+/// `titles` are offered as numbered choices wherever a track title is expected;
+/// the user can answer with a number or type a title. An empty weight defaults to 1.
+/// Invalid choices are asked again; running out of input is a user error.
+pub fn prompt_constraint<R: BufRead, W: Write>(
+    titles: &[String],
+    input: &mut R,
+    output: &mut W,
+) -> CliResult<WizardAnswers> {
+    for (i, (kind, description, _)) in KINDS.iter().enumerate() {
+        say(
+            output,
+            &format!("  {}) {:<17} {}", i + 1, kind, description),
+        )?;
+    }
+    let (kind, _, arg_kinds) = loop {
+        let answer = ask(input, output, "Constraint kind (number or name): ")?;
+        match pick(&answer, KINDS.len(), |i| KINDS[i].0) {
+            Some(i) => break KINDS[i],
+            None => say(output, &format!("Unknown constraint kind: {}", answer))?,
+        }
+    };
+
+    let mut args = Vec::new();
+    for arg_kind in arg_kinds {
+        match arg_kind {
+            ArgKind::Title => {
+                args.push(prompt_title(titles, input, output, false)?.unwrap_or_default())
+            }
+            ArgKind::Titles => {
+                let first = args.len();
+                while let Some(title) = prompt_title(titles, input, output, args.len() > first)? {
+                    args.push(title);
+                }
+            }
+            ArgKind::Value(prompt) => {
+                args.push(ask(input, output, &format!("{}: ", capitalize(prompt)))?)
+            }
+        }
+    }
+
+    let weight = loop {
+        let answer = ask(input, output, "Weight [1]: ")?;
+        if answer.is_empty() {
+            break 1;
+        }
+        match answer.parse::<usize>() {
+            Ok(weight) => break weight,
+            Err(_) => say(output, &format!("Invalid weight: {}", answer))?,
+        }
+    };
+
+    Ok(WizardAnswers {
+        kind: kind.to_string(),
+        args,
+        weight,
+    })
+}
+
+/// Prompts for a track title, offering the known titles as numbered choices.
+/// With `optional`, an empty answer returns `None`; otherwise it is asked again.
+fn prompt_title<R: BufRead, W: Write>(
+    titles: &[String],
+    input: &mut R,
+    output: &mut W,
+    optional: bool,
+) -> CliResult<Option<String>> {
+    for (i, title) in titles.iter().enumerate() {
+        say(output, &format!("  {}) {}", i + 1, title))?;
+    }
+
+    let prompt = if optional {
+        "Track (number or title, empty to finish): "
+    } else {
+        "Track (number or title): "
+    };
+    loop {
+        let answer = ask(input, output, prompt)?;
+        if answer.is_empty() {
+            if optional {
+                return Ok(None);
+            }
+            continue;
+        }
+        if let Some(i) = pick(&answer, titles.len(), |i| titles[i].as_str()) {
+            return Ok(Some(titles[i].clone()));
+        }
+        if answer.parse::<usize>().is_err() {
+            return Ok(Some(answer));
+        }
+        say(output, &format!("No track number {}", answer))?;
+    }
+}
+
+/// Resolves an answer to a choice index, either as a 1-based number or as a
+/// name (case-insensitive).
+fn pick<'a>(answer: &str, len: usize, name: impl Fn(usize) -> &'a str) -> Option<usize> {
+    match answer.parse::<usize>() {
+        Ok(n) => (1..=len).contains(&n).then(|| n - 1),
+        Err(_) => (0..len).find(|&i| name(i).eq_ignore_ascii_case(answer)),
+    }
+}
+
+/// Returns the text with its first letter upper-cased.
+fn capitalize(text: &str) -> String {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+/// Writes a prompt and reads one trimmed line of input.
+fn ask<R: BufRead, W: Write>(input: &mut R, output: &mut W, prompt: &str) -> CliResult<String> {
+    write!(output, "{}", prompt)
+        .and_then(|_| output.flush())
+        .map_err(|e| CliError::Io(format!("Failed to write prompt: {}", e)))?;
+
+    let mut line = String::new();
+    let read = input
+        .read_line(&mut line)
+        .map_err(|e| CliError::Io(format!("Failed to read input: {}", e)))?;
+    if read == 0 {
+        return Err(CliError::User(
+            "Input ended before the constraint was complete".to_string(),
+        ));
+    }
+
+    Ok(line.trim().to_string())
+}
+
+/// Writes one line of output.
+fn say<W: Write>(output: &mut W, text: &str) -> CliResult {
+    writeln!(output, "{}", text).map_err(|e| CliError::Io(format!("Failed to write output: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn titles() -> Vec<String> {
+        ["Intro", "Song", "Outro"]
+            .iter()
+            .map(|t| t.to_string())
+            .collect()
+    }
+
+    /// Runs the wizard on scripted input, returning its answers and what it wrote.
+    fn run(script: &str) -> (CliResult<WizardAnswers>, String) {
+        let mut output = Vec::new();
+        let answers = prompt_constraint(&titles(), &mut script.as_bytes(), &mut output);
+        (answers, String::from_utf8(output).unwrap())
+    }
+
+    #[test]
+    fn answers_by_number_with_the_default_weight() {
+        let (answers, _) = run("1\n2\n0\n\n");
+        let answers = answers.unwrap();
+        assert_eq!(answers.kind, "atpos");
+        assert_eq!(answers.args, ["Song", "0"]);
+        assert_eq!(answers.weight, 1);
+    }
+
+    #[test]
+    fn invalid_answers_are_asked_again() {
+        let (answers, output) = run("nope\nAdjacent\n9\nintro\nCustom Title\nx\n3\n");
+        let answers = answers.unwrap();
+        assert_eq!(answers.kind, "adjacent");
+        assert_eq!(answers.args, ["Intro", "Custom Title"]);
+        assert_eq!(answers.weight, 3);
+
+        assert!(output.contains("Unknown constraint kind: nope"));
+        assert!(output.contains("No track number 9"));
+        assert!(output.contains("Invalid weight: x"));
+    }
+
+    #[test]
+    fn title_lists_end_with_an_empty_answer() {
+        let (answers, _) = run("sidecontains\n0\nexact\n\n1\n3\n\n2\n");
+        let answers = answers.unwrap();
+        assert_eq!(answers.kind, "sidecontains");
+        assert_eq!(answers.args, ["0", "exact", "Intro", "Outro"]);
+        assert_eq!(answers.weight, 2);
+    }
+
+    #[test]
+    fn running_out_of_input_is_a_user_error() {
+        let (answers, _) = run("adjacent\n1\n");
+        assert!(matches!(answers, Err(CliError::User(_))));
+    }
+}
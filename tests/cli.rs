@@ -0,0 +1,1748 @@
+//! # End-to-End Tests
+//!
+//! These tests run the albumseq_cli binary on a context file of their own and check
+//! what it prints and how it exits.
+
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+/// A context file unique to a test, removed when dropped.
+struct TempContext(PathBuf);
+
+impl TempContext {
+    /// Creates a context with tracklist "Album" (A 4:00, B 3:00, C 5:00, D 2:00),
+    /// medium "LP" (2 sides x 8:00) and a constraint opening with C.
+    fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!(
+            "albumseq_cli_e2e_{}_{}.json",
+            name,
+            std::process::id()
+        ));
+        let context = TempContext(path);
+        context.remove_files();
+
+        context.run_ok(&[
+            "add-tracklist",
+            "--name",
+            "Album",
+            "--tracks",
+            "A:4:00",
+            "B:3:00",
+            "C:5:00",
+            "D:2:00",
+        ]);
+        context.run_ok(&[
+            "add-medium",
+            "--name",
+            "LP",
+            "--sides",
+            "2",
+            "--max-duration",
+            "8:00",
+        ]);
+        context.run_ok(&[
+            "add-constraint",
+            "--kind",
+            "atpos",
+            "--args",
+            "C",
+            "0",
+            "--weight",
+            "2",
+        ]);
+        context
+    }
+
+    /// Runs the binary with this context and the given arguments.
+    fn run(&self, args: &[&str]) -> Output {
+        Command::new(env!("CARGO_BIN_EXE_albumseq_cli"))
+            .arg("--context")
+            .arg(&self.0)
+            .args(args)
+            .output()
+            .expect("the binary runs")
+    }
+
+    /// Runs the binary and checks that it succeeds.
+    fn run_ok(&self, args: &[&str]) -> Output {
+        let output = self.run(args);
+        assert!(
+            output.status.success(),
+            "{:?} failed: {}",
+            args,
+            String::from_utf8_lossy(&output.stderr)
+        );
+        output
+    }
+
+    fn remove_files(&self) {
+        let _ = fs::remove_file(&self.0);
+        let _ = fs::remove_file(self.0.with_extension("cache.json"));
+    }
+}
+
+impl Drop for TempContext {
+    fn drop(&mut self) {
+        self.remove_files();
+    }
+}
+
+#[test]
+fn ndjson_prints_only_json_lines() {
+    let context = TempContext::new("ndjson");
+    // More proposals requested than fit, so notices about the results are due too
+    let output = context.run_ok(&[
+        "propose",
+        "--tracklist",
+        "Album",
+        "--medium",
+        "LP",
+        "--count",
+        "50",
+        "--format",
+        "ndjson",
+    ]);
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines.len(), 16);
+    for (i, line) in lines.iter().enumerate() {
+        let value: serde_json::Value =
+            serde_json::from_str(line).unwrap_or_else(|e| panic!("{:?}: {}", line, e));
+        assert_eq!(value["rank"].as_u64(), Some(i as u64 + 1));
+        assert!(value["score"].as_f64().is_some());
+        assert!(value["sides"].as_array().is_some());
+    }
+}
+
+#[test]
+fn propose_notes_fewer_proposals_than_requested() {
+    let context = TempContext::new("fewer");
+    let propose = |extra: &[&str]| {
+        let mut args = vec![
+            "--quiet",
+            "propose",
+            "--tracklist",
+            "Album",
+            "--medium",
+            "LP",
+            "--count",
+            "50",
+        ];
+        args.extend_from_slice(extra);
+        let output = context.run_ok(&args);
+        String::from_utf8(output.stderr).unwrap()
+    };
+
+    assert!(propose(&[]).contains("Only 16 of 50 requested permutations fit the medium."));
+    // Of the 16, only C B | A D, C B | D A, C D | A B and C D | B A open with C
+    assert!(
+        propose(&["--min-score", "2"])
+            .contains("Only 4 of 50 requested permutations fit the medium with score >= 2.")
+    );
+    assert!(
+        propose(&["--min-score", "3"])
+            .contains("Only 0 of 50 requested permutations fit the medium with score >= 3.")
+    );
+}
+
+#[test]
+fn verbose_logging_leaves_stdout_unchanged() {
+    let context = TempContext::new("verbose");
+    let args = [
+        "propose",
+        "--tracklist",
+        "Album",
+        "--medium",
+        "LP",
+        "--no-cache",
+    ];
+    let plain = context.run_ok(&args);
+    let verbose = context.run_ok(&[&["-vv"], &args[..]].concat());
+
+    assert_eq!(
+        String::from_utf8(verbose.stdout).unwrap(),
+        String::from_utf8(plain.stdout).unwrap()
+    );
+    assert!(String::from_utf8_lossy(&verbose.stderr).contains("DEBUG"));
+    assert!(!String::from_utf8_lossy(&plain.stderr).contains("DEBUG"));
+}
+
+/// Returns the box-drawn tables in the output, as their lines.
+fn tables(stdout: &str) -> Vec<Vec<&str>> {
+    let mut tables = Vec::new();
+    let mut current = Vec::new();
+    for line in stdout.lines() {
+        if line.starts_with(['┌', '│', '├', '└']) {
+            current.push(line);
+        } else if !current.is_empty() {
+            tables.push(std::mem::take(&mut current));
+        }
+    }
+    if !current.is_empty() {
+        tables.push(current);
+    }
+    tables
+}
+
+#[test]
+fn unicode_titles_keep_table_columns_aligned() {
+    let context = TempContext::new("unicode");
+    context.run_ok(&[
+        "add-tracklist",
+        "--name",
+        "Unicode",
+        "--tracks",
+        "Café Noir:4:00",
+        "Ça ira:3:00",
+        "Ωmega Ünder:5:00",
+        "D:2:00",
+    ]);
+    let output = context.run_ok(&[
+        "propose",
+        "--tracklist",
+        "Unicode",
+        "--medium",
+        "LP",
+        "--count",
+        "3",
+    ]);
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let tables = tables(&stdout);
+    assert_eq!(tables.len(), 3);
+    for table in tables {
+        let width = table[0].chars().count();
+        assert!(table.iter().all(|line| line.chars().count() == width));
+        assert!(table.iter().any(|line| line.contains("Ωmega Ünder")));
+    }
+}
+
+#[test]
+fn accented_titles_are_padded_by_display_width() {
+    let context = TempContext::new("accented");
+    context.run_ok(&[
+        "add-tracklist",
+        "--name",
+        "Accented",
+        "--tracks",
+        "Éléphant déçu:4:00",
+        "B:3:00",
+        "C:5:00",
+        "D:2:00",
+    ]);
+    let output = context.run_ok(&[
+        "propose",
+        "--tracklist",
+        "Accented",
+        "--medium",
+        "LP",
+        "--count",
+        "1",
+    ]);
+
+    // The title column is as wide as the longest title, 13 columns, plus padding
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let row = stdout
+        .lines()
+        .find(|line| line.contains("Éléphant déçu"))
+        .unwrap();
+    let title_cell = row.split('│').nth(2).unwrap();
+    assert_eq!(title_cell, " Éléphant déçu ");
+    let short_row = stdout.lines().find(|line| line.contains("│ B ")).unwrap();
+    assert_eq!(short_row.split('│').nth(2).unwrap().chars().count(), 15);
+}
+
+#[test]
+fn capacity_is_sides_times_side_length() {
+    let context = TempContext::new("capacity");
+    context.run_ok(&[
+        "add-medium",
+        "--name",
+        "Triple",
+        "--sides",
+        "3",
+        "--max-duration",
+        "7:20",
+    ]);
+
+    for (medium, total) in [("LP", "16:00 (2 x 08:00)"), ("Triple", "22:00 (3 x 07:20)")] {
+        let output = context.run_ok(&["capacity", "--medium", medium]);
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        assert!(
+            stdout.contains(&format!("Total capacity: {}", total)),
+            "{}",
+            stdout
+        );
+    }
+}
+
+#[test]
+fn propose_for_a_missing_tracklist_exits_with_a_user_error() {
+    let context = TempContext::new("exit_code");
+
+    let output = context.run(&["propose", "--tracklist", "Missing", "--medium", "LP"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Tracklist 'Missing' not found"));
+    assert!(output.stdout.is_empty());
+
+    let output = context.run(&["propose", "--tracklist", "Album", "--medium", "Missing"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Medium 'Missing' not found"));
+
+    let output = context.run(&["propose", "--tracklist", "Album", "--medium", "LP"]);
+    assert_eq!(output.status.code(), Some(0));
+}
+
+#[test]
+fn diagnose_explains_why_nothing_is_proposed() {
+    let context = TempContext::new("diagnose");
+    context.run_ok(&[
+        "add-medium",
+        "--name",
+        "Short",
+        "--sides",
+        "1",
+        "--max-duration",
+        "6:00",
+    ]);
+
+    let output = context.run_ok(&[
+        "propose",
+        "--tracklist",
+        "Album",
+        "--medium",
+        "Short",
+        "--diagnose",
+    ]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    for line in [
+        "Diagnosis:",
+        "Total duration 14:00 vs capacity 06:00 (1 sides x 06:00).",
+        "At least 3 sides are required, the medium has 1.",
+        "Overflow: 08:00 over the medium's capacity.",
+        "In the stored order, 3 tracks spill past the last side: B, C, D.",
+    ] {
+        assert!(stdout.contains(line), "{:?} missing from {}", line, stdout);
+    }
+
+    let output = context.run_ok(&[
+        "propose",
+        "--tracklist",
+        "Album",
+        "--medium",
+        "LP",
+        "--min-score",
+        "5",
+        "--diagnose",
+    ]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains(
+        "16 permutations fit the medium, but the best score is 2 (below --min-score 5)."
+    ));
+}
+
+#[test]
+fn tracklist_notes_are_shown() {
+    let context = TempContext::new("notes");
+    context.run_ok(&[
+        "set-notes",
+        "--tracklist",
+        "Album",
+        "--notes",
+        "needs remastering",
+    ]);
+
+    let output = context.run_ok(&["show-tracklist", "--name", "Album"]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("Notes: needs remastering"));
+
+    context.run_ok(&["set-notes", "--tracklist", "Album"]);
+    let output = context.run_ok(&["show-tracklist", "--name", "Album"]);
+    assert!(!String::from_utf8(output.stdout).unwrap().contains("Notes:"));
+}
+
+#[test]
+fn propose_all_covers_every_medium() {
+    let context = TempContext::new("propose_all");
+    context.run_ok(&[
+        "add-medium",
+        "--name",
+        "CD",
+        "--sides",
+        "1",
+        "--max-duration",
+        "80:00",
+    ]);
+
+    let output = context.run_ok(&["propose-all", "--tracklist", "Album", "--count", "1"]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let sections: Vec<&str> = stdout
+        .lines()
+        .filter(|line| line.starts_with("=== Medium: "))
+        .collect();
+    assert_eq!(sections, ["=== Medium: LP ===", "=== Medium: CD ==="]);
+    assert!(stdout.contains("on medium 'LP'"));
+    assert!(stdout.contains("on medium 'CD'"));
+}
+
+/// Returns the proposal cache written next to the context.
+fn read_cache(context: &TempContext) -> serde_json::Value {
+    let data = fs::read_to_string(context.0.with_extension("cache.json")).unwrap();
+    serde_json::from_str(&data).unwrap()
+}
+
+#[test]
+fn second_run_reads_identical_proposals_from_cache() {
+    let context = TempContext::new("cache_second_run");
+    let args = [
+        "-v",
+        "propose",
+        "--tracklist",
+        "Album",
+        "--medium",
+        "LP",
+        "--count",
+        "3",
+    ];
+    let first = context.run_ok(&args);
+    let second = context.run_ok(&args);
+    let uncached = context.run_ok(&[&args[..], &["--no-cache"]].concat());
+
+    assert!(String::from_utf8_lossy(&first.stderr).contains("(scored)"));
+    assert!(String::from_utf8_lossy(&second.stderr).contains("(cached)"));
+    assert_eq!(first.stdout, second.stdout);
+    assert_eq!(first.stdout, uncached.stdout);
+    assert_eq!(read_cache(&context)["entries"].as_array().unwrap().len(), 1);
+}
+
+#[test]
+fn cached_scores_are_used_on_a_hit() {
+    let context = TempContext::new("cache_hit");
+    let args = [
+        "propose",
+        "--tracklist",
+        "Album",
+        "--medium",
+        "LP",
+        "--count",
+        "3",
+    ];
+    context.run_ok(&args);
+
+    let mut cache = read_cache(&context);
+    for proposal in cache["entries"][0]["top"].as_array_mut().unwrap() {
+        proposal[0] = serde_json::json!(100);
+    }
+    fs::write(context.0.with_extension("cache.json"), cache.to_string()).unwrap();
+    let output = context.run_ok(&args);
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let scores: Vec<&str> = stdout
+        .lines()
+        .filter_map(|line| line.strip_prefix("Score: "))
+        .collect();
+    assert_eq!(scores, ["100", "100", "100"]);
+}
+
+#[test]
+fn changed_constraint_misses_the_cache() {
+    let context = TempContext::new("cache_constraint");
+    let args = [
+        "propose",
+        "--tracklist",
+        "Album",
+        "--medium",
+        "LP",
+        "--count",
+        "3",
+    ];
+    let before = context.run_ok(&args);
+    context.run_ok(&[
+        "add-constraint",
+        "--kind",
+        "atpos",
+        "--args",
+        "D",
+        "3",
+        "--weight",
+        "3",
+    ]);
+    let after = context.run_ok(&args);
+    let uncached = context.run_ok(&[&args[..], &["--no-cache"]].concat());
+
+    assert_eq!(read_cache(&context)["entries"].as_array().unwrap().len(), 2);
+    assert_ne!(after.stdout, before.stdout);
+    assert_eq!(after.stdout, uncached.stdout);
+}
+
+#[test]
+fn preview_leaves_the_context_unchanged() {
+    let context = TempContext::new("preview");
+    let before = fs::read(&context.0).unwrap();
+
+    let output = context.run_ok(&[
+        "add-constraint",
+        "--kind",
+        "atpos",
+        "--args",
+        "A",
+        "0",
+        "--weight",
+        "5",
+        "--preview",
+        "Album",
+        "LP",
+    ]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("Without the new constraint:"));
+    assert!(stdout.contains("With the new constraint:"));
+    assert!(stdout.contains("Preview only: the constraint was not saved."));
+
+    assert_eq!(fs::read(&context.0).unwrap(), before);
+}
+
+#[test]
+fn reordered_reference_tracklist_misses_the_cache() {
+    let context = TempContext::new("cache_reference");
+    let reference = |tracks: &[&str]| {
+        context.run_ok(
+            &[
+                &["add-tracklist", "--name", "Reference", "--tracks"],
+                tracks,
+            ]
+            .concat(),
+        );
+    };
+    reference(&["A:4:00", "B:3:00", "C:5:00", "D:2:00"]);
+    context.run_ok(&[
+        "add-constraint",
+        "--kind",
+        "stayclose",
+        "--args",
+        "Reference",
+        "--weight",
+        "4",
+    ]);
+    let args = [
+        "propose",
+        "--tracklist",
+        "Album",
+        "--medium",
+        "LP",
+        "--count",
+        "3",
+    ];
+
+    let first = context.run_ok(&args);
+    let identical = context.run_ok(&args);
+    let entries_when_identical = read_cache(&context)["entries"].as_array().unwrap().len();
+
+    reference(&["D:2:00", "B:3:00", "C:5:00", "A:4:00"]);
+    let reordered = context.run_ok(&args);
+    let uncached = context.run_ok(&[&args[..], &["--no-cache"]].concat());
+
+    assert_eq!(first.stdout, identical.stdout);
+    assert_eq!(entries_when_identical, 1);
+    assert_eq!(read_cache(&context)["entries"].as_array().unwrap().len(), 2);
+    assert_eq!(reordered.stdout, uncached.stdout);
+    assert_ne!(first.stdout, reordered.stdout);
+}
+
+#[test]
+fn names_shared_by_a_tracklist_and_a_medium_are_warned_about() {
+    let context = TempContext::new("name_clash");
+
+    let output = context.run_ok(&[
+        "add-medium",
+        "--name",
+        "album",
+        "--sides",
+        "2",
+        "--max-duration",
+        "20:00",
+    ]);
+    assert!(
+        String::from_utf8_lossy(&output.stderr)
+            .contains("Medium 'album' has the same name as tracklist 'Album'")
+    );
+
+    let output = context.run_ok(&["add-tracklist", "--name", "lp", "--tracks", "E:3:00"]);
+    assert!(
+        String::from_utf8_lossy(&output.stderr)
+            .contains("Tracklist 'lp' has the same name as medium 'LP'")
+    );
+
+    let output = context.run_ok(&["add-tracklist", "--name", "Other", "--tracks", "E:3:00"]);
+    assert!(!String::from_utf8_lossy(&output.stderr).contains("same name"));
+}
+
+#[test]
+fn stats_match_the_proposals_shown() {
+    let context = TempContext::new("stats");
+    let output = context.run_ok(&[
+        "propose",
+        "--tracklist",
+        "Album",
+        "--medium",
+        "LP",
+        "--count",
+        "50",
+        "--stats",
+    ]);
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let scores: Vec<&str> = stdout
+        .lines()
+        .filter_map(|line| line.strip_prefix("Score: "))
+        .collect();
+    assert_eq!(scores.len(), 16);
+    assert_eq!(scores[0], "2");
+    assert_eq!(scores[15], "0");
+
+    assert!(stdout.contains("Permutations evaluated: 24"));
+    assert!(stdout.contains("Fitting the medium: 16"));
+    assert!(stdout.contains("Scores: min 0, max 2,"));
+}
+
+#[test]
+fn show_tracklist_prints_only_that_tracklist() {
+    let context = TempContext::new("show_tracklist");
+    context.run_ok(&[
+        "add-tracklist",
+        "--name",
+        "Other",
+        "--tracks",
+        "Elsewhere:3:00",
+    ]);
+
+    let output = context.run_ok(&["show-tracklist", "--name", "Album"]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("Tracklist: Album"));
+    assert!(stdout.contains("Total runtime: 14:00 (4 tracks)"));
+    assert!(!stdout.contains("Other"));
+    assert!(!stdout.contains("Elsewhere"));
+
+    let output = context.run(&["show-tracklist", "--name", "Missing"]);
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn include_original_reports_the_stored_order() {
+    let context = TempContext::new("include_original");
+    let output = context.run_ok(&[
+        "propose",
+        "--tracklist",
+        "Album",
+        "--medium",
+        "LP",
+        "--include-original",
+    ]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    // A B | C D fits and doesn't open with C: the 4 orders opening with C score higher
+    assert!(stdout.contains("Original order:"));
+    assert!(stdout.contains("Score 0, ranked 5 of 16 fitting permutations."));
+
+    let output = context.run_ok(&["propose", "--tracklist", "Album", "--medium", "LP"]);
+    assert!(
+        !String::from_utf8(output.stdout)
+            .unwrap()
+            .contains("Original order:")
+    );
+}
+
+#[test]
+fn cache_holds_only_the_proposals_shown() {
+    let context = TempContext::new("cache_bounded");
+    let propose = |count: &str, extra: &[&str]| {
+        let args = [
+            "propose",
+            "--tracklist",
+            "Album",
+            "--medium",
+            "LP",
+            "--count",
+            count,
+        ];
+        context.run_ok(&[&args[..], extra].concat()).stdout
+    };
+
+    propose("3", &[]);
+    let stored = read_cache(&context);
+    // A larger count than stored can't be served from the entry
+    let more = propose("5", &[]);
+    let restored = read_cache(&context);
+    let fewer = propose("2", &[]);
+
+    assert_eq!(stored["entries"][0]["top"].as_array().unwrap().len(), 3);
+    assert!(stored["entries"][0]["fitting"].as_u64().unwrap() > 5);
+    assert_eq!(restored["entries"].as_array().unwrap().len(), 1);
+    assert_eq!(restored["entries"][0]["top"].as_array().unwrap().len(), 5);
+    assert_eq!(more, propose("5", &["--no-cache"]));
+    assert_eq!(fewer, propose("2", &["--no-cache"]));
+}
+
+#[test]
+fn markdown_has_one_row_per_track_and_side_total() {
+    let context = TempContext::new("markdown");
+    let output = context.run_ok(&[
+        "propose",
+        "--tracklist",
+        "Album",
+        "--medium",
+        "LP",
+        "--count",
+        "1",
+        "--format",
+        "markdown",
+    ]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("### Permutation #1 (score 2)"));
+
+    let rows: Vec<&str> = stdout
+        .lines()
+        .filter(|line| line.starts_with('|'))
+        .collect();
+    // Per side: a header, a separator, two tracks and a total
+    assert_eq!(rows.len(), 10);
+    assert_eq!(
+        rows.iter().filter(|row| row.contains("**TOTAL**")).count(),
+        2
+    );
+    assert_eq!(rows[2], "| 1 | C | 05:00 |");
+    assert_eq!(rows[3], "| 2 | B | 03:00 |");
+    assert_eq!(rows[4], "| | **TOTAL** | **08:00** |");
+    assert_eq!(rows[7], "| 3 | A | 04:00 |");
+    assert_eq!(rows[8], "| 4 | D | 02:00 |");
+    assert!(!stdout.contains('│'));
+}
+
+#[test]
+fn fit_medium_lists_media_by_capacity_with_a_verdict() {
+    let context = TempContext::new("fit_medium");
+    for (name, sides, side) in [
+        ("Single", "4", "4:30"),
+        ("EP", "2", "6:00"),
+        ("Tight", "2", "7:00"),
+    ] {
+        context.run_ok(&[
+            "add-medium",
+            "--name",
+            name,
+            "--sides",
+            sides,
+            "--max-duration",
+            side,
+        ]);
+    }
+
+    let output = context.run_ok(&["fit-medium", "--tracklist", "Album"]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let rows: Vec<Vec<&str>> = tables(&stdout)[0]
+        .iter()
+        .filter(|line| line.starts_with('│'))
+        .skip(1)
+        .map(|line| line.split('│').map(str::trim).collect())
+        .collect();
+    let verdicts: Vec<(&str, &str)> = rows.iter().map(|row| (row[1], row[5])).collect();
+    assert_eq!(
+        verdicts,
+        [
+            ("EP", "no (too long)"),
+            ("Tight", "yes"),
+            ("LP", "yes"),
+            ("Single", "no (track longer than a side)"),
+        ]
+    );
+    assert!(stdout.contains("Smallest fitting medium: Tight"));
+}
+
+#[test]
+fn quiet_hides_status_messages_but_still_acts() {
+    let context = TempContext::new("quiet");
+    let output = context.run_ok(&[
+        "--quiet",
+        "add-medium",
+        "--name",
+        "CD",
+        "--sides",
+        "1",
+        "--max-duration",
+        "80:00",
+    ]);
+    assert!(output.stdout.is_empty());
+
+    let output = context.run_ok(&["-q", "capacity", "--medium", "CD"]);
+    assert!(
+        String::from_utf8(output.stdout)
+            .unwrap()
+            .contains("Total capacity: 80:00 (1 x 80:00)")
+    );
+
+    let output = context.run_ok(&[
+        "add-medium",
+        "--name",
+        "EP",
+        "--sides",
+        "2",
+        "--max-duration",
+        "6:00",
+    ]);
+    assert!(!output.stdout.is_empty());
+}
+
+#[test]
+fn toggled_off_constraints_are_ignored_when_proposing() {
+    let context = TempContext::new("toggle");
+    let scores = || {
+        let output = context.run_ok(&[
+            "propose",
+            "--tracklist",
+            "Album",
+            "--medium",
+            "LP",
+            "--count",
+            "3",
+        ]);
+        String::from_utf8(output.stdout)
+            .unwrap()
+            .lines()
+            .filter_map(|line| line.strip_prefix("Score: "))
+            .map(String::from)
+            .collect::<Vec<String>>()
+    };
+    let toggle = |index: &str| context.run(&["toggle-constraint", "--index", index]);
+
+    assert_eq!(scores()[0], "2");
+    assert!(toggle("0").status.success());
+    assert_eq!(scores(), ["0", "0", "0"]);
+    assert!(toggle("0").status.success());
+    assert_eq!(scores()[0], "2");
+    assert!(!toggle("1").status.success());
+}
+
+#[test]
+fn side_overrides_change_the_split() {
+    let context = TempContext::new("sides_override");
+    let sides = |extra: &[&str]| {
+        let mut args = vec![
+            "propose",
+            "--tracklist",
+            "Album",
+            "--medium",
+            "LP",
+            "--count",
+            "1",
+            "--format",
+            "markdown",
+        ];
+        args.extend_from_slice(extra);
+        let output = context.run_ok(&args);
+        String::from_utf8(output.stdout)
+            .unwrap()
+            .lines()
+            .filter(|line| line.starts_with("#### Side "))
+            .count()
+    };
+
+    assert_eq!(sides(&[]), 2);
+    assert_eq!(sides(&["--sides", "1", "--max-duration", "14:00"]), 1);
+    // C | A B | D
+    assert_eq!(sides(&["--sides", "3", "--max-duration", "7:00"]), 3);
+
+    // The stored medium is left as it was
+    let output = context.run_ok(&["capacity", "--medium", "LP"]);
+    assert!(
+        String::from_utf8(output.stdout)
+            .unwrap()
+            .contains("Total capacity: 16:00 (2 x 08:00)")
+    );
+}
+
+/// Returns the score and titles of each proposal printed as a table.
+fn proposals(stdout: &str) -> Vec<(&str, Vec<&str>)> {
+    let scores = stdout
+        .lines()
+        .filter_map(|line| line.strip_prefix("Score: "));
+    scores
+        .zip(tables(stdout))
+        .map(|(score, table)| {
+            let titles = table
+                .iter()
+                .filter_map(|line| {
+                    let cells: Vec<&str> = line.split('│').collect();
+                    cells.get(1)?.trim().parse::<usize>().ok()?;
+                    Some(cells.get(2)?.trim())
+                })
+                .collect();
+            (score, titles)
+        })
+        .collect()
+}
+
+#[test]
+fn stronger_anchors_keep_closer_to_the_reference() {
+    let context = TempContext::new("anchor");
+    let top = |strength: &str| {
+        let output = context.run_ok(&[
+            "propose",
+            "--tracklist",
+            "Album",
+            "--medium",
+            "LP",
+            "--algorithm",
+            "sample",
+            "--samples",
+            "24",
+            "--anchor",
+            "Album",
+            "--anchor-strength",
+            strength,
+        ]);
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        let (score, titles) = proposals(&stdout).remove(0);
+        (score.to_string(), titles.join(" "))
+    };
+
+    // C B A D is 4 positions from the stored order, half the maximum of 8
+    assert_eq!(top("1"), ("2.5".to_string(), "C B A D".to_string()));
+    assert_eq!(top("10"), ("10".to_string(), "A B C D".to_string()));
+}
+
+#[test]
+fn malformed_track_entries_are_named() {
+    let context = TempContext::new("malformed_tracks");
+    let entries = ["Good:3:00", "No colon", ":3:00", "Bad:x"];
+
+    let mut args = vec!["add-tracklist", "--name", "Other", "--strict", "--tracks"];
+    args.extend(entries);
+    let output = context.run(&args);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains(
+        "Malformed track entries: \"No colon\" (missing colon), \":3:00\" (missing title), \"Bad:x\" (bad duration)"
+    ));
+
+    let mut args = vec!["add-tracklist", "--name", "Other", "--tracks"];
+    args.extend(entries);
+    let output = context.run_ok(&args);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Skipping track entry \"No colon\": missing colon"));
+    assert!(stderr.contains("Skipping track entry \"Bad:x\": bad duration"));
+
+    let output = context.run_ok(&["show-tracklist", "--name", "Other"]);
+    assert!(
+        String::from_utf8(output.stdout)
+            .unwrap()
+            .contains("(1 tracks)")
+    );
+}
+
+#[test]
+fn constraints_for_leaves_out_dangling_constraints() {
+    let context = TempContext::new("constraints_for");
+    context.run_ok(&[
+        "add-tracklist",
+        "--name",
+        "Other",
+        "--tracks",
+        "A:3:00",
+        "E:2:00",
+    ]);
+    context.run_ok(&[
+        "add-constraint",
+        "--kind",
+        "atpos",
+        "--args",
+        "A",
+        "0",
+        "--weight",
+        "1",
+    ]);
+    context.run_ok(&[
+        "add-constraint",
+        "--kind",
+        "fillsides",
+        "--args",
+        "0.8",
+        "--weight",
+        "1",
+    ]);
+
+    let output = context.run_ok(&["constraints-for", "--tracklist", "Other"]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("A @ 0"));
+    assert!(stdout.contains("FillSides"));
+    assert!(!stdout.contains("C @ 0"));
+    assert!(stdout.contains("1 constraints refer to tracks missing from this tracklist."));
+
+    let output = context.run_ok(&["constraints-for", "--tracklist", "Album"]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("C @ 0"));
+    assert!(stdout.contains("A @ 0"));
+    assert!(!stdout.contains("refer to tracks missing"));
+}
+
+#[test]
+fn prefer_original_wins_ties_for_the_stored_order() {
+    let context = TempContext::new("prefer_original");
+    context.run_ok(&["remove-constraint", "--index", "0"]);
+    context.run_ok(&[
+        "add-tracklist",
+        "--name",
+        "Album",
+        "--tracks",
+        "D:2:00",
+        "C:5:00",
+        "B:3:00",
+        "A:4:00",
+    ]);
+    let titles = |extra: &[&str]| {
+        let args = [
+            "propose",
+            "--tracklist",
+            "Album",
+            "--medium",
+            "LP",
+            "--count",
+            "2",
+        ];
+        let output = context.run_ok(&[&args[..], extra].concat());
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        proposals(&stdout)
+            .into_iter()
+            .map(|(score, titles)| format!("{} {}", score, titles.join(" ")))
+            .collect::<Vec<String>>()
+    };
+
+    // Every order scores 0, so titles break the tie
+    assert_eq!(titles(&[])[0], "0 A B C D");
+    // Then the orders one swap away
+    assert_eq!(titles(&["--prefer-original"]), ["0 D C B A", "0 C D B A"]);
+}
+
+#[test]
+fn constraints_for_lists_medium_constraints_under_their_medium() {
+    let context = TempContext::new("constraints_for_medium");
+    context.run_ok(&[
+        "add-tracklist",
+        "--name",
+        "Other",
+        "--tracks",
+        "A:3:00",
+        "E:2:00",
+    ]);
+    context.run_ok(&[
+        "add-medium-constraint",
+        "--medium",
+        "LP",
+        "--kind",
+        "atpos",
+        "--args",
+        "E",
+        "1",
+    ]);
+    let output = context.run_ok(&["constraints-for", "--tracklist", "Other"]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("Constraints of medium 'LP':"));
+    assert!(stdout.contains("E @ 1"));
+
+    let output = context.run_ok(&["constraints-for", "--tracklist", "Album"]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(!stdout.contains("Constraints of medium 'LP':"));
+    // There is no E on "Album"
+    assert!(stdout.contains("1 constraints refer to tracks missing from this tracklist."));
+}
+
+#[test]
+fn medium_constraints_only_apply_on_their_medium() {
+    let context = TempContext::new("medium_constraints");
+    context.run_ok(&["copy-medium", "--source", "LP", "--new-name", "Copy"]);
+    context.run_ok(&[
+        "add-medium-constraint",
+        "--medium",
+        "Copy",
+        "--kind",
+        "atpos",
+        "--args",
+        "A",
+        "0",
+        "--weight",
+        "5",
+    ]);
+    let top = |medium: &str| {
+        let output = context.run_ok(&[
+            "propose",
+            "--tracklist",
+            "Album",
+            "--medium",
+            medium,
+            "--count",
+            "1",
+        ]);
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        let (score, titles) = proposals(&stdout).remove(0);
+        format!("{} {}", score, titles.join(" "))
+    };
+
+    assert_eq!(top("LP"), "2 C B A D");
+    assert_eq!(top("Copy"), "5 A B C D");
+
+    let saved: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&context.0).unwrap()).unwrap();
+    assert_eq!(saved["constraints"].as_array().unwrap().len(), 1);
+}
+
+#[test]
+fn group_by_side1_keeps_the_best_of_each_first_side() {
+    let context = TempContext::new("group_by");
+    let output = context.run_ok(&[
+        "propose",
+        "--tracklist",
+        "Album",
+        "--medium",
+        "LP",
+        "--count",
+        "24",
+        "--group-by",
+        "side1",
+    ]);
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let shown: Vec<String> = proposals(&stdout)
+        .into_iter()
+        .map(|(score, titles)| format!("{} {}", score, titles.join(" ")))
+        .collect();
+    assert_eq!(shown, ["2 C B A D", "2 C D A B", "0 A B C D", "0 A D B C"]);
+    assert_eq!(
+        stdout
+            .lines()
+            .filter(|line| line.contains("more of the top permutations"))
+            .count(),
+        4
+    );
+}
+
+#[test]
+fn import_m3u_adds_the_fixture_tracks() {
+    let context = TempContext::new("import_m3u");
+    let fixture = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/dj_set.m3u8");
+
+    context.run_ok(&["import-m3u", "--name", "Friday", "--path", fixture]);
+    let output = context.run_ok(&["show-tracklist", "--name", "Friday"]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("Artist Two - Long Build"));
+    assert!(stdout.contains("(3 tracks)"));
+    assert!(!stdout.contains("Broken"));
+}
+
+#[test]
+fn layout_shows_the_split_of_the_stored_order() {
+    let context = TempContext::new("layout");
+    let output = context.run_ok(&["layout", "--tracklist", "Album", "--medium", "LP"]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+
+    let rows: Vec<Vec<&str>> = tables(&stdout)[0]
+        .iter()
+        .filter(|line| line.starts_with('│'))
+        .skip(1)
+        .map(|line| {
+            line.split('│')
+                .map(str::trim)
+                .filter(|c| !c.is_empty())
+                .collect()
+        })
+        .collect();
+    assert_eq!(
+        rows,
+        [
+            vec!["1", "A (04:00)", "07:00", "88%"],
+            vec!["B (03:00)"],
+            vec!["2", "C (05:00)", "07:00", "88%"],
+            vec!["D (02:00)"],
+        ]
+    );
+    assert!(!stdout.contains("doesn't fit"));
+
+    context.run_ok(&[
+        "add-medium",
+        "--name",
+        "EP",
+        "--sides",
+        "2",
+        "--max-duration",
+        "6:00",
+    ]);
+    let output = context.run_ok(&["layout", "--tracklist", "Album", "--medium", "EP"]);
+    assert!(
+        String::from_utf8(output.stdout)
+            .unwrap()
+            .contains("The stored order doesn't fit this medium")
+    );
+}
+
+#[test]
+fn sort_by_balance_puts_even_sides_first() {
+    let context = TempContext::new("sort_by_balance");
+    let shown = |extra: &[&str]| {
+        let args = [
+            "propose",
+            "--tracklist",
+            "Album",
+            "--medium",
+            "LP",
+            "--count",
+            "9",
+        ];
+        let output = context.run_ok(&[&args[..], extra].concat());
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        proposals(&stdout)
+            .into_iter()
+            .map(|(score, titles)| format!("{} {}", score, titles.join(" ")))
+            .collect::<Vec<String>>()
+    };
+
+    assert_eq!(shown(&[])[0], "2 C B A D");
+
+    let balanced = shown(&["--sort-by", "balance"]);
+    assert_eq!(balanced[0], "2 C D A B");
+    // The 8 orders splitting 7:00 | 7:00 before any 8:00 | 6:00 one
+    for proposal in &balanced[..8] {
+        let mut first_side: Vec<&str> = proposal.split(' ').skip(1).take(2).collect();
+        first_side.sort();
+        assert!(first_side == ["A", "B"] || first_side == ["C", "D"]);
+    }
+    assert_eq!(balanced[8], "2 C B A D");
+}
+
+#[test]
+fn unconstrained_lists_tracks_no_constraint_names() {
+    let context = TempContext::new("unconstrained");
+    let output = context.run_ok(&["unconstrained", "--tracklist", "Album"]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let listed: Vec<&str> = stdout
+        .lines()
+        .filter(|line| line.starts_with("  "))
+        .collect();
+    assert!(stdout.contains("referenced by no constraint (3 of 4)"));
+    assert_eq!(listed, ["  A (04:00)", "  B (03:00)", "  D (02:00)"]);
+
+    context.run_ok(&["add-constraint", "--kind", "adjacent", "--args", "A", "B"]);
+    context.run_ok(&["toggle-constraint", "--index", "0"]);
+    let output = context.run_ok(&["unconstrained", "--tracklist", "Album"]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    // The constraint on C is disabled
+    assert!(stdout.contains("(2 of 4)"));
+    assert!(stdout.contains("  C (05:00)"));
+    assert!(stdout.contains("  D (02:00)"));
+}
+
+#[test]
+fn minified_contexts_save_on_one_line_and_load() {
+    let context = TempContext::new("minified");
+    assert!(fs::read_to_string(&context.0).unwrap().lines().count() > 1);
+
+    context.run_ok(&[
+        "--minified-context",
+        "set-notes",
+        "--tracklist",
+        "Album",
+        "--notes",
+        "final",
+    ]);
+    let data = fs::read_to_string(&context.0).unwrap();
+    assert_eq!(data.trim_end().lines().count(), 1);
+
+    let output = context.run_ok(&["show-tracklist", "--name", "Album"]);
+    assert!(
+        String::from_utf8(output.stdout)
+            .unwrap()
+            .contains("Notes: final")
+    );
+
+    // Saving without the flag indents again
+    context.run_ok(&["set-notes", "--tracklist", "Album"]);
+    assert!(fs::read_to_string(&context.0).unwrap().lines().count() > 1);
+}
+
+#[test]
+fn diff_lists_added_removed_and_moved_tracks() {
+    let context = TempContext::new("diff");
+    context.run_ok(&[
+        "add-tracklist",
+        "--name",
+        "Old",
+        "--tracks",
+        "A:4:00",
+        "B:3:00",
+        "C:5:00",
+        "D:2:00",
+        "X:1:00",
+    ]);
+    context.run_ok(&[
+        "add-tracklist",
+        "--name",
+        "New",
+        "--tracks",
+        "b:3:00",
+        "C:5:10",
+        "D:2:00",
+        "A:4:00",
+        "E:1:00",
+    ]);
+
+    let output = context.run_ok(&["diff-tracklists", "--a", "Old", "--b", "New"]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let changes: Vec<&str> = stdout.lines().skip(1).map(str::trim).collect();
+    assert_eq!(
+        changes,
+        [
+            "+ E (01:00)",
+            "- X (01:00)",
+            "~ A moved from #1 to #4",
+            "~ C duration 05:00 -> 05:10",
+        ]
+    );
+
+    let output = context.run_ok(&["diff-tracklists", "--a", "Album", "--b", "Album"]);
+    assert!(
+        String::from_utf8(output.stdout)
+            .unwrap()
+            .contains("(no differences)")
+    );
+}
+
+#[test]
+fn remove_constraint_dry_run_leaves_the_context_unchanged() {
+    let context = TempContext::new("remove_dry_run");
+    let before = fs::read(&context.0).unwrap();
+
+    let output = context.run_ok(&["remove-constraint", "--index", "0", "--dry-run"]);
+    assert!(
+        String::from_utf8(output.stdout)
+            .unwrap()
+            .contains("Would remove constraint 0: AtPosition C @ 0 (weight 2)")
+    );
+    assert_eq!(fs::read(&context.0).unwrap(), before);
+
+    let output = context.run(&["remove-constraint", "--index", "1", "--dry-run"]);
+    assert_eq!(output.status.code(), Some(2));
+
+    context.run_ok(&["remove-constraint", "--index", "0"]);
+    assert_ne!(fs::read(&context.0).unwrap(), before);
+}
+
+#[test]
+fn fit_weight_favours_balanced_layouts() {
+    let context = TempContext::new("fit_weight");
+    let top = |fit_weight: &str| {
+        let output = context.run_ok(&[
+            "propose",
+            "--tracklist",
+            "Album",
+            "--medium",
+            "LP",
+            "--count",
+            "1",
+            "--fit-weight",
+            fit_weight,
+        ]);
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        let (score, titles) = proposals(&stdout).remove(0);
+        format!("{} {}", score, titles.join(" "))
+    };
+
+    assert_eq!(top("0"), "2 C B A D");
+    // 7:00 | 7:00 rates 0.9375 (88% full, balanced), 8:00 | 6:00 rates 0.8125
+    assert_eq!(top("1"), "2.94 C D A B");
+    assert_eq!(top("20"), "20.75 C D A B");
+}
+
+#[test]
+fn stdin_context_is_piped_through_to_stdout() {
+    use std::io::Write;
+    use std::process::Stdio;
+
+    let context = TempContext::new("stdio");
+    let input = fs::read_to_string(&context.0).unwrap();
+    let mut child = Command::new(env!("CARGO_BIN_EXE_albumseq_cli"))
+        .args(["--context", "-", "add-tracklist", "--name", "Single"])
+        .args(["--tracks", "E:3:00", "F:4:00"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("the binary runs");
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    // Stdout holds only the updated context
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let piped: serde_json::Value = serde_json::from_str(&stdout).unwrap();
+    let names: Vec<&str> = piped["tracklists"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|tracklist| tracklist["name"].as_str().unwrap())
+        .collect();
+    assert_eq!(names, ["Album", "Single"]);
+    assert_eq!(piped["constraints"].as_array().unwrap().len(), 1);
+
+    // The context file is left alone
+    assert_eq!(fs::read_to_string(&context.0).unwrap(), input);
+}
+
+#[test]
+fn measure_prints_phase_times_and_keeps_the_results() {
+    let context = TempContext::new("measure");
+    let propose = |extra: &[&str]| {
+        let mut args = vec![
+            "propose",
+            "--tracklist",
+            "Album",
+            "--medium",
+            "LP",
+            "--no-cache",
+        ];
+        args.extend_from_slice(extra);
+        String::from_utf8(context.run_ok(&args).stdout).unwrap()
+    };
+
+    let plain = propose(&[]);
+    let measured = propose(&["--measure"]);
+    assert!(!plain.contains("Timing:"));
+    assert!(measured.contains("Timing:"));
+    for phase in ["Generation: ", "Scoring: ", "Ranking: "] {
+        assert!(
+            measured
+                .lines()
+                .any(|line| line.trim_start().starts_with(phase))
+        );
+    }
+    assert_eq!(tables(&measured), tables(&plain));
+}
+
+#[test]
+fn check_durations_flags_and_fixes_a_zero_duration_track() {
+    let context = TempContext::new("check_durations");
+    context.run_ok(&[
+        "add-tracklist",
+        "--name",
+        "Import",
+        "--tracks",
+        "E:3:00",
+        "Silence:0:00",
+    ]);
+
+    let stdout = String::from_utf8(context.run_ok(&["check-durations"]).stdout).unwrap();
+    assert!(stdout.contains("1 tracks without a positive duration:"));
+    let row = stdout
+        .lines()
+        .find(|line| line.contains("Silence"))
+        .unwrap();
+    assert!(row.contains("Import") && row.contains("00:00"));
+
+    context.run_ok(&["check-durations", "--fix", "--yes"]);
+    let stdout = String::from_utf8(context.run_ok(&["check-durations"]).stdout).unwrap();
+    assert!(stdout.contains("Every track has a positive duration."));
+}
+
+#[test]
+fn conflicts_include_medium_constraints() {
+    let context = TempContext::new("conflicts");
+    let output = context.run_ok(&["conflicts"]);
+    assert!(
+        String::from_utf8(output.stdout)
+            .unwrap()
+            .contains("No conflicting constraints found.")
+    );
+
+    context.run_ok(&[
+        "add-medium-constraint",
+        "--medium",
+        "LP",
+        "--kind",
+        "atpos",
+        "--args",
+        "A",
+        "0",
+    ]);
+    let stdout = String::from_utf8(context.run_ok(&["conflicts"]).stdout).unwrap();
+    assert!(stdout.contains("1 conflicting constraint pairs:"));
+    assert!(stdout.contains("#0 AtPosition (C @ 0)"));
+    assert!(stdout.contains("medium 'LP' #0 AtPosition (A @ 0)"));
+}
+
+#[test]
+fn unconstrained_counts_medium_constraints() {
+    let context = TempContext::new("unconstrained_medium");
+    context.run_ok(&[
+        "add-medium-constraint",
+        "--medium",
+        "LP",
+        "--kind",
+        "adjacent",
+        "--args",
+        "A",
+        "B",
+    ]);
+    let output = context.run_ok(&["unconstrained", "--tracklist", "Album"]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("referenced by no constraint (1 of 4)"));
+    assert!(stdout.contains("  D (02:00)"));
+}
+
+#[test]
+fn constraint_info_describes_a_medium_constraint() {
+    let context = TempContext::new("constraint_info_medium");
+    context.run_ok(&[
+        "add-medium",
+        "--name",
+        "Single",
+        "--sides",
+        "1",
+        "--max-duration",
+        "20:00",
+    ]);
+    context.run_ok(&[
+        "add-medium-constraint",
+        "--medium",
+        "LP",
+        "--kind",
+        "onsameside",
+        "--args",
+        "A",
+        "B",
+    ]);
+
+    let output = context.run_ok(&["constraint-info", "--index", "0", "--medium", "LP"]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("Constraint 0 of medium 'LP': OnSameSide A, B"));
+    assert!(stdout.contains("LP (2 sides): meaningful"));
+    // It doesn't apply on the other medium
+    assert!(!stdout.contains("Single"));
+
+    let output = context.run(&["constraint-info", "--index", "1", "--medium", "LP"]);
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn remember_is_refused_for_a_piped_context() {
+    use std::io::Write;
+    use std::process::Stdio;
+
+    let context = TempContext::new("stdio_remember");
+    let input = fs::read_to_string(&context.0).unwrap();
+    let mut child = Command::new(env!("CARGO_BIN_EXE_albumseq_cli"))
+        .args(["--context", "-", "propose", "--tracklist", "Album"])
+        .args(["--medium", "LP", "--remember"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("the binary runs");
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--remember can't be combined"));
+    assert!(output.stdout.is_empty());
+}
+
+#[test]
+fn rpm_scales_only_a_derived_side_length() {
+    let context = TempContext::new("rpm");
+    context.run_ok(&[
+        "add-medium",
+        "--name",
+        "Derived",
+        "--sides",
+        "2",
+        "--rpm",
+        "55",
+    ]);
+    context.run_ok(&[
+        "add-medium",
+        "--name",
+        "Explicit",
+        "--sides",
+        "2",
+        "--max-duration",
+        "15:00",
+        "--rpm",
+        "45",
+    ]);
+
+    // 22:00 at 33 1/3 RPM is 13:20 at 55 RPM
+    for (medium, total) in [
+        ("Derived", "26:40 (2 x 13:20)"),
+        ("Explicit", "30:00 (2 x 15:00)"),
+    ] {
+        let output = context.run_ok(&["capacity", "--medium", medium]);
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        assert!(
+            stdout.contains(&format!("Total capacity: {}", total)),
+            "{}",
+            stdout
+        );
+    }
+
+    let output = context.run(&["add-medium", "--name", "Neither", "--sides", "2"]);
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn diagnose_refuses_a_medium_without_side_length() {
+    let context = TempContext::new("diagnose_zero");
+    context.run_ok(&[
+        "add-medium",
+        "--name",
+        "Blank",
+        "--sides",
+        "2",
+        "--max-duration",
+        "0:00",
+    ]);
+
+    let output = context.run(&[
+        "propose",
+        "--tracklist",
+        "Album",
+        "--medium",
+        "Blank",
+        "--diagnose",
+    ]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("has sides of length 00:00"));
+}
+
+#[test]
+fn diagnose_counts_gaps_toward_the_sides_required() {
+    let context = TempContext::new("diagnose_gaps");
+    context.run_ok(&[
+        "add-medium",
+        "--name",
+        "Short",
+        "--sides",
+        "1",
+        "--max-duration",
+        "7:00",
+    ]);
+    context.run_ok(&[
+        "set-track-info",
+        "--tracklist",
+        "Album",
+        "--title",
+        "A",
+        "--gap",
+        "1:00",
+    ]);
+
+    let output = context.run_ok(&[
+        "propose",
+        "--tracklist",
+        "Album",
+        "--medium",
+        "Short",
+        "--diagnose",
+    ]);
+    // 14:00 of tracks and a 1:00 gap: 2 sides of 7:00 aren't enough
+    assert!(
+        String::from_utf8(output.stdout)
+            .unwrap()
+            .contains("At least 3 sides are required, the medium has 1.")
+    );
+}
+
+#[test]
+fn preview_checks_the_stayclose_reference() {
+    let context = TempContext::new("preview_stayclose");
+    let output = context.run(&[
+        "add-constraint",
+        "--kind",
+        "stayclose",
+        "--args",
+        "Missing",
+        "--preview",
+        "Album",
+        "LP",
+    ]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Tracklist 'Missing' not found"));
+    assert!(output.stdout.is_empty());
+}
+
+#[test]
+fn allow_partial_lists_a_left_off_track_sharing_a_title() {
+    let context = TempContext::new("partial_same_title");
+    // 18:00 for an LP holding 16:00
+    context.run_ok(&[
+        "add-tracklist",
+        "--name",
+        "Live",
+        "--tracks",
+        "A:4:00",
+        "A:6:00",
+        "B:3:00",
+        "C:5:00",
+    ]);
+
+    let output = context.run_ok(&[
+        "propose",
+        "--tracklist",
+        "Live",
+        "--medium",
+        "LP",
+        "--allow-partial",
+    ]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let left_off: Vec<&str> = stdout
+        .lines()
+        .filter(|line| line.starts_with("Left off:"))
+        .collect();
+    assert!(!left_off.is_empty());
+    assert!(left_off.iter().all(|line| line.trim_end() != "Left off:"));
+    // Either A fits with B and C, the other is left off
+    assert!(left_off.contains(&"Left off: A"));
+}
+
+#[test]
+fn import_chapters_skips_comment_lines_silently() {
+    let context = TempContext::new("import_chapters_comments");
+    let fixture = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/fixtures/live_set_chapters.txt"
+    );
+
+    let output = context.run_ok(&[
+        "import-chapters",
+        "--name",
+        "Live",
+        "--path",
+        fixture,
+        "--total",
+        "9:40",
+    ]);
+    assert!(!String::from_utf8_lossy(&output.stderr).contains("Skipping"));
+
+    let output = context.run_ok(&["show-tracklist", "--name", "Live"]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("(3 tracks)"));
+    assert!(!stdout.contains("Encore"));
+    assert!(!stdout.contains("crowd noise"));
+}